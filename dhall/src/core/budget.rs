@@ -0,0 +1,83 @@
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+/// The budget that ran out, see `with_budget`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Exceeded {
+    Steps,
+    Values,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Budget {
+    steps_left: Option<usize>,
+    values_left: Option<usize>,
+}
+
+thread_local! {
+    // There is no budget outside of `with_budget`
+    static BUDGET: Cell<Option<Budget>> = Cell::new(None);
+}
+
+/// Restores the enclosing budget, even if `f` panics.
+struct Restore(Option<Budget>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        BUDGET.with(|budget| budget.set(self.0))
+    }
+}
+
+/// Runs `f` with a budget on the normalization steps it takes and on the
+/// values it allocates. `f` is interrupted as soon as one runs out, and
+/// everything it was building is dropped. Interrupting unwinds without
+/// calling the panic hook, so this needs `panic = "unwind"`.
+pub(crate) fn with_budget<T>(
+    max_steps: Option<usize>,
+    max_values: Option<usize>,
+    f: impl FnOnce() -> T,
+) -> Result<T, Exceeded> {
+    let budget = Budget {
+        steps_left: max_steps,
+        values_left: max_values,
+    };
+    let _restore = Restore(BUDGET.with(|b| b.replace(Some(budget))));
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        match payload.downcast::<Exceeded>() {
+            Ok(exceeded) => *exceeded,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    })
+}
+
+/// Counts a normalization step against the budget.
+pub(crate) fn step() {
+    spend(|b| &mut b.steps_left, Exceeded::Steps)
+}
+
+/// Counts the allocation of a value against the budget.
+pub(crate) fn alloc_value() {
+    spend(|b| &mut b.values_left, Exceeded::Values)
+}
+
+fn spend(left: fn(&mut Budget) -> &mut Option<usize>, kind: Exceeded) {
+    let exceeded = BUDGET.with(|cell| {
+        let mut budget = match cell.get() {
+            Some(budget) => budget,
+            None => return false,
+        };
+        let exceeded = match left(&mut budget) {
+            Some(0) => true,
+            Some(n) => {
+                *n -= 1;
+                false
+            }
+            None => false,
+        };
+        cell.set(Some(budget));
+        exceeded
+    });
+    if exceeded {
+        panic::resume_unwind(Box::new(kind))
+    }
+}
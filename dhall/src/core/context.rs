@@ -6,7 +6,6 @@ use dhall_syntax::{Label, V};
 use crate::core::value::Value;
 use crate::core::valuef::ValueF;
 use crate::core::var::{AlphaVar, Shift, Subst};
-use crate::error::TypeError;
 
 #[derive(Debug, Clone)]
 enum CtxItem {
@@ -14,27 +13,67 @@ enum CtxItem {
     Replaced(Value),
 }
 
+/// The variables in scope, innermost first. Extending a context shares the
+/// outer one instead of copying it.
 #[derive(Debug, Clone)]
-pub(crate) struct TypecheckContext(Rc<Vec<(Label, CtxItem)>>);
+pub(crate) struct TypecheckContext(Option<Rc<CtxNode>>);
+
+#[derive(Debug)]
+struct CtxNode {
+    label: Label,
+    item: CtxItem,
+    outer: TypecheckContext,
+}
+
+// Unlink the outer nodes one by one, to avoid recursing once per variable.
+impl Drop for CtxNode {
+    fn drop(&mut self) {
+        let mut outer = self.outer.0.take();
+        while let Some(node) = outer {
+            outer = match Rc::try_unwrap(node) {
+                Ok(mut node) => node.outer.0.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
 
 impl TypecheckContext {
     pub fn new() -> Self {
-        TypecheckContext(Rc::new(Vec::new()))
+        TypecheckContext(None)
+    }
+    fn push(&self, x: &Label, item: CtxItem) -> Self {
+        TypecheckContext(Some(Rc::new(CtxNode {
+            label: x.clone(),
+            item,
+            outer: self.clone(),
+        })))
+    }
+    fn iter(&self) -> impl Iterator<Item = (&Label, &CtxItem)> {
+        let mut ctx = self;
+        std::iter::from_fn(move || {
+            let node = ctx.0.as_ref()?;
+            ctx = &node.outer;
+            Some((&node.label, &node.item))
+        })
+    }
+    /// Rebuilds the context from its innermost items, stacked on `outer`.
+    fn rebuild(items: Vec<(Label, CtxItem)>, outer: Self) -> Self {
+        items
+            .into_iter()
+            .rev()
+            .fold(outer, |ctx, (l, i)| ctx.push(&l, i))
     }
     pub fn insert_type(&self, x: &Label, t: Value) -> Self {
-        let mut vec = self.0.as_ref().clone();
-        vec.push((x.clone(), CtxItem::Kept(x.into(), t.under_binder(x))));
-        TypecheckContext(Rc::new(vec))
+        self.push(x, CtxItem::Kept(x.into(), t.under_binder(x)))
     }
-    pub fn insert_value(&self, x: &Label, e: Value) -> Result<Self, TypeError> {
-        let mut vec = self.0.as_ref().clone();
-        vec.push((x.clone(), CtxItem::Replaced(e)));
-        Ok(TypecheckContext(Rc::new(vec)))
+    pub fn insert_value(&self, x: &Label, e: Value) -> Self {
+        self.push(x, CtxItem::Replaced(e))
     }
     pub fn lookup(&self, var: &V<Label>) -> Option<Value> {
         let mut var = var.clone();
         let mut shift_map: HashMap<Label, _> = HashMap::new();
-        for (l, i) in self.0.iter().rev() {
+        for (l, i) in self.iter() {
             match var.over_binder(l) {
                 None => {
                     let i = i.under_multiple_binders(&shift_map);
@@ -57,41 +96,36 @@ impl TypecheckContext {
     /// Given a var that makes sense in the current context, map the given function in such a way
     /// that the passed variable always makes sense in the context of the passed item.
     /// Once we pass the variable definition, the variable doesn't make sense anymore so we just
-    /// copy the remaining items.
+    /// share the remaining items.
     fn do_with_var<E>(
         &self,
         var: &AlphaVar,
         mut f: impl FnMut(&AlphaVar, &CtxItem) -> Result<CtxItem, E>,
     ) -> Result<Self, E> {
-        let mut vec = Vec::new();
-        vec.reserve(self.0.len());
+        let mut items = Vec::new();
         let mut var = var.clone();
-        let mut iter = self.0.iter().rev();
-        for (l, i) in iter.by_ref() {
-            vec.push((l.clone(), f(&var, i)?));
-            if let CtxItem::Kept(_, _) = i {
-                match var.over_binder(l) {
+        let mut ctx = self;
+        while let Some(node) = &ctx.0 {
+            items.push((node.label.clone(), f(&var, &node.item)?));
+            ctx = &node.outer;
+            if let CtxItem::Kept(_, _) = node.item {
+                match var.over_binder(&node.label) {
                     None => break,
                     Some(newvar) => var = newvar,
                 };
             }
         }
-        for (l, i) in iter {
-            vec.push((l.clone(), (*i).clone()));
-        }
-        vec.reverse();
-        Ok(TypecheckContext(Rc::new(vec)))
+        Ok(Self::rebuild(items, ctx.clone()))
     }
     fn shift(&self, delta: isize, var: &AlphaVar) -> Option<Self> {
         if delta < 0 {
             Some(self.do_with_var(var, |var, i| Ok(i.shift(delta, &var)?))?)
         } else {
-            Some(TypecheckContext(Rc::new(
-                self.0
-                    .iter()
-                    .map(|(l, i)| Ok((l.clone(), i.shift(delta, &var)?)))
-                    .collect::<Result<_, _>>()?,
-            )))
+            let items = self
+                .iter()
+                .map(|(l, i)| Some((l.clone(), i.shift(delta, &var)?)))
+                .collect::<Option<_>>()?;
+            Some(Self::rebuild(items, TypecheckContext::new()))
         }
     }
     fn subst_shift(&self, var: &AlphaVar, val: &Value) -> Self {
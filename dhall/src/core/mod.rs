@@ -1,3 +1,4 @@
+pub(crate) mod budget;
 pub mod context;
 pub mod value;
 pub mod valuef;
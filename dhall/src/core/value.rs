@@ -1,8 +1,9 @@
 use std::cell::{Ref, RefCell, RefMut};
 use std::rc::Rc;
 
-use dhall_syntax::{Builtin, Const};
+use dhall_syntax::{Builtin, Const, Span};
 
+use crate::core::budget;
 use crate::core::context::TypecheckContext;
 use crate::core::valuef::ValueF;
 use crate::core::var::{AlphaVar, Shift, Subst};
@@ -36,6 +37,10 @@ struct ValueInternal {
     value: ValueF,
     /// This is None if and only if `value` is `Sort` (which doesn't have a type)
     ty: Option<Value>,
+    /// Where the expression that produced this value comes from. It is kept
+    /// through normalization, so that a value in the output can be traced
+    /// back to its source.
+    span: Option<Span>,
 }
 
 /// Stores a possibly unevaluated value. Gets (partially) normalized on-demand,
@@ -45,7 +50,10 @@ struct ValueInternal {
 pub(crate) struct Value(Rc<RefCell<ValueInternal>>);
 
 #[derive(Copy, Clone)]
-/// Controls conversion from `Value` to `Expr`
+/// Controls conversion from `Value` to `Expr`.
+///
+/// Empty lists and `None` always keep their element type, as `[] : List T`
+/// and `None T`, so that the output typechecks again whatever the options.
 pub(crate) struct ToExprOptions {
     /// Whether to convert all variables to `_`
     pub(crate) alpha: bool,
@@ -55,6 +63,7 @@ pub(crate) struct ToExprOptions {
 
 impl ValueInternal {
     fn into_value(self) -> Value {
+        budget::alloc_value();
         Value(Rc::new(RefCell::new(self)))
     }
     fn as_valuef(&self) -> &ValueF {
@@ -69,18 +78,21 @@ impl ValueInternal {
                 form: Unevaled,
                 value: ValueF::Const(Const::Type),
                 ty: None,
+                span: None,
             },
             |vint| match (&vint.form, &vint.ty) {
                 (Unevaled, Some(ty)) => ValueInternal {
                     form: WHNF,
                     value: normalize_whnf(vint.value, &ty),
                     ty: vint.ty,
+                    span: vint.span,
                 },
                 // `value` is `Sort`
                 (Unevaled, None) => ValueInternal {
                     form: NF,
                     value: ValueF::Const(Const::Sort),
                     ty: None,
+                    span: vint.span,
                 },
                 // Already in WHNF
                 (WHNF, _) | (NF, _) => vint,
@@ -118,6 +130,7 @@ impl Value {
             form,
             value,
             ty: Some(ty),
+            span: None,
         }
         .into_value()
     }
@@ -126,6 +139,7 @@ impl Value {
             form: NF,
             value: ValueF::Const(Const::Sort),
             ty: None,
+            span: None,
         }
         .into_value()
     }
@@ -142,6 +156,30 @@ impl Value {
         builtin_to_value(b)
     }
 
+    /// Records where the value comes from, unless it is already known. A
+    /// value that is shared, e.g. a variable or a cached import, is copied
+    /// instead of changed, so that its other uses don't get this span.
+    pub(crate) fn with_span_if_missing(self, span: &Span) -> Value {
+        if self.as_internal().span.is_some() {
+            return self;
+        }
+        if Rc::strong_count(&self.0) == 1 {
+            self.as_internal_mut().span = Some(span.clone());
+            return self;
+        }
+        let vint = self.as_internal();
+        ValueInternal {
+            form: vint.form,
+            value: vint.value.clone(),
+            ty: vint.ty.clone(),
+            span: Some(span.clone()),
+        }
+        .into_value()
+    }
+    pub(crate) fn span(&self) -> Option<Span> {
+        self.as_internal().span.clone()
+    }
+
     pub(crate) fn as_const(&self) -> Option<Const> {
         match &*self.as_whnf() {
             ValueF::Const(c) => Some(*c),
@@ -172,7 +210,12 @@ impl Value {
         if opts.normalize {
             self.normalize_whnf();
         }
-        self.as_valuef().to_expr(opts)
+        let expr = self.as_valuef().to_expr(opts);
+        // Keep the source location, e.g. for errors when deserializing
+        match self.span() {
+            Some(span) => expr.with_span(span),
+            None => expr,
+        }
     }
     pub(crate) fn to_whnf_ignore_type(&self) -> ValueF {
         self.as_whnf().clone()
@@ -239,6 +282,12 @@ impl Value {
     pub(crate) fn get_type(&self) -> Result<Value, TypeError> {
         Ok(self.as_internal().get_type()?.clone())
     }
+    /// Replaces the type of this value with `ty`, which must be equal to it,
+    /// so that the two are shared. The previous type is dropped as is; no
+    /// normalization happens here.
+    pub(crate) fn share_type(&self, ty: &Value) {
+        self.as_internal_mut().ty = Some(ty.clone());
+    }
     /// When we know the value isn't `Sort`, this gets the type directly
     pub(crate) fn get_type_not_sort(&self) -> Value {
         self.get_type()
@@ -258,6 +307,7 @@ impl Shift for ValueInternal {
             form: self.form,
             value: self.value.shift(delta, var)?,
             ty: self.ty.shift(delta, var)?,
+            span: self.span.clone(),
         })
     }
 }
@@ -285,14 +335,16 @@ impl Subst<Value> for ValueInternal {
             form: Unevaled,
             value: self.value.subst_shift(var, val),
             ty: self.ty.subst_shift(var, val),
+            span: self.span.clone(),
         }
     }
 }
 
-// TODO: use Rc comparison to shortcut on identical pointers
 impl std::cmp::PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
-        *self.as_whnf() == *other.as_whnf()
+        // Shared values are equal without normalizing them, which is common
+        // when comparing types during typechecking.
+        Rc::ptr_eq(&self.0, &other.0) || *self.as_whnf() == *other.as_whnf()
     }
 }
 impl std::cmp::Eq for Value {}
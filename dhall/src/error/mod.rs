@@ -1,9 +1,14 @@
 use std::io::Error as IOError;
+use std::path::PathBuf;
 
-use dhall_syntax::{BinOp, Import, Label, ParseError, V};
+use dhall_syntax::{
+    rc, BinOp, Builtin, ExprF, Feature, Hash, Import, ImportLocation, Label,
+    ParseError, PrintOptions, Span, StandardVersion, V,
+};
 
 use crate::core::context::TypecheckContext;
-use crate::core::value::Value;
+use crate::core::value::{ToExprOptions, Value};
+use crate::core::valuef::ValueF;
 use crate::phase::resolve::ImportStack;
 use crate::phase::NormalizedExpr;
 
@@ -18,6 +23,31 @@ pub enum Error {
     Encode(EncodeError),
     Resolve(ImportError),
     Typecheck(TypeError),
+    /// A feature was used that the targeted version of the standard lacks
+    UnsupportedFeature(Feature, StandardVersion),
+    /// A source file is not valid UTF-8. Holds the file and the offset of the
+    /// first invalid byte.
+    InvalidUtf8(PathBuf, usize),
+    /// The expression to evaluate is longer than `EvalOptions` allow. Holds
+    /// the limit, in bytes.
+    InputTooLarge(usize),
+    /// Evaluation took more normalization steps than `EvalOptions` allow.
+    /// Holds the limit.
+    TooManySteps(usize),
+    /// Evaluation allocated more values than `EvalOptions` allow. Holds the
+    /// limit.
+    TooManyValues(usize),
+    /// A manifest given to `Validator::read_manifest` is not a record of
+    /// types. Holds the manifest and what is wrong with it.
+    InvalidManifest(PathBuf, String),
+}
+
+/// An error from one phase of the pipeline, along with the value that phase
+/// was given, so that it can be inspected or processed again.
+#[derive(Debug)]
+pub struct PhaseError<T, E> {
+    pub input: T,
+    pub error: E,
 }
 
 #[derive(Debug)]
@@ -25,12 +55,34 @@ pub enum ImportError {
     Recursive(Import<NormalizedExpr>, Box<Error>),
     UnexpectedImport(Import<NormalizedExpr>),
     ImportCycle(ImportStack, Import<NormalizedExpr>),
+    /// The import is nested deeper than `ResolutionLimits::max_depth` allows.
+    /// Holds the imports it is nested in.
+    TooDeep(ImportStack, Import<NormalizedExpr>),
+    /// The import goes over the `ResolutionLimits::max_imports` limit, which
+    /// is given first.
+    TooManyImports(usize, Import<NormalizedExpr>),
+    /// The import is from a location that `ResolutionLimits` forbids.
+    NotAllowed(Import<NormalizedExpr>),
+    /// The imported file is larger than `ResolutionLimits::max_file_size`,
+    /// which is given first.
+    TooLarge(u64, Import<NormalizedExpr>),
+    /// The import is not in a `Recording` that is only replayed.
+    NotRecorded(Import<NormalizedExpr>),
+    /// The contents didn't match the expected hash: (expected, found)
+    HashMismatch(Hash, Hash),
+    /// The custom headers of a remote import have neither of the types
+    /// allowed by the standard. Holds the type they have instead.
+    InvalidHeadersType(Import<NormalizedExpr>, NormalizedExpr),
 }
 
 #[derive(Debug)]
 pub enum DecodeError {
     CBORError(serde_cbor::error::Error),
     WrongFormatError(String),
+    /// The encoded expression refers to a builtin that this version of the
+    /// crate doesn't know about, likely because it was added to the standard
+    /// later.
+    UnknownBuiltin(String),
 }
 
 #[derive(Debug)]
@@ -38,6 +90,99 @@ pub enum EncodeError {
     CBORError(serde_cbor::error::Error),
 }
 
+/// Failure to select a field path inside a normalized expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectError {
+    /// The last segment of `path` is not a field of the record
+    MissingField {
+        path: Vec<String>,
+        available: Vec<String>,
+    },
+    /// The value at `path` is not a record, so it has no fields to select
+    NotARecord { path: Vec<String> },
+}
+
+/// Failure to convert a Dhall value to a Rust value, see
+/// `dhall::phase::FromDhall`
+#[derive(Debug, Clone)]
+pub struct ConversionError {
+    /// What was expected, and what was found instead
+    pub message: String,
+    /// Where the value that couldn't be converted comes from, if known
+    pub span: Option<Span>,
+}
+
+/// Failure to read a Rust scalar from a value, see `Typed::as_natural`, etc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScalarError {
+    /// The value has another type. `found` is `None` for `Sort`, which has
+    /// no type.
+    WrongType {
+        expected: Builtin,
+        found: Option<String>,
+    },
+    /// The value has the expected type but isn't a literal, e.g. because it
+    /// depends on a variable of unknown value
+    NotALiteral { expected: Builtin, value: String },
+}
+
+/// Failure to interpret an expression as a `{ Type = ..., default = ... }`
+/// record schema
+#[derive(Debug)]
+pub enum SchemaError {
+    /// The expression is not a record with `Type` and `default` fields
+    NotASchema,
+    /// No placeholder value can be made for the field at `path`, e.g. because
+    /// it is a function
+    NoExample { path: Vec<String>, ty: String },
+    /// The defaults don't match the schema's type
+    Typecheck(TypeError),
+}
+
+/// Failure of one of the round-trip checks in `dhall::testing`
+#[derive(Debug)]
+pub enum RoundTripError {
+    Encode(EncodeError),
+    Decode(DecodeError),
+    /// The printed expression doesn't parse
+    Parse(ParseError),
+    /// The expression that came back through `format`, either `binary` or
+    /// `text`, differs from the original. Both are shown with `Debug`.
+    Mismatch {
+        format: &'static str,
+        original: String,
+        roundtripped: String,
+    },
+}
+
+/// A problem that doesn't prevent evaluating an expression, see
+/// `Parsed::warnings`.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// Where the problem is in the source, if known
+    pub span: Option<Span>,
+    /// Another location involved, such as the binding that is shadowed
+    pub related_span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WarningKind {
+    /// A builtin that is deprecated by the standard
+    DeprecatedBuiltin(Builtin),
+    /// A `let` binding that is never used
+    UnusedLet(Label),
+    /// An import over plain `http`, with the given URL
+    InsecureImport(String),
+    /// A `let` or lambda that binds the same name as an enclosing one. The
+    /// enclosing binding is at `related_span`.
+    ShadowedBinding(Label),
+    /// An entry of the `Store` that doesn't hold the expression its hash
+    /// protects, with why. The import was resolved from its location instead.
+    CorruptStoreEntry(Hash, String),
+}
+
 /// A structured type error that includes context
 #[derive(Debug)]
 pub struct TypeError {
@@ -54,8 +199,16 @@ pub(crate) enum TypeMessage {
     NotAFunction(Value),
     TypeMismatch(Value, Value, Value),
     AnnotMismatch(Value, Value),
+    /// The expected type given to `Resolved::typecheck_with` is not a type
+    AnnotMustBeType(Value),
     InvalidListElement(usize, Value, Value),
     InvalidListType(Value),
+    /// The annotation of an empty list, and where it is, when it is not a
+    /// `List` type
+    InvalidEmptyListAnnotation {
+        annotation: Value,
+        span: Option<Span>,
+    },
     InvalidOptionalType(Value),
     InvalidPredicate(Value),
     IfBranchMismatch(Value, Value),
@@ -64,14 +217,48 @@ pub(crate) enum TypeMessage {
     NotARecord(Label, Value),
     MustCombineRecord(Value),
     MissingRecordField(Label, Value),
-    MissingUnionField(Label, Value),
+    /// Selecting `label` from a union type that has no such alternative.
+    /// Holds the alternatives it has, the closest one to `label` if any is
+    /// close enough, and where the union type is defined.
+    MissingUnionField {
+        label: Label,
+        union: Value,
+        alternatives: Vec<Label>,
+        suggestion: Option<Label>,
+        span: Option<Span>,
+    },
     BinOpTypeMismatch(BinOp, Value),
-    InvalidTextInterpolation(Value),
+    /// A value lacks some fields of the record type given to
+    /// `Typed::conforms_to`, or has some of them with another type. Holds
+    /// the paths of the missing fields, and those of the mismatched fields
+    /// with their expected and found types.
+    RecordDoesNotConform {
+        missing: Vec<Vec<Label>>,
+        mismatched: Vec<(Vec<Label>, Value, Value)>,
+    },
+    /// An interpolation in a text literal that is not `Text`, with its type,
+    /// the builtin that converts it to `Text` if there is one, its position
+    /// among the interpolations of the literal, and where it is
+    InvalidTextInterpolation {
+        expr: Value,
+        ty: Value,
+        conversion: Option<Builtin>,
+        index: usize,
+        span: Option<Span>,
+    },
     Merge1ArgMustBeRecord(Value),
     Merge2ArgMustBeUnion(Value),
     MergeEmptyNeedsAnnotation,
-    MergeHandlerMissingVariant(Label),
-    MergeVariantMissingHandler(Label),
+    /// The handlers of a `merge` don't match the alternatives of the union
+    /// type. Holds the alternatives without a handler and the handlers
+    /// without an alternative, both sorted, and where the union type is
+    /// defined.
+    MergeHandlersMismatch {
+        union: Value,
+        missing: Vec<Label>,
+        extra: Vec<Label>,
+        span: Option<Span>,
+    },
     MergeAnnotMismatch,
     MergeHandlerTypeMismatch,
     MergeHandlerReturnTypeMustNotBeDependent,
@@ -80,6 +267,15 @@ pub(crate) enum TypeMessage {
     Sort,
     RecordTypeDuplicateField,
     RecordTypeMergeRequiresRecordType(Value),
+    /// Two fields at `path` in a recursive record merge that aren't both
+    /// records, with their types and where they are defined
+    RecordMergeCollision {
+        path: Vec<Label>,
+        left: Value,
+        right: Value,
+        left_span: Option<Span>,
+        right_span: Option<Span>,
+    },
     UnionTypeDuplicateField,
     EquivalenceArgumentMustBeTerm(bool, Value),
     EquivalenceTypeMismatch(Value, Value),
@@ -97,6 +293,10 @@ impl TypeError {
             type_message,
         }
     }
+
+    pub(crate) fn message_mut(&mut self) -> &mut TypeMessage {
+        &mut self.type_message
+    }
 }
 
 impl std::error::Error for TypeMessage {
@@ -113,50 +313,688 @@ impl std::error::Error for TypeMessage {
     }
 }
 
+/// How deep and how wide values are printed in diagnostics, unless the
+/// alternate flag (`{:#}`) asks for them in full.
+const DIAGNOSTIC_PRINT_OPTIONS: PrintOptions = PrintOptions {
+    preserve_field_order: false,
+    max_depth: Some(4),
+    max_width: Some(8),
+    ascii_only: false,
+};
+
+/// A value printed in a diagnostic, elided according to
+/// `DIAGNOSTIC_PRINT_OPTIONS` unless `verbose` is set.
+struct DiagValue<'a>(&'a Value, bool);
+
+impl DiagValue<'_> {
+    fn to_expr(&self) -> NormalizedExpr {
+        self.0.to_expr(ToExprOptions {
+            alpha: false,
+            normalize: false,
+        })
+    }
+}
+
+impl std::fmt::Display for DiagValue<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt_diag_expr(f, &self.to_expr(), self.1)
+    }
+}
+
+fn fmt_diag_expr(
+    f: &mut std::fmt::Formatter,
+    expr: &NormalizedExpr,
+    verbose: bool,
+) -> std::fmt::Result {
+    if verbose {
+        write!(f, "{}", expr)
+    } else {
+        write!(f, "{}", expr.display_with(DIAGNOSTIC_PRINT_OPTIONS))
+    }
+}
+
+/// The argument of `t` if it is the builtin `b` applied to one argument, e.g.
+/// `T` for `Optional T`.
+fn applied_builtin(t: &Value, b: Builtin) -> Option<Value> {
+    match &*t.as_whnf() {
+        ValueF::AppliedBuiltin(b2, args) if *b2 == b && args.len() == 1 => {
+            Some(args[0].clone())
+        }
+        _ => None,
+    }
+}
+
 impl std::fmt::Display for TypeMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use TypeMessage::*;
+        let verbose = f.alternate();
+        let v = |v| DiagValue(v, verbose);
+        let side = |left: &bool| if *left { "left" } else { "right" };
+        match self {
+            UnboundVariable(x) => {
+                write!(f, "Unbound variable `{}`", x)?;
+                if x.0 == "constructors".into() {
+                    write!(
+                        f,
+                        "; the `constructors` keyword was removed, select alternatives from the union type directly, e.g. `U.A` instead of `(constructors U).A`"
+                    )?;
+                }
+                Ok(())
+            }
+            InvalidInputType(t) => {
+                write!(f, "Invalid function input type: {}", v(t))
+            }
+            InvalidOutputType(t) => {
+                write!(f, "Invalid function output type: {}", v(t))
+            }
+            NotAFunction(x) => write!(f, "Not a function: {}", v(x)),
+            TypeMismatch(g, expected, x) => write!(
+                f,
+                "Wrong type of function argument: {} expects an argument of type {}, but got {}",
+                v(g),
+                v(expected),
+                v(x)
+            ),
+            AnnotMismatch(x, t) => {
+                write!(
+                    f,
+                    "Expression doesn't match its annotation: {} is not of type {}",
+                    v(x),
+                    v(t)
+                )?;
+                let is_list = x.get_type().ok().map_or(false, |t| {
+                    applied_builtin(&t, Builtin::List).is_some()
+                });
+                if is_list && applied_builtin(t, Builtin::Optional).is_some() {
+                    write!(
+                        f,
+                        "; lists annotated with `Optional` were removed, use `Some x` instead of `[ x ] : Optional T`"
+                    )?;
+                }
+                Ok(())
+            }
+            AnnotMustBeType(t) => write!(
+                f,
+                "Expected a type to check against, but got {}",
+                v(t)
+            ),
+            InvalidListElement(i, t, x) => write!(
+                f,
+                "List element {} should have type {}, but got {}",
+                i,
+                v(t),
+                v(x)
+            ),
+            InvalidListType(t) => write!(f, "Invalid list type: {}", v(t)),
+            InvalidEmptyListAnnotation { annotation, span } => {
+                let normalized = annotation.to_expr(ToExprOptions {
+                    alpha: false,
+                    normalize: true,
+                });
+                write!(
+                    f,
+                    "An empty list must be annotated with `List a`, but the annotation is "
+                )?;
+                fmt_diag_expr(f, &normalized, verbose)?;
+                if let Some(span) = span {
+                    let (line, col) = span.start_line_col();
+                    write!(f, " (at {}:{})", line, col)?;
+                }
+                if let Some(a) = applied_builtin(annotation, Builtin::Optional) {
+                    write!(
+                        f,
+                        "; empty lists annotated with `Optional` were removed, use `None {}` instead",
+                        v(&a)
+                    )?;
+                }
+                Ok(())
+            }
+            InvalidOptionalType(t) => {
+                write!(f, "Invalid optional type: {}", v(t))
+            }
+            InvalidPredicate(x) => {
+                write!(f, "Invalid `if` predicate: {}", v(x))
+            }
+            IfBranchMismatch(x, y) => write!(
+                f,
+                "`if` branches have different types: {} and {}",
+                v(x),
+                v(y)
+            ),
+            IfBranchMustBeTerm(then, x) => write!(
+                f,
+                "The {} branch of `if` must be a term: {}",
+                if *then { "then" } else { "else" },
+                v(x)
+            ),
+            InvalidFieldType(l, t) => {
+                write!(f, "Invalid type for field `{}`: {}", l, v(t))
+            }
+            NotARecord(l, x) => write!(
+                f,
+                "Cannot access field `{}` of a non-record: {}",
+                l,
+                v(x)
+            ),
+            MustCombineRecord(x) => {
+                write!(f, "Only records can be combined: {}", v(x))
+            }
+            MissingRecordField(l, x) => {
+                write!(f, "Missing record field `{}` in {}", l, v(x))
+            }
+            MissingUnionField {
+                label,
+                union,
+                alternatives,
+                suggestion,
+                span,
+            } => {
+                write!(
+                    f,
+                    "Missing union alternative `{}` in {}",
+                    label,
+                    v(union)
+                )?;
+                if let Some(span) = span {
+                    let (line, col) = span.start_line_col();
+                    write!(f, " (defined at {}:{})", line, col)?;
+                }
+                let alternatives: Vec<_> =
+                    alternatives.iter().map(|l| format!("`{}`", l)).collect();
+                write!(f, "; the alternatives are {}", alternatives.join(", "))?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ". Did you mean `{}`?", suggestion)?;
+                }
+                Ok(())
+            }
+            BinOpTypeMismatch(op, x) => write!(
+                f,
+                "Wrong type of operand for `{}`: {}",
+                op,
+                v(x)
+            ),
+            RecordDoesNotConform {
+                missing,
+                mismatched,
+            } => {
+                let path = |path: &[Label]| {
+                    let labels: Vec<_> =
+                        path.iter().map(|l| l.to_string()).collect();
+                    labels.join(".")
+                };
+                f.write_str("The value doesn't conform to the record type")?;
+                let mut sep = ": ";
+                if !missing.is_empty() {
+                    let missing: Vec<_> = missing
+                        .iter()
+                        .map(|p| format!("`{}`", path(p)))
+                        .collect();
+                    write!(f, "{}missing {}", sep, missing.join(", "))?;
+                    sep = "; ";
+                }
+                for (p, expected, found) in mismatched {
+                    if p.is_empty() {
+                        write!(
+                            f,
+                            "{}expected {}, but found {}",
+                            sep,
+                            v(expected),
+                            v(found)
+                        )?;
+                    } else {
+                        write!(
+                            f,
+                            "{}`{}` should have type {}, but has type {}",
+                            sep,
+                            path(p),
+                            v(expected),
+                            v(found)
+                        )?;
+                    }
+                    sep = "; ";
+                }
+                Ok(())
+            }
+            InvalidTextInterpolation {
+                expr,
+                ty,
+                conversion,
+                span,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Only `Text` can be interpolated, but `${{{}}}`",
+                    v(expr),
+                )?;
+                if let Some(span) = span {
+                    let (line, col) = span.start_line_col();
+                    write!(f, " (at {}:{})", line, col)?;
+                }
+                write!(f, " has type {}", v(ty))?;
+                if let Some(b) = conversion {
+                    let converted = rc(ExprF::App(
+                        rc(ExprF::Builtin(*b)),
+                        v(expr).to_expr(),
+                    ));
+                    write!(f, "; convert it with `${{")?;
+                    fmt_diag_expr(f, &converted, verbose)?;
+                    write!(f, "}}`")?;
+                }
+                Ok(())
+            }
+            Merge1ArgMustBeRecord(x) => write!(
+                f,
+                "The first argument of `merge` must be a record: {}",
+                v(x)
+            ),
+            Merge2ArgMustBeUnion(x) => write!(
+                f,
+                "The second argument of `merge` must be a union: {}",
+                v(x)
+            ),
+            MergeEmptyNeedsAnnotation => {
+                f.write_str("An empty `merge` needs a type annotation")
+            }
+            MergeHandlersMismatch {
+                union,
+                missing,
+                extra,
+                span,
+            } => {
+                let labels = |ls: &[Label]| {
+                    let ls: Vec<_> =
+                        ls.iter().map(|l| format!("`{}`", l)).collect();
+                    ls.join(", ")
+                };
+                write!(
+                    f,
+                    "The handlers of `merge` don't match the alternatives of {}",
+                    v(union)
+                )?;
+                if let Some(span) = span {
+                    let (line, col) = span.start_line_col();
+                    write!(f, " (defined at {}:{})", line, col)?;
+                }
+                if !missing.is_empty() {
+                    write!(f, "; no handler for {}", labels(missing))?;
+                }
+                if !extra.is_empty() {
+                    write!(
+                        f,
+                        "; handlers for unknown alternatives {}",
+                        labels(extra)
+                    )?;
+                }
+                Ok(())
+            }
+            MergeAnnotMismatch => {
+                f.write_str("`merge` doesn't match its annotation")
+            }
+            MergeHandlerTypeMismatch => {
+                f.write_str("`merge` handlers have different types")
+            }
+            MergeHandlerReturnTypeMustNotBeDependent => {
+                f.write_str("`merge` handlers must not have a dependent type")
+            }
+            ProjectionMustBeRecord => {
+                f.write_str("Only records can be projected")
+            }
+            ProjectionMissingEntry => {
+                f.write_str("Projection of a field missing from the record")
+            }
+            Sort => f.write_str("`Sort` has no type"),
+            RecordTypeDuplicateField => {
+                f.write_str("Duplicate field in record type")
+            }
+            RecordTypeMergeRequiresRecordType(x) => write!(
+                f,
+                "Only record types can be combined with `⩓`: {}",
+                v(x)
+            ),
+            RecordMergeCollision {
+                path,
+                left,
+                right,
+                left_span,
+                right_span,
+            } => {
+                let path: Vec<_> = path.iter().map(String::from).collect();
+                write!(
+                    f,
+                    "Field `{}` is defined on both sides of a recursive merge, but not as two records: {} and {}",
+                    path.join("."),
+                    v(left),
+                    v(right)
+                )?;
+                if let (Some(l), Some(r)) = (left_span, right_span) {
+                    let (l_line, l_col) = l.start_line_col();
+                    let (r_line, r_col) = r.start_line_col();
+                    write!(
+                        f,
+                        " (defined at {}:{} and {}:{})",
+                        l_line, l_col, r_line, r_col
+                    )?;
+                }
+                Ok(())
+            }
+            UnionTypeDuplicateField => {
+                f.write_str("Duplicate alternative in union type")
+            }
+            EquivalenceArgumentMustBeTerm(left, x) => write!(
+                f,
+                "The {} side of `≡` must be a term: {}",
+                side(left),
+                v(x)
+            ),
+            EquivalenceTypeMismatch(x, y) => write!(
+                f,
+                "The sides of `≡` have different types: {} and {}",
+                v(x),
+                v(y)
+            ),
+            AssertMismatch(x, y) => write!(
+                f,
+                "Assertion failed: {} is not equivalent to {}",
+                v(x),
+                v(y)
+            ),
+            AssertMustTakeEquivalence => {
+                f.write_str("`assert` expects an equivalence")
+            }
+        }
+    }
+}
+
+/// Values in the message are elided to keep it readable; use `{:#}` to print
+/// them in full.
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.type_message.fmt(f)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+impl std::fmt::Display for ImportError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            // UnboundVariable(_) => {
-            //     f.write_str(include_str!("errors/UnboundVariable.txt"))
-            // }
-            // TypeMismatch(e0, e1, e2) => {
-            //     let template = include_str!("errors/TypeMismatch.txt");
-            //     let s = template
-            //         .replace("$txt0", &format!("{}", e0.as_expr()))
-            //         .replace("$txt1", &format!("{}", e1.as_expr()))
-            //         .replace("$txt2", &format!("{}", e2.as_expr()))
-            //         .replace(
-            //             "$txt3",
-            //             &format!(
-            //                 "{}",
-            //                 e2.get_type()
-            //                     .unwrap()
-            //                     .as_normalized()
-            //                     .unwrap()
-            //                     .as_expr()
-            //             ),
-            //         );
-            //     f.write_str(&s)
-            // }
-            _ => f.write_str("Unhandled error message"),
+            ImportError::Recursive(import, err) => {
+                write!(f, "Error in import {}: ", import)?;
+                err.fmt(f)
+            }
+            ImportError::UnexpectedImport(import) => {
+                write!(f, "Unexpected import: {}", import)
+            }
+            ImportError::ImportCycle(stack, import) => {
+                write!(f, "Import cycle: ")?;
+                for i in stack {
+                    write!(f, "{} -> ", i)?;
+                }
+                write!(f, "{}", import)
+            }
+            ImportError::TooDeep(stack, import) => {
+                write!(f, "Import nested too deeply: ")?;
+                for i in stack {
+                    write!(f, "{} -> ", i)?;
+                }
+                write!(f, "{}", import)
+            }
+            ImportError::TooManyImports(max, import) => write!(
+                f,
+                "Too many imports, the limit is {}: {}",
+                max, import
+            ),
+            ImportError::NotAllowed(import) => {
+                let reason = match import.location {
+                    ImportLocation::Remote(_) => "remote imports are disabled",
+                    ImportLocation::Env(_) => {
+                        "environment variable imports are disabled"
+                    }
+                    _ => "outside of the allowed directory",
+                };
+                write!(f, "Import not allowed, {}: {}", reason, import)
+            }
+            ImportError::TooLarge(max, import) => write!(
+                f,
+                "Imported file is larger than the limit of {} bytes: {}",
+                max, import
+            ),
+            ImportError::NotRecorded(import) => {
+                write!(f, "Import not found in the recording: {}", import)
+            }
+            ImportError::InvalidHeadersType(import, found) => write!(
+                f,
+                "Headers of {} should have type `List {{ mapKey : Text, mapValue : Text }}` or `List {{ header : Text, value : Text }}`, but have type {}",
+                import, found
+            ),
+            ImportError::HashMismatch(expected, found) => write!(
+                f,
+                "Hash mismatch: expected {}, found {}",
+                expected, found
+            ),
         }
     }
 }
 
+impl std::error::Error for ImportError {}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Error::IO(err) => write!(f, "{}", err),
             Error::Parse(err) => write!(f, "{}", err),
+            Error::Decode(DecodeError::UnknownBuiltin(name)) => write!(
+                f,
+                "unknown builtin `{}`; decoding it may require a newer version of this crate",
+                name
+            ),
             Error::Decode(err) => write!(f, "{:?}", err),
             Error::Encode(err) => write!(f, "{:?}", err),
-            Error::Resolve(err) => write!(f, "{:?}", err),
-            Error::Typecheck(err) => write!(f, "{:?}", err),
+            Error::Resolve(err) => err.fmt(f),
+            Error::Typecheck(err) => err.fmt(f),
+            Error::UnsupportedFeature(feature, version) => {
+                write!(f, "{}", feature.unavailable_message(*version))
+            }
+            Error::InvalidUtf8(path, offset) => write!(
+                f,
+                "{}: invalid UTF-8 at byte {}",
+                path.display(),
+                offset
+            ),
+            Error::InputTooLarge(max) => {
+                write!(f, "Input is larger than the limit of {} bytes", max)
+            }
+            Error::TooManySteps(max) => write!(
+                f,
+                "Evaluation took more than the limit of {} normalization steps",
+                max
+            ),
+            Error::TooManyValues(max) => write!(
+                f,
+                "Evaluation allocated more than the limit of {} values",
+                max
+            ),
+            Error::InvalidManifest(path, message) => {
+                write!(f, "{}: invalid manifest: {}", path.display(), message)
+            }
         }
     }
 }
 
 impl std::error::Error for Error {}
+
+impl std::fmt::Display for SelectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SelectError::MissingField { path, available } => write!(
+                f,
+                "No field `{}`; available fields are: {}",
+                path.join("."),
+                available.join(", ")
+            ),
+            SelectError::NotARecord { path } if path.is_empty() => {
+                write!(f, "The expression is not a record")
+            }
+            SelectError::NotARecord { path } => {
+                write!(f, "`{}` is not a record", path.join("."))
+            }
+        }
+    }
+}
+
+impl std::error::Error for SelectError {}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(span) = &self.span {
+            let (line, col) = span.start_line_col();
+            write!(f, " (at {}:{})", line, col)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl std::fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WarningKind::DeprecatedBuiltin(b) => {
+                write!(f, "The builtin `{}` is deprecated", b)?;
+                match b {
+                    Builtin::OptionalFold => write!(
+                        f,
+                        "; use `merge {{ None = n, Some = s }} o` instead"
+                    ),
+                    Builtin::OptionalBuild => write!(
+                        f,
+                        "; build the value with `Some` and `None` instead"
+                    ),
+                    _ => Ok(()),
+                }
+            }
+            WarningKind::UnusedLet(x) => {
+                write!(f, "The binding `{}` is never used", x)
+            }
+            WarningKind::InsecureImport(url) => {
+                write!(f, "The import `{}` is not over https", url)
+            }
+            WarningKind::ShadowedBinding(x) => {
+                write!(f, "The binding `{}` shadows an outer binding", x)
+            }
+            WarningKind::CorruptStoreEntry(hash, reason) => write!(
+                f,
+                "The store entry for {} was ignored because it is corrupt: {}",
+                hash, reason
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = &self.span {
+            let (line, col) = span.start_line_col();
+            match span.source_name() {
+                Some(name) => write!(f, " at {}:{}:{}", name, line, col)?,
+                None => write!(f, " at {}:{}", line, col)?,
+            }
+        }
+        if let Some(span) = &self.related_span {
+            let (line, col) = span.start_line_col();
+            write!(f, " (see {}:{})", line, col)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ScalarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScalarError::WrongType {
+                expected,
+                found: Some(found),
+            } => write!(
+                f,
+                "Expected a value of type `{}`, found one of type `{}`",
+                expected, found
+            ),
+            ScalarError::WrongType {
+                expected,
+                found: None,
+            } => write!(
+                f,
+                "Expected a value of type `{}`, found `Sort`",
+                expected
+            ),
+            ScalarError::NotALiteral { expected, value } => {
+                write!(f, "Expected a {} literal, found `{}`", expected, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScalarError {}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SchemaError::NotASchema => write!(
+                f,
+                "Expected a record with a `Type` record type and a `default` record"
+            ),
+            SchemaError::NoExample { path, ty } => write!(
+                f,
+                "Cannot make an example value for `{}` of type `{}`",
+                path.join("."),
+                ty
+            ),
+            SchemaError::Typecheck(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl std::fmt::Display for RoundTripError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RoundTripError::Encode(e) => write!(f, "{:?}", e),
+            RoundTripError::Decode(e) => write!(f, "{:?}", e),
+            RoundTripError::Parse(e) => write!(f, "{}", e),
+            RoundTripError::Mismatch {
+                format,
+                original,
+                roundtripped,
+            } => write!(
+                f,
+                "The expression changed through the {} format:\n{}\nbecame:\n{}",
+                format, original, roundtripped
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RoundTripError {}
+
+impl<T, E: std::fmt::Display> std::fmt::Display for PhaseError<T, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<T: std::fmt::Debug, E: std::error::Error> std::error::Error
+    for PhaseError<T, E>
+{
+}
+impl<T, E: Into<Error>> From<PhaseError<T, E>> for Error {
+    fn from(err: PhaseError<T, E>) -> Error {
+        err.error.into()
+    }
+}
 impl From<IOError> for Error {
     fn from(err: IOError) -> Error {
         Error::IO(err)
@@ -187,3 +1025,26 @@ impl From<TypeError> for Error {
         Error::Typecheck(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::phase::Parsed;
+
+    #[test]
+    fn type_errors_elide_large_values() {
+        let err = Parsed::parse_str("[ 1, 2, 3, 4, 5, 6, 7, 8, 9, 10 ] : Bool")
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expression doesn't match its annotation: [1, 2, 3, 4, 5, 6, 7, 8, …] is not of type Bool"
+        );
+        assert_eq!(
+            format!("{:#}", err),
+            "Expression doesn't match its annotation: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10] is not of type Bool"
+        );
+    }
+}
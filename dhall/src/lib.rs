@@ -2,6 +2,7 @@
 #![feature(slice_patterns)]
 #![feature(non_exhaustive)]
 #![feature(never_type)]
+#![cfg_attr(test, feature(test))]
 #![allow(
     clippy::type_complexity,
     clippy::infallible_destructuring_match,
@@ -14,6 +15,12 @@
 #[macro_use]
 mod tests;
 
+// Lets the code generated by the `FromDhall` and `ToDhall` derives refer to
+// this crate as `::dhall` in its own tests.
+#[cfg(test)]
+extern crate self as dhall;
+
 pub mod core;
 pub mod error;
 pub mod phase;
+pub mod testing;
@@ -1,11 +1,17 @@
+//! Conversion from and to the standard binary encoding of expressions.
 use itertools::Itertools;
 use serde_cbor::value::value as cbor;
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
 use std::iter::FromIterator;
+use std::path::Path;
 
 use dhall_syntax::map::DupTreeMap;
 use dhall_syntax::{
-    rc, Expr, ExprF, FilePrefix, Hash, Import, ImportLocation, ImportMode,
-    Integer, InterpolatedText, Label, Natural, Scheme, URL, V,
+    rc, Expr, ExprF, FilePrefix, Hash, HashAlgorithm, Import, ImportLocation,
+    ImportMode, Integer, InterpolatedText, Label, Natural, Scheme, URL, V,
 };
 
 use crate::error::{DecodeError, EncodeError};
@@ -23,6 +29,48 @@ pub(crate) fn encode<E>(expr: &Expr<E>) -> Result<Vec<u8>, EncodeError> {
         .map_err(|e| EncodeError::CBORError(e))
 }
 
+/// Writes `data` to a temporary file next to `path` and renames it to `path`,
+/// so that readers never see a partially written file.
+pub(crate) fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "not a file path")
+    })?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = path.with_file_name(tmp_name);
+    let result = File::create(&tmp)
+        .and_then(|mut f| f.write_all(data).and_then(|()| f.sync_all()))
+        .and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Labels can be any string in CBOR, but only some are allowed by the
+/// standard.
+fn decode_label(s: &str) -> Result<Label, DecodeError> {
+    if Label::is_valid(s) {
+        Ok(Label::from(s))
+    } else {
+        Err(DecodeError::WrongFormatError(format!("label `{}`", s)))
+    }
+}
+
+/// The hash of some binary-encoded data.
+pub(crate) fn hash_bytes(data: &[u8]) -> Hash {
+    Hash::new(HashAlgorithm::SHA256, Sha256::digest(data).to_vec())
+        .expect("sha256 digests are 32 bytes long")
+}
+
+/// Checks that some binary-encoded data matches the given semantic hash,
+/// without decoding it. The data must be the encoding of an alpha-normalized
+/// expression, as found in the import cache.
+pub fn verify_hash(data: &[u8], hash: &Hash) -> bool {
+    &hash_bytes(data) == hash
+}
+
 fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
     use cbor::Value::*;
     use dhall_syntax::{BinOp, Builtin, Const};
@@ -36,7 +84,7 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
                 "Type" => Const(Const::Type),
                 "Kind" => Const(Const::Kind),
                 "Sort" => Const(Const::Sort),
-                _ => Err(DecodeError::WrongFormatError("builtin".to_owned()))?,
+                _ => return Err(DecodeError::UnknownBuiltin(s.clone())),
             },
         },
         U64(n) => Var(V(Label::from("_"), *n as usize)),
@@ -49,7 +97,7 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
                         "`_` variable was encoded incorrectly".to_owned(),
                     ))?
                 }
-                let l = Label::from(l.as_str());
+                let l = decode_label(l)?;
                 Var(V(l, *n as usize))
             }
             [U64(0), f, args @ ..] => {
@@ -79,7 +127,7 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
                 }
                 let x = cbor_value_to_dhall(&x)?;
                 let y = cbor_value_to_dhall(&y)?;
-                let l = Label::from(l.as_str());
+                let l = decode_label(l)?;
                 Lam(l, x, y)
             }
             [U64(2), x, y] => {
@@ -95,7 +143,7 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
                 }
                 let x = cbor_value_to_dhall(&x)?;
                 let y = cbor_value_to_dhall(&y)?;
-                let l = Label::from(l.as_str());
+                let l = decode_label(l)?;
                 Pi(l, x, y)
             }
             [U64(3), U64(n), x, y] => {
@@ -171,7 +219,7 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
             }
             [U64(9), x, String(l)] => {
                 let x = cbor_value_to_dhall(&x)?;
-                let l = Label::from(l.as_str());
+                let l = decode_label(l)?;
                 Field(x, l)
             }
             [U64(10), x, rest @ ..] => {
@@ -179,7 +227,7 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
                 let labels = rest
                     .iter()
                     .map(|s| match s {
-                        String(s) => Ok(Label::from(s.as_str())),
+                        String(s) => decode_label(s),
                         _ => Err(DecodeError::WrongFormatError(
                             "projection".to_owned(),
                         )),
@@ -238,9 +286,15 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
                 let hash = match hash {
                     Null => None,
                     Bytes(bytes) => match bytes.as_slice() {
-                        [18, 32, rest @ ..] => {
-                            Some(Hash::SHA256(rest.to_vec()))
-                        }
+                        [18, 32, rest @ ..] => Some(
+                            Hash::new(HashAlgorithm::SHA256, rest.to_vec())
+                                .map_err(|e| {
+                                    DecodeError::WrongFormatError(format!(
+                                        "import/hash/{}",
+                                        e
+                                    ))
+                                })?,
+                        ),
                         _ => Err(DecodeError::WrongFormatError(format!(
                             "import/hash/unknown_multihash: {:?}",
                             bytes
@@ -345,7 +399,7 @@ fn cbor_value_to_dhall(data: &cbor::Value) -> Result<DecodedExpr, DecodeError> {
                                 "let/label".to_owned(),
                             )
                         })?;
-                        let x = Label::from(x.as_str());
+                        let x = decode_label(x)?;
                         let t = match t {
                             Null => None,
                             t => Some(cbor_value_to_dhall(&t)?),
@@ -399,7 +453,7 @@ where
                 DecodeError::WrongFormatError("map/key".to_owned())
             })?;
             let v = cbor_value_to_dhall(v)?;
-            Ok((Label::from(k.as_ref()), v))
+            Ok((decode_label(k)?, v))
         })
         .collect::<Result<_, _>>()
 }
@@ -419,7 +473,7 @@ where
                 cbor::Value::Null => None,
                 _ => Some(cbor_value_to_dhall(v)?),
             };
-            Ok((Label::from(k.as_ref()), v))
+            Ok((decode_label(k)?, v))
         })
         .collect::<Result<_, _>>()
 }
@@ -563,9 +617,9 @@ where
                 .chain(ls.iter().map(label)),
         ),
         Import(import) => serialize_import(ser, import),
-        Embed(_) => unimplemented!(
-            "An expression with resolved imports cannot be binary-encoded"
-        ),
+        Embed(_) => Err(serde::ser::Error::custom(
+            "an expression with resolved imports cannot be binary-encoded",
+        )),
     }
 }
 
@@ -591,11 +645,7 @@ where
 
     let hash = match &import.hash {
         None => Null,
-        Some(Hash::SHA256(h)) => {
-            let mut bytes = vec![18, 32];
-            bytes.extend_from_slice(h);
-            Bytes(bytes)
-        }
+        Some(hash) => Bytes(hash.to_multihash()),
     };
     ser_seq.serialize_element(&hash)?;
 
@@ -717,3 +767,112 @@ fn collect_nested_lets<'a, E>(
     let e = go(e, &mut vec);
     (e, vec)
 }
+
+#[cfg(test)]
+mod tests {
+    use dhall_syntax::{parse_expr, rc, ExprF, Label, V};
+
+    use super::decode;
+    use crate::error::DecodeError;
+    use crate::phase::{Normalized, ParsedExpr};
+    use crate::testing::check_roundtrips;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn decode_unknown_builtin() {
+        let data = serde_cbor::to_vec(&"Natural/frobnicate").unwrap();
+        match decode(&data) {
+            Err(DecodeError::UnknownBuiltin(name)) => {
+                assert_eq!(name, "Natural/frobnicate")
+            }
+            res => panic!("Expected UnknownBuiltin, got: {:?}", res),
+        }
+        let data = serde_cbor::to_vec(&"Natural/subtract").unwrap();
+        decode(&data).unwrap();
+    }
+
+    #[test]
+    fn exotic_labels_roundtrip() {
+        let labels = [
+            "x",
+            "_x",
+            "x-y/z",
+            "Some",
+            "if",
+            "assert",
+            "forall",
+            "ifx",
+            "Natural",
+            "Type",
+            "1x",
+            "a b",
+            "-",
+            "~!@#$%^&*()",
+        ];
+        for l in labels.iter() {
+            let l = Label::from(*l);
+            let var: ParsedExpr = rc(ExprF::Var(V(l.clone(), 0)));
+            let field = rc(ExprF::Field(var, l.clone()));
+            let record =
+                rc(ExprF::RecordLit(Some((l, field)).into_iter().collect()));
+            check_roundtrips(&record).unwrap();
+        }
+    }
+
+    #[test]
+    fn reject_invalid_labels() {
+        for l in ["é", "a`b", "", "tab\t"].iter() {
+            let data = serde_cbor::to_vec(&(*l, 0)).unwrap();
+            match decode(&data) {
+                Err(DecodeError::WrongFormatError(_)) => {}
+                res => panic!("Expected WrongFormatError, got: {:?}", res),
+            }
+        }
+        for src in
+            ["`é`", "\\(assert : Bool) -> assert", "let Some = 1 in 2"].iter()
+        {
+            parse_expr::<Normalized>(src).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn write_binary_files() {
+        use crate::phase::Parsed;
+        let dir = TempDir::new();
+        let path = dir.join("out.dhallb");
+        let parsed = Parsed::parse_str("{ x = 1 + 1 }").unwrap();
+        parsed.to_binary_file(&path).unwrap();
+        let read = Parsed::parse_binary_file(&path).unwrap();
+        assert_eq!(read.encode().unwrap(), parsed.encode().unwrap());
+
+        let normalized = parsed.skip_resolve().unwrap().typecheck().unwrap();
+        normalized.normalize().encode_to_file(&path).unwrap();
+        let read = Parsed::parse_binary_file(&path).unwrap();
+        assert_eq!(read.skip_resolve().unwrap().to_string(), "{ x = 2 }");
+        // Only the output file is left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn encoding_and_hashing_normal_forms() {
+        use crate::phase::Parsed;
+        use std::collections::HashSet;
+        let normalize = |s| {
+            let resolved =
+                Parsed::parse_str(s).unwrap().skip_resolve().unwrap();
+            resolved.typecheck().unwrap().normalize()
+        };
+        let values: HashSet<_> = vec![
+            normalize("λ(x : Natural) → x"),
+            normalize("λ(y : Natural) → y"),
+            normalize("λ(x : Natural) → 1"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(values.len(), 2);
+
+        // Resolved imports are an error rather than a panic
+        let resolved = rc(ExprF::Embed(normalize("1")));
+        assert!(super::encode(&resolved).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use dhall_syntax::Label;
+
+use crate::core::context::TypecheckContext;
+use crate::phase::Normalized;
+
+/// Variables in scope for separately parsed expressions, see
+/// `Resolved::typecheck_in`. This lets a REPL or a notebook keep the
+/// definitions of earlier inputs around for the following ones.
+///
+/// Later variables shadow earlier ones with the same name, which can still be
+/// referred to as `x@1`, etc. Cloning a context is cheap.
+#[derive(Debug, Clone)]
+pub struct Context(TypecheckContext);
+
+impl Context {
+    pub fn new() -> Self {
+        Context(TypecheckContext::new())
+    }
+
+    /// Adds a variable of type `ty` whose value is unknown. Expressions that
+    /// use it are kept abstract, like with `Resolved::typecheck_abstract`.
+    pub fn insert_type(&mut self, name: &str, ty: &Normalized) -> &mut Self {
+        self.0 = self.0.insert_type(&Label::from(name), ty.0.to_value());
+        self
+    }
+
+    /// Adds a variable bound to `value`, as if by `let name = value in`.
+    pub fn insert_value(
+        &mut self,
+        name: &str,
+        value: &Normalized,
+    ) -> &mut Self {
+        self.0 = self.0.insert_value(&Label::from(name), value.0.to_value());
+        self
+    }
+
+    pub(crate) fn as_typecheck_context(&self) -> &TypecheckContext {
+        &self.0
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+    use crate::phase::{Normalized, Parsed};
+
+    fn eval_in(ctx: &Context, s: &str) -> Result<Normalized, String> {
+        Parsed::parse_str(s)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck_in(ctx)
+            .map(|t| t.normalize())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn evaluate_in_context() {
+        let empty = Context::new();
+        let mut ctx = Context::new();
+        ctx.insert_type("n", &eval_in(&empty, "Natural").unwrap())
+            .insert_value("greeting", &eval_in(&empty, "\"hello\"").unwrap());
+        assert_eq!(
+            eval_in(&ctx, "{ n = n + 0, g = greeting ++ \"!\" }")
+                .unwrap()
+                .to_string(),
+            "{ g = \"hello!\", n = n }"
+        );
+
+        // Each input can use the definitions of the earlier ones
+        let f = eval_in(&ctx, "\\(x : Natural) -> x + 2").unwrap();
+        ctx.insert_value("f", &f);
+        assert_eq!(eval_in(&ctx, "f 1").unwrap().to_string(), "3");
+        ctx.insert_value("f", &eval_in(&ctx, "f 1").unwrap());
+        assert_eq!(eval_in(&ctx, "f@1 f").unwrap().to_string(), "5");
+
+        assert!(eval_in(&ctx, "f + m").is_err());
+        assert!(eval_in(&ctx, "greeting + 1").is_err());
+    }
+
+    #[test]
+    fn each_use_of_a_variable_has_its_own_span() {
+        // Decoded values have no span, so uses of `U` are reported where
+        // they are, and the first one must not stick to the shared value.
+        let data = eval_in(&Context::new(), "< A | B >")
+            .unwrap()
+            .encode()
+            .unwrap();
+        let union = Parsed::parse_binary(&data)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize();
+        let mut ctx = Context::new();
+        ctx.insert_value("U", &union);
+        let err = eval_in(&ctx, "let x = U in U.C").unwrap_err();
+        assert!(err.contains("(defined at 1:14)"), err);
+    }
+}
@@ -0,0 +1,493 @@
+use std::cell::Ref;
+use std::convert::TryFrom;
+
+use dhall_syntax::{Builtin, InterpolatedTextContents, Label};
+
+use crate::core::value::Value;
+use crate::core::valuef::ValueF;
+use crate::error::{ConversionError, ScalarError};
+use crate::phase::normalize::squash_textlit;
+use crate::phase::{Normalized, Typed};
+
+/// A Rust type that can be read from a Dhall value directly, without going
+/// through serde.
+///
+/// It can be derived for structs, which are read from records, and for
+/// enums whose variants have at most one field, which are read from unions.
+/// Tuple structs are read from records with fields `_1`, `_2`, etc.
+pub trait FromDhall: Sized {
+    fn from_dhall(v: &Normalized) -> Result<Self, ConversionError>;
+}
+
+/// A Rust type whose values can be turned into Dhall values of a single type
+/// directly, without going through serde. It can be derived for the same
+/// types as `FromDhall`, and gives values that `FromDhall` reads back.
+pub trait ToDhall {
+    /// The type of the values given by `to_dhall`
+    fn dhall_type() -> Normalized;
+    /// Fails for integers that do not fit in the literals of this
+    /// implementation, which are `usize` and `isize`: e.g. a `u64` above
+    /// `u32::MAX` on a 32-bit target.
+    fn to_dhall(&self) -> Result<Normalized, ConversionError>;
+}
+
+fn whnf(v: &Normalized) -> Ref<ValueF> {
+    (v.0).0.as_whnf()
+}
+
+fn from_value(v: &Value) -> Normalized {
+    Typed::from_value(v.clone()).normalize()
+}
+
+fn literal(v: ValueF, ty: Builtin) -> Normalized {
+    Typed::from_valuef_and_type(v, Typed::make_builtin_type(ty)).normalize()
+}
+
+impl Normalized {
+    fn into_value(self) -> Value {
+        self.into_typed().into_value()
+    }
+    /// `expected` is the name of a builtin type, or a description such as
+    /// "a record".
+    fn mismatch(&self, expected: &str) -> ConversionError {
+        self.conversion_error(format!("Expected {}, found {}", expected, self))
+    }
+
+    // Made public for the FromDhall and ToDhall derive macros
+    #[doc(hidden)]
+    pub fn conversion_error(&self, message: String) -> ConversionError {
+        ConversionError {
+            message,
+            span: self.source_span(),
+        }
+    }
+    #[doc(hidden)]
+    pub fn expect_record(&self) -> Result<(), ConversionError> {
+        match &*whnf(self) {
+            ValueF::RecordLit(_) => Ok(()),
+            _ => Err(self.mismatch("a record")),
+        }
+    }
+    #[doc(hidden)]
+    pub fn record_field(
+        &self,
+        name: &str,
+    ) -> Result<Normalized, ConversionError> {
+        match &*whnf(self) {
+            ValueF::RecordLit(kvs) => {
+                match kvs.get(&Label::from(name)) {
+                    Some(v) => Ok(from_value(v)),
+                    None => Err(self
+                        .conversion_error(format!("Missing field `{}`", name))),
+                }
+            }
+            _ => Err(self.mismatch("a record")),
+        }
+    }
+    #[doc(hidden)]
+    pub fn union_alternative(
+        &self,
+    ) -> Result<(String, Option<Normalized>), ConversionError> {
+        match self.as_union() {
+            Some((l, payload)) => Ok((String::from(&l), payload)),
+            None => Err(self.mismatch("a union value")),
+        }
+    }
+    #[doc(hidden)]
+    pub fn make_record(kvs: Vec<(&str, Normalized)>) -> Normalized {
+        let kts = kvs
+            .iter()
+            .map(|(k, v)| (Label::from(*k), v.0.get_type().unwrap().to_value()))
+            .collect();
+        let kvs = kvs
+            .into_iter()
+            .map(|(k, v)| (Label::from(k), v.into_value()))
+            .collect();
+        Typed::from_valuef_and_type(
+            ValueF::RecordLit(kvs),
+            Typed::from_valuef_and_type(
+                ValueF::RecordType(kts),
+                Typed::const_type(),
+            ),
+        )
+        .normalize()
+    }
+    #[doc(hidden)]
+    pub fn make_record_type(kts: Vec<(&str, Normalized)>) -> Normalized {
+        Typed::make_record_type(
+            kts.into_iter().map(|(k, t)| (k.to_owned(), t.into_typed())),
+        )
+        .normalize()
+    }
+    #[doc(hidden)]
+    pub fn make_union_type(kts: Vec<(&str, Option<Normalized>)>) -> Normalized {
+        Typed::make_union_type(
+            kts.into_iter()
+                .map(|(k, t)| (k.to_owned(), t.map(Normalized::into_typed))),
+        )
+        .normalize()
+    }
+    /// Makes a value of the union type `ty` without typechecking it, unlike
+    /// `make_union_value`. `ty` must have the given alternative, with a
+    /// payload of the right type if there is one.
+    #[doc(hidden)]
+    pub fn make_union_alternative(
+        ty: &Normalized,
+        alternative: &str,
+        payload: Option<Normalized>,
+    ) -> Normalized {
+        let kts = match &*whnf(ty) {
+            ValueF::UnionType(kts) => kts.clone(),
+            _ => panic!("Expected a union type, found {}", ty),
+        };
+        let l = Label::from(alternative);
+        let v = match payload {
+            Some(v) => ValueF::UnionLit(l, v.into_value(), kts),
+            None => ValueF::UnionConstructor(l, kts),
+        };
+        Typed::from_valuef_and_type(v, ty.clone().into_typed()).normalize()
+    }
+}
+
+impl Typed {
+    /// Reads a `Natural` literal, for quick extractions that don't need
+    /// `FromDhall`.
+    pub fn as_natural(&self) -> Result<u64, ScalarError> {
+        match &*self.0.as_whnf() {
+            ValueF::NaturalLit(n) => Ok(*n as u64),
+            _ => Err(self.scalar_error(Builtin::Natural)),
+        }
+    }
+    /// Reads a `Bool` literal.
+    pub fn as_bool(&self) -> Result<bool, ScalarError> {
+        match &*self.0.as_whnf() {
+            ValueF::BoolLit(b) => Ok(*b),
+            _ => Err(self.scalar_error(Builtin::Bool)),
+        }
+    }
+    /// Reads a `Text` literal, which must not have interpolations left.
+    pub fn as_text(&self) -> Result<String, ScalarError> {
+        match &*self.0.as_whnf() {
+            ValueF::TextLit(elts) => elts
+                .iter()
+                .map(|contents| match contents {
+                    InterpolatedTextContents::Text(s) => Ok(s.as_str()),
+                    InterpolatedTextContents::Expr(_) => {
+                        Err(self.scalar_error(Builtin::Text))
+                    }
+                })
+                .collect(),
+            _ => Err(self.scalar_error(Builtin::Text)),
+        }
+    }
+
+    fn scalar_error(&self, expected: Builtin) -> ScalarError {
+        match self.get_type() {
+            Ok(ty) if ty == Typed::make_builtin_type(expected) => {
+                ScalarError::NotALiteral {
+                    expected,
+                    value: self.normalize_to_expr().to_string(),
+                }
+            }
+            ty => ScalarError::WrongType {
+                expected,
+                found: ty.ok().map(|ty| ty.normalize_to_expr().to_string()),
+            },
+        }
+    }
+}
+
+impl FromDhall for Normalized {
+    fn from_dhall(v: &Normalized) -> Result<Self, ConversionError> {
+        Ok(v.clone())
+    }
+}
+
+impl FromDhall for bool {
+    fn from_dhall(v: &Normalized) -> Result<Self, ConversionError> {
+        match &*whnf(v) {
+            ValueF::BoolLit(b) => Ok(*b),
+            _ => Err(v.mismatch("Bool")),
+        }
+    }
+}
+
+impl ToDhall for bool {
+    fn dhall_type() -> Normalized {
+        Typed::make_builtin_type(Builtin::Bool).normalize()
+    }
+    fn to_dhall(&self) -> Result<Normalized, ConversionError> {
+        Ok(literal(ValueF::BoolLit(*self), Builtin::Bool))
+    }
+}
+
+macro_rules! number_conversions {
+    ($builtin:ident, $lit:ident, $($ty:ty),*) => {
+        $(
+            impl FromDhall for $ty {
+                fn from_dhall(v: &Normalized) -> Result<Self, ConversionError> {
+                    match &*whnf(v) {
+                        ValueF::$lit(n) => <$ty>::try_from(*n).map_err(|_| {
+                            v.conversion_error(format!(
+                                "{} is out of the range of {}",
+                                n,
+                                stringify!($ty)
+                            ))
+                        }),
+                        _ => Err(v.mismatch(stringify!($builtin))),
+                    }
+                }
+            }
+
+            impl ToDhall for $ty {
+                fn dhall_type() -> Normalized {
+                    Typed::make_builtin_type(Builtin::$builtin).normalize()
+                }
+                fn to_dhall(&self) -> Result<Normalized, ConversionError> {
+                    let n = TryFrom::try_from(*self).map_err(|_| {
+                        ConversionError {
+                            message: format!(
+                                "{} is out of the range of {} on this target",
+                                self,
+                                stringify!($builtin)
+                            ),
+                            span: None,
+                        }
+                    })?;
+                    Ok(literal(ValueF::$lit(n), Builtin::$builtin))
+                }
+            }
+        )*
+    };
+}
+
+number_conversions!(Natural, NaturalLit, u8, u16, u32, u64, usize);
+number_conversions!(Integer, IntegerLit, i8, i16, i32, i64, isize);
+
+impl FromDhall for f64 {
+    fn from_dhall(v: &Normalized) -> Result<Self, ConversionError> {
+        match &*whnf(v) {
+            ValueF::DoubleLit(n) => Ok((*n).into()),
+            _ => Err(v.mismatch("Double")),
+        }
+    }
+}
+
+impl ToDhall for f64 {
+    fn dhall_type() -> Normalized {
+        Typed::make_builtin_type(Builtin::Double).normalize()
+    }
+    fn to_dhall(&self) -> Result<Normalized, ConversionError> {
+        Ok(literal(ValueF::DoubleLit((*self).into()), Builtin::Double))
+    }
+}
+
+impl FromDhall for String {
+    fn from_dhall(v: &Normalized) -> Result<Self, ConversionError> {
+        match &*whnf(v) {
+            ValueF::TextLit(elts) => elts
+                .iter()
+                .map(|contents| match contents {
+                    InterpolatedTextContents::Text(s) => Ok(s.as_str()),
+                    InterpolatedTextContents::Expr(_) => {
+                        Err(v.mismatch("a Text literal"))
+                    }
+                })
+                .collect(),
+            _ => Err(v.mismatch("Text")),
+        }
+    }
+}
+
+impl ToDhall for String {
+    fn dhall_type() -> Normalized {
+        Typed::make_builtin_type(Builtin::Text).normalize()
+    }
+    fn to_dhall(&self) -> Result<Normalized, ConversionError> {
+        let text = InterpolatedTextContents::Text(self.clone());
+        Ok(literal(
+            ValueF::TextLit(squash_textlit(vec![text])),
+            Builtin::Text,
+        ))
+    }
+}
+
+impl<T: FromDhall> FromDhall for Option<T> {
+    fn from_dhall(v: &Normalized) -> Result<Self, ConversionError> {
+        match &*whnf(v) {
+            ValueF::EmptyOptionalLit(_) => Ok(None),
+            ValueF::NEOptionalLit(x) => {
+                Ok(Some(T::from_dhall(&from_value(x))?))
+            }
+            _ => Err(v.mismatch("Optional")),
+        }
+    }
+}
+
+impl<T: ToDhall> ToDhall for Option<T> {
+    fn dhall_type() -> Normalized {
+        Typed::make_optional_type(T::dhall_type().into_typed()).normalize()
+    }
+    fn to_dhall(&self) -> Result<Normalized, ConversionError> {
+        let v = match self {
+            None => ValueF::EmptyOptionalLit(T::dhall_type().into_value()),
+            Some(x) => ValueF::NEOptionalLit(x.to_dhall()?.into_value()),
+        };
+        Ok(
+            Typed::from_valuef_and_type(v, Self::dhall_type().into_typed())
+                .normalize(),
+        )
+    }
+}
+
+impl<T: FromDhall> FromDhall for Vec<T> {
+    fn from_dhall(v: &Normalized) -> Result<Self, ConversionError> {
+        match &*whnf(v) {
+            ValueF::EmptyListLit(_) => Ok(Vec::new()),
+            ValueF::NEListLit(xs) => {
+                xs.iter().map(|x| T::from_dhall(&from_value(x))).collect()
+            }
+            _ => Err(v.mismatch("List")),
+        }
+    }
+}
+
+impl<T: ToDhall> ToDhall for Vec<T> {
+    fn dhall_type() -> Normalized {
+        Typed::make_list_type(T::dhall_type().into_typed()).normalize()
+    }
+    fn to_dhall(&self) -> Result<Normalized, ConversionError> {
+        let v = if self.is_empty() {
+            ValueF::EmptyListLit(T::dhall_type().into_value())
+        } else {
+            ValueF::NEListLit(
+                self.iter()
+                    .map(|x| Ok(x.to_dhall()?.into_value()))
+                    .collect::<Result<_, ConversionError>>()?,
+            )
+        };
+        Ok(
+            Typed::from_valuef_and_type(v, Self::dhall_type().into_typed())
+                .normalize(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dhall_syntax::Builtin;
+
+    use crate::error::ScalarError;
+    use crate::phase::{Context, FromDhall, Normalized, Parsed, ToDhall};
+
+    #[derive(Debug, PartialEq, FromDhall, ToDhall)]
+    enum Level {
+        Quiet,
+        Verbose(u8),
+    }
+
+    #[derive(Debug, PartialEq, FromDhall, ToDhall)]
+    struct Address(String, u16);
+
+    #[derive(Debug, PartialEq, FromDhall, ToDhall)]
+    struct Server {
+        name: String,
+        port: u16,
+        tags: Vec<String>,
+        level: Level,
+        backup: Option<Address>,
+    }
+
+    fn eval(s: &str) -> Normalized {
+        Parsed::parse_str(s)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize()
+    }
+
+    #[test]
+    fn derived_conversions() {
+        let src = "{ name = \"web\", port = 8000 + 80, tags = [] : List Text, \
+                   level = < Quiet | Verbose : Natural >.Verbose 2, \
+                   backup = Some { _1 = \"db\", _2 = 5432 } }";
+        let server = Server::from_dhall(&eval(src)).unwrap();
+        assert_eq!(
+            server,
+            Server {
+                name: "web".to_owned(),
+                port: 8080,
+                tags: vec![],
+                level: Level::Verbose(2),
+                backup: Some(Address("db".to_owned(), 5432)),
+            }
+        );
+        assert_eq!(server.to_dhall().unwrap(), eval(src));
+        assert_eq!(
+            Server::dhall_type().to_string(),
+            "{ backup : Optional { _1 : Text, _2 : Natural }, \
+             level : < Quiet | Verbose: Natural >, name : Text, \
+             port : Natural, tags : List Text }"
+        );
+        // The values are well-typed
+        let v = server.to_dhall().unwrap();
+        assert_eq!(eval(&v.to_string()), v);
+
+        let err = Server::from_dhall(&eval(
+            "{ name = \"web\", port = 80000, tags = [ \"a\" ], \
+             level = < Quiet | Verbose : Natural >.Quiet, backup = None {} }",
+        ))
+        .unwrap_err();
+        assert_eq!(err.message, "80000 is out of the range of u16");
+        let err =
+            Level::from_dhall(&eval("< Quiet | Loud >.Loud")).unwrap_err();
+        assert_eq!(err.message, "Unexpected alternative `Loud`");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!((-128i8).to_dhall().unwrap(), eval("-128"));
+        assert_eq!(
+            i8::from_dhall(&(-128i8).to_dhall().unwrap()).unwrap(),
+            -128
+        );
+        // Fits in a `usize` on every target
+        let max = u64::from(u32::max_value());
+        assert_eq!(u64::from_dhall(&max.to_dhall().unwrap()).unwrap(), max);
+    }
+
+    #[test]
+    fn scalars() {
+        let typed = |s: &str| eval(s).into_typed();
+        assert_eq!(typed("2 + 3").as_natural(), Ok(5));
+        assert_eq!(typed("Natural/even 3").as_bool(), Ok(false));
+        assert_eq!(typed("\"a${\"b\"}c\"").as_text(), Ok("abc".to_owned()));
+
+        assert_eq!(
+            typed("[ 1 ]").as_natural(),
+            Err(ScalarError::WrongType {
+                expected: Builtin::Natural,
+                found: Some("List Natural".to_owned()),
+            })
+        );
+        assert_eq!(
+            typed("Kind").get_type().unwrap().as_bool(),
+            Err(ScalarError::WrongType {
+                expected: Builtin::Bool,
+                found: None,
+            })
+        );
+        let abstract_text = Parsed::parse_str("\"${x}!\"")
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck_in(Context::new().insert_type("x", &eval("Text")))
+            .unwrap();
+        assert_eq!(
+            abstract_text.as_text().unwrap_err().to_string(),
+            "Expected a Text literal, found `\"${ x }!\"`"
+        );
+    }
+}
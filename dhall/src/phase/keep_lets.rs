@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use dhall_syntax::{ExprF, Label};
+
+use crate::core::value::Value;
+use crate::error::TypeError;
+use crate::phase::typecheck::{typecheck, typecheck_abstract};
+use crate::phase::{NormalizedExpr, ResolvedExpr, Typed};
+
+/// A top-level `let` binding.
+struct Binding<'a> {
+    label: &'a Label,
+    annot: Option<&'a ResolvedExpr>,
+    value: &'a ResolvedExpr,
+    /// The type of the value, if the binding is kept abstract when
+    /// normalizing the rest of the expression. Bindings of types are
+    /// substituted instead, as typechecking may need to see through them.
+    abstract_type: Option<Value>,
+}
+
+/// Wraps `e` in the given bindings, outermost first.
+fn wrap<'a>(
+    bindings: impl DoubleEndedIterator<Item = &'a Binding<'a>>,
+    e: &ResolvedExpr,
+) -> ResolvedExpr {
+    bindings.rev().fold(e.clone(), |body, b| {
+        e.rewrap(ExprF::Let(
+            b.label.clone(),
+            b.annot.cloned(),
+            b.value.clone(),
+            body,
+        ))
+    })
+}
+
+/// Typechecks `e` in the scope of the previous bindings, keeping abstract the
+/// ones that can be. Since the whole expression typechecks, substituting all
+/// of them is a fallback that always works, e.g. when a type depends on the
+/// value of a binding.
+fn check_in_scope(
+    bindings: &[Binding],
+    e: &ResolvedExpr,
+) -> Result<Value, TypeError> {
+    let labels: HashSet<_> = bindings.iter().map(|b| b.label).collect();
+    // With distinct names, moving the substituted bindings inside the
+    // abstract ones doesn't change what variables refer to.
+    if labels.len() == bindings.len() {
+        let inputs: Vec<_> = bindings
+            .iter()
+            .filter_map(|b| Some((b.label.clone(), b.abstract_type.clone()?)))
+            .collect();
+        let substituted = bindings.iter().filter(|b| b.abstract_type.is_none());
+        if let Ok(v) = typecheck_abstract(wrap(substituted, e), &inputs) {
+            return Ok(v);
+        }
+    }
+    typecheck(wrap(bindings.iter(), e))
+}
+
+fn normalize_value(v: Value) -> NormalizedExpr {
+    Typed::from_value(v).normalize().to_expr()
+}
+
+/// Normalizes the bound values and the body of the top-level `let`s of
+/// `expr`, but keeps the bindings instead of inlining them. Annotations are
+/// kept as written, since they document the bindings: they can only refer to
+/// earlier bindings, which are all kept.
+pub(crate) fn normalize_keeping_lets(
+    expr: ResolvedExpr,
+) -> Result<NormalizedExpr, TypeError> {
+    typecheck(expr.clone())?;
+
+    let mut bindings = Vec::new();
+    let mut kept = Vec::new();
+    let mut e = &expr;
+    while let ExprF::Let(label, annot, value, body) = e.as_ref() {
+        let v = check_in_scope(&bindings, value)?;
+        let is_type = v.get_type()?.as_const().is_some();
+        bindings.push(Binding {
+            label,
+            annot: annot.as_ref(),
+            value,
+            abstract_type: if is_type { None } else { Some(v.get_type()?) },
+        });
+        kept.push((e, label.clone(), annot.clone(), normalize_value(v)));
+        e = body;
+    }
+
+    let body = normalize_value(check_in_scope(&bindings, e)?);
+    Ok(kept.into_iter().rev().fold(body, |body, (e, x, t, v)| {
+        e.rewrap(ExprF::Let(x, t, v, body))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::phase::Parsed;
+
+    fn keep_lets(s: &str) -> String {
+        let resolved = Parsed::parse_str(s).unwrap().skip_resolve().unwrap();
+        resolved.normalize_keeping_lets().unwrap().to_string()
+    }
+
+    #[test]
+    fn normalize_keeping_lets() {
+        assert_eq!(
+            keep_lets(
+                "let n = 1 + 1
+                 let T = { a : Natural }
+                 let r : T = { a = n }
+                 in  { r = r, t = 2 + 3 }"
+            ),
+            "let n = 2 let T = { a : Natural } \
+             let r : T = { a = n } in { r = r, t = 5 }"
+        );
+        assert_eq!(
+            keep_lets(
+                "let f : forall (a : Type) -> a -> a
+                       = \\(a : Type) -> \\(x : a) -> x
+                 let n : Natural = f Natural 1 in n"
+            ),
+            "let f : ∀(a : Type) → a → a = λ(a : Type) → λ(x : a) → x \
+             let n : Natural = (f Natural) 1 in n"
+        );
+        // Shadowed names and types that depend on values are inlined
+        assert_eq!(
+            keep_lets("let x = 1 let x = x + 1 in x"),
+            "let x = 1 let x = x + 1 in 2"
+        );
+        assert_eq!(
+            keep_lets("let n = 2 let p = assert : n ≡ 2 in n"),
+            "let n = 2 let p = assert : 2 ≡ 2 in n"
+        );
+    }
+}
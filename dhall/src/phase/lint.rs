@@ -0,0 +1,148 @@
+use dhall_syntax::{
+    Builtin, ExprF, Import, ImportLocation, Label, Scheme, Span, V,
+};
+
+use crate::error::{Warning, WarningKind};
+use crate::phase::ParsedExpr;
+
+/// Collects the warnings that can be found by looking at the syntax alone.
+pub(crate) fn lint(expr: &ParsedExpr) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    lint_expr(expr, &mut Vec::new(), &mut warnings);
+    warnings
+}
+
+/// `scope` holds the variables bound by the enclosing `let`s and lambdas,
+/// along with where they were bound.
+fn lint_expr(
+    expr: &ParsedExpr,
+    scope: &mut Vec<(Label, Option<Span>)>,
+    out: &mut Vec<Warning>,
+) {
+    let span = expr.span();
+    let mut warn = |kind, related_span| {
+        out.push(Warning {
+            kind,
+            span: span.cloned(),
+            related_span,
+        })
+    };
+    let binder = match expr.as_ref() {
+        ExprF::Builtin(b @ Builtin::OptionalFold)
+        | ExprF::Builtin(b @ Builtin::OptionalBuild) => {
+            warn(WarningKind::DeprecatedBuiltin(*b), None);
+            None
+        }
+        ExprF::Let(x, _, v, body) => {
+            // Libraries name their tests `let example0 = assert : ...` and
+            // never refer to them.
+            let is_assert = match v.as_ref() {
+                ExprF::Assert(_) => true,
+                _ => false,
+            };
+            if x != &Label::from("_")
+                && !is_assert
+                && !body.free_vars().contains(&V(x.clone(), 0))
+            {
+                warn(WarningKind::UnusedLet(x.clone()), None)
+            }
+            Some((x, body))
+        }
+        ExprF::Lam(x, _, body) => Some((x, body)),
+        ExprF::Import(import) => {
+            if let Some(url) = insecure_url(import) {
+                warn(WarningKind::InsecureImport(url), None)
+            }
+            None
+        }
+        _ => None,
+    };
+    match binder {
+        Some((x, body)) => {
+            if x != &Label::from("_") {
+                if let Some((_, previous)) =
+                    scope.iter().rev().find(|(y, _)| y == x)
+                {
+                    warn(
+                        WarningKind::ShadowedBinding(x.clone()),
+                        previous.clone(),
+                    )
+                }
+            }
+            // The body is in the scope of the binding, the other
+            // subexpressions aren't.
+            expr.as_ref().map_ref(|e| {
+                if !std::ptr::eq(e, body) {
+                    lint_expr(e, scope, out)
+                }
+            });
+            scope.push((x.clone(), span.cloned()));
+            lint_expr(body, scope, out);
+            scope.pop();
+        }
+        None => {
+            expr.as_ref().map_ref(|e| lint_expr(e, scope, out));
+        }
+    }
+}
+
+fn insecure_url(import: &Import<ParsedExpr>) -> Option<String> {
+    match &import.location {
+        ImportLocation::Remote(url) if url.scheme == Scheme::HTTP => {
+            let mut s = format!("http://{}", url.authority);
+            for segment in &url.path {
+                s.push('/');
+                s.push_str(segment);
+            }
+            Some(s)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::WarningKind;
+    use crate::phase::Parsed;
+    use dhall_syntax::Builtin;
+
+    #[test]
+    fn lint_parsed_expression() {
+        let parsed = Parsed::parse_str(
+            "let x = 1 let y = 2 let _ = 3 let t = assert : y === 2 in \
+             { a = y, b = Optional/fold, c = http://example.com/a.dhall }",
+        )
+        .unwrap();
+        let kinds: Vec<_> =
+            parsed.warnings().into_iter().map(|w| w.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                WarningKind::UnusedLet("x".into()),
+                WarningKind::DeprecatedBuiltin(Builtin::OptionalFold),
+                WarningKind::InsecureImport(
+                    "http://example.com/a.dhall".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_shadowed_bindings() {
+        let parsed =
+            Parsed::parse_str("let x = 1 in \\(x : Natural) -> let x = x in x")
+                .unwrap();
+        let shadowed: Vec<_> = parsed
+            .warnings()
+            .into_iter()
+            .filter_map(|w| match w.kind {
+                WarningKind::ShadowedBinding(_) => Some((
+                    w.span.unwrap().start(),
+                    w.related_span.unwrap().start(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shadowed, vec![(13, 0), (31, 13)]);
+    }
+}
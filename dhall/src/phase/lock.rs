@@ -0,0 +1,232 @@
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dhall_syntax::{Hash, ImportMode};
+
+use crate::error::{DecodeError, Error};
+use crate::phase::{Parsed, ResolutionReport};
+
+/// An import recorded in a lock file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockEntry {
+    /// The canonical absolute location of the import
+    pub location: String,
+    pub mode: ImportMode,
+    /// The semantic hash of what the import resolved to
+    pub hash: Hash,
+    /// When the import was resolved, in seconds since the Unix epoch
+    pub resolved_at: u64,
+}
+
+/// The transitive imports of an expression along with the semantic hash of
+/// what they resolved to, so that changes to any of them can be detected.
+///
+/// A lock file is printed with one `<mode> sha256:<hex> <timestamp>
+/// <location>` line per import, sorted by location.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lockfile {
+    entries: Vec<LockEntry>,
+}
+
+/// A difference between a lock file and what the imports currently resolve
+/// to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockMismatch {
+    /// The import now resolves to a different expression
+    Changed {
+        location: String,
+        mode: ImportMode,
+        locked: Hash,
+        found: Hash,
+    },
+    /// The import is not in the lock file
+    Unlocked {
+        location: String,
+        mode: ImportMode,
+        hash: Hash,
+    },
+    /// The import is in the lock file but is no longer used
+    Unused { location: String, mode: ImportMode },
+}
+
+impl Lockfile {
+    /// Resolves the expression and locks all of its transitive imports.
+    pub fn generate(parsed: Parsed) -> Result<Lockfile, Error> {
+        let (_, report) = parsed.resolve_with_report()?;
+        Lockfile::from_report(&report)
+    }
+
+    /// Locks the imports that were resolved in the given report.
+    pub fn from_report(report: &ResolutionReport) -> Result<Lockfile, Error> {
+        let resolved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut entries = BTreeMap::new();
+        for import in &report.imports {
+            let key = (import.location.clone(), mode_name(import.mode));
+            if let Entry::Vacant(entry) = entries.entry(key) {
+                entry.insert(LockEntry {
+                    location: import.location.clone(),
+                    mode: import.mode,
+                    hash: import.expr.semantic_hash()?,
+                    resolved_at,
+                });
+            }
+        }
+        Ok(Lockfile {
+            entries: entries.into_iter().map(|(_, e)| e).collect(),
+        })
+    }
+
+    pub fn entries(&self) -> &[LockEntry] {
+        &self.entries
+    }
+
+    /// Resolves the expression again and lists how its imports differ from
+    /// the lock file. An empty list means the lock file is up to date.
+    pub fn check(&self, parsed: Parsed) -> Result<Vec<LockMismatch>, Error> {
+        let current = Lockfile::generate(parsed)?;
+        let key = |e: &LockEntry| (e.location.clone(), mode_name(e.mode));
+        let locked: BTreeMap<_, _> =
+            self.entries.iter().map(|e| (key(e), e)).collect();
+        let mut mismatches = Vec::new();
+        for entry in &current.entries {
+            match locked.get(&key(entry)) {
+                Some(l) if l.hash == entry.hash => {}
+                Some(l) => mismatches.push(LockMismatch::Changed {
+                    location: entry.location.clone(),
+                    mode: entry.mode,
+                    locked: l.hash.clone(),
+                    found: entry.hash.clone(),
+                }),
+                None => mismatches.push(LockMismatch::Unlocked {
+                    location: entry.location.clone(),
+                    mode: entry.mode,
+                    hash: entry.hash.clone(),
+                }),
+            }
+        }
+        let used: BTreeSet<_> = current.entries.iter().map(key).collect();
+        for entry in &self.entries {
+            if !used.contains(&key(entry)) {
+                mismatches.push(LockMismatch::Unused {
+                    location: entry.location.clone(),
+                    mode: entry.mode,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+
+    pub fn read(path: &Path) -> Result<Lockfile, Error> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        contents.parse()
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        File::create(path)?.write_all(self.to_string().as_bytes())?;
+        Ok(())
+    }
+}
+
+fn mode_name(mode: ImportMode) -> &'static str {
+    match mode {
+        ImportMode::Code => "code",
+        ImportMode::RawText => "text",
+        ImportMode::Location => "location",
+    }
+}
+
+fn mode_from_name(name: &str) -> Option<ImportMode> {
+    match name {
+        "code" => Some(ImportMode::Code),
+        "text" => Some(ImportMode::RawText),
+        "location" => Some(ImportMode::Location),
+        _ => None,
+    }
+}
+
+impl std::fmt::Display for Lockfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for e in &self.entries {
+            writeln!(
+                f,
+                "{} {} {} {}",
+                mode_name(e.mode),
+                e.hash,
+                e.resolved_at,
+                e.location
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Lockfile {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |msg: &str, line: &str| {
+            DecodeError::WrongFormatError(format!("lockfile/{}: {}", msg, line))
+        };
+        let mut entries = Vec::new();
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let mut parts = line.splitn(4, ' ');
+            let mut next = || parts.next().ok_or_else(|| err("short", line));
+            let mode = mode_from_name(next()?)
+                .ok_or_else(|| err("unknown_mode", line))?;
+            let hash =
+                next()?.parse().map_err(|e| err(&format!("{}", e), line))?;
+            let resolved_at = next()?
+                .parse()
+                .map_err(|_| err("invalid_timestamp", line))?;
+            let location = next()?.to_owned();
+            entries.push(LockEntry {
+                location,
+                mode,
+                hash,
+                resolved_at,
+            });
+        }
+        Ok(Lockfile { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{LockMismatch, Lockfile};
+    use crate::phase::Parsed;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn lock_and_check_imports() {
+        let dir = TempDir::new();
+        fs::write(dir.join("a.dhall"), "./b.dhall + 1").unwrap();
+        fs::write(dir.join("b.dhall"), "2").unwrap();
+        let top = dir.join("top.dhall");
+        fs::write(&top, "./a.dhall + ./b.dhall").unwrap();
+        let parse = || Parsed::parse_file(&top).unwrap();
+
+        let lock = Lockfile::generate(parse()).unwrap();
+        assert_eq!(lock.entries().len(), 2);
+        let lock: Lockfile = lock.to_string().parse().unwrap();
+        assert_eq!(lock.check(parse()).unwrap(), vec![]);
+
+        fs::write(dir.join("b.dhall"), "3").unwrap();
+        let mismatches = lock.check(parse()).unwrap();
+        assert_eq!(mismatches.len(), 2);
+        for m in mismatches {
+            match m {
+                LockMismatch::Changed { .. } => {}
+                m => panic!("Expected a changed import, got: {:?}", m),
+            }
+        }
+    }
+}
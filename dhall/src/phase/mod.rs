@@ -1,20 +1,59 @@
+use std::collections::BTreeSet;
 use std::fmt::Display;
 use std::path::Path;
 
-use dhall_syntax::{Builtin, Const, Expr};
+use dhall_syntax::{
+    Builtin, Const, Expr, ExprF, Hash, InterpolatedTextContents, Label,
+    PrintOptions, Span, StandardVersion, V,
+};
 
 use crate::core::value::{ToExprOptions, Value};
 use crate::core::valuef::ValueF;
 use crate::core::var::{AlphaVar, Shift, Subst};
-use crate::error::{EncodeError, Error, ImportError, TypeError};
+use crate::error::{
+    EncodeError, Error, ImportError, PhaseError, SchemaError, SelectError,
+    TypeError, Warning,
+};
 
 use resolve::ImportRoot;
 
-pub(crate) mod binary;
+pub mod binary;
+pub(crate) mod context;
+pub(crate) mod convert;
+pub(crate) mod keep_lets;
+pub(crate) mod lint;
+pub(crate) mod lock;
 pub(crate) mod normalize;
+pub(crate) mod options;
+pub(crate) mod overlay;
 pub(crate) mod parse;
+pub(crate) mod pipeline;
+pub(crate) mod recording;
 pub(crate) mod resolve;
+pub(crate) mod rewrite;
+pub(crate) mod schema;
+pub(crate) mod store;
 pub(crate) mod typecheck;
+pub(crate) mod validate;
+
+pub use context::Context;
+pub use convert::{FromDhall, ToDhall};
+#[doc(hidden)]
+pub use dhall_proc_macros::{FromDhall, ToDhall};
+pub use lock::{LockEntry, LockMismatch, Lockfile};
+pub use options::EvalOptions;
+pub use pipeline::{eval_file_with_report, PhaseReport, PipelineReport};
+pub use recording::{Recording, RecordingMode};
+pub use resolve::{
+    ImportAlternative, ImportCache, ResolutionLimits, ResolutionReport,
+    ResolvedImport,
+};
+pub use rewrite::{Node, Rewriter};
+pub use schema::SchemaField;
+pub use store::Store;
+pub use validate::{
+    eval_files_sequentially, FileReport, ValidationReport, Validator,
+};
 
 pub type ParsedExpr = Expr<Normalized>;
 pub type DecodedExpr = Expr<Normalized>;
@@ -40,6 +79,27 @@ pub struct Typed(Value);
 #[derive(Debug, Clone)]
 pub struct Normalized(Typed);
 
+/// Size measurements of a normalized expression, see `Normalized::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExprStats {
+    /// Number of nodes in the syntax tree
+    pub nodes: usize,
+    /// Length of the longest path from the root to a leaf, the root alone
+    /// having depth 1
+    pub max_depth: usize,
+    /// Total size in bytes of the text literals, excluding interpolations
+    pub text_bytes: usize,
+    /// Total number of list elements
+    pub list_elements: usize,
+    /// Number of elements of the longest list
+    pub max_list_len: usize,
+}
+
+/// An iterator over the elements of a list that normalizes each element only
+/// when it is reached, see `Typed::list_elements`.
+#[derive(Debug, Clone)]
+pub struct ListElements(std::vec::IntoIter<Value>);
+
 impl Parsed {
     pub fn parse_file(f: &Path) -> Result<Parsed, Error> {
         parse::parse_file(f)
@@ -47,6 +107,11 @@ impl Parsed {
     pub fn parse_str(s: &str) -> Result<Parsed, Error> {
         parse::parse_str(s)
     }
+    /// Like `parse_str`, but names the source so that errors and spans can
+    /// refer to it, e.g. by the file the text was read from.
+    pub fn parse_str_with_name(name: &str, s: &str) -> Result<Parsed, Error> {
+        parse::parse_str_with_name(name, s)
+    }
     pub fn parse_binary_file(f: &Path) -> Result<Parsed, Error> {
         parse::parse_binary_file(f)
     }
@@ -54,26 +119,213 @@ impl Parsed {
         parse::parse_binary(data)
     }
 
+    /// Fails if the expression uses a feature that is not available in the
+    /// given version of the standard. Imported files are not checked.
+    pub fn check_standard_version(
+        &self,
+        version: StandardVersion,
+    ) -> Result<(), Error> {
+        match self.0.first_unavailable_feature(version) {
+            Some(feature) => Err(Error::UnsupportedFeature(feature, version)),
+            None => Ok(()),
+        }
+    }
+
+    /// Problems found in the expression that don't prevent evaluating it,
+    /// such as unused bindings or deprecated builtins. Warnings in imported
+    /// files are in the `ResolutionReport`.
+    pub fn warnings(&self) -> Vec<Warning> {
+        lint::lint(&self.0)
+    }
+
     pub fn resolve(self) -> Result<Resolved, ImportError> {
         resolve::resolve(self)
     }
+    /// Like `resolve`, but gives back the unresolved expression on failure.
+    pub fn try_resolve(
+        self,
+    ) -> Result<Resolved, PhaseError<Parsed, ImportError>> {
+        resolve::resolve(self.clone())
+            .map_err(|error| PhaseError { input: self, error })
+    }
+    /// Resolve without performing any I/O.
+    ///
+    /// Fails on the first import encountered, unless it is part of an `x ? y`
+    /// chain with an import-free alternative, in which case that alternative
+    /// is used.
     pub fn skip_resolve(self) -> Result<Resolved, ImportError> {
         resolve::skip_resolve_expr(self)
     }
+    /// Resolve imports, and report which operand of each `x ? y` was used.
+    pub fn resolve_with_report(
+        self,
+    ) -> Result<(Resolved, ResolutionReport), ImportError> {
+        resolve::resolve_with_report(self)
+    }
+    /// Resolve imports, reusing and filling the given cache.
+    pub fn resolve_with_cache(
+        self,
+        cache: &mut ImportCache,
+    ) -> Result<Resolved, ImportError> {
+        resolve::resolve_with_cache(self, cache)
+    }
+    /// Resolve imports, failing if they go over the given limits.
+    pub fn resolve_with_limits(
+        self,
+        limits: &ResolutionLimits,
+    ) -> Result<Resolved, ImportError> {
+        resolve::resolve_with_limits(self, limits)
+    }
+    /// Resolve imports, looking up hashed imports in the given store before
+    /// fetching them.
+    pub fn resolve_with_store(
+        self,
+        store: &Store,
+    ) -> Result<Resolved, ImportError> {
+        Ok(resolve::resolve_with_store(self, store)?.0)
+    }
+    /// Like `resolve_with_store`, and also report what happened. Corrupt
+    /// entries of the store are ignored with a warning in the report.
+    pub fn resolve_with_store_and_report(
+        self,
+        store: &Store,
+    ) -> Result<(Resolved, ResolutionReport), ImportError> {
+        resolve::resolve_with_store(self, store)
+    }
+    /// Resolve imports, replaying them from the given recording or recording
+    /// them according to its mode. Only local files are recorded, see
+    /// `Recording`.
+    pub fn resolve_with_recording(
+        self,
+        recording: &Recording,
+    ) -> Result<Resolved, ImportError> {
+        resolve::resolve_with_recording(self, recording)
+    }
 
     pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         crate::phase::binary::encode(&self.0)
     }
+    /// Writes the binary encoding to a file, typically a `.dhallb`. The file
+    /// is replaced atomically.
+    pub fn to_binary_file(&self, path: &Path) -> Result<(), Error> {
+        binary::write_atomically(path, &self.encode()?)?;
+        Ok(())
+    }
+    /// A hash of the expression that can be computed without fetching
+    /// anything: imports are identified by their declared `sha256:...` hash
+    /// only, so moving a protected import elsewhere doesn't change it. Fails
+    /// if an import has no hash.
+    ///
+    /// The expression is not normalized, so unlike `Normalized::semantic_hash`
+    /// this only ignores formatting and comments. It is meant for cheap change
+    /// detection, e.g. to check a lock file offline.
+    pub fn offline_hash(&self) -> Result<Hash, Error> {
+        let expr = resolve::opaque_imports(&self.0)?;
+        Ok(binary::hash_bytes(&binary::encode(&expr)?))
+    }
 }
 
 impl Resolved {
+    /// Overrides fields with environment variables whose name starts with
+    /// `prefix`, before typechecking. `__` separates nested fields and names
+    /// are lowercased, so with prefix `APP_`, `APP_DB__PORT=5432` overrides
+    /// the `db.port` field.
+    ///
+    /// Values that are closed Dhall expressions without imports are used as
+    /// such, so `5432` is a `Natural`; other values are used as `Text`.
+    pub fn overlay_env(self, prefix: &str) -> Resolved {
+        self.overlay_vars(prefix, std::env::vars())
+    }
+    /// Like `overlay_env`, with the given variables instead of the
+    /// environment.
+    pub fn overlay_vars(
+        self,
+        prefix: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Resolved {
+        Resolved(overlay::overlay_vars(self.0, prefix, vars))
+    }
+    /// The expression as it is, without typechecking or normalizing it.
+    pub fn into_expr(self) -> ResolvedExpr {
+        self.0
+    }
     pub fn typecheck(self) -> Result<Typed, TypeError> {
         Ok(typecheck::typecheck(self.0)?.into_typed())
     }
-    pub fn typecheck_with(self, ty: &Typed) -> Result<Typed, TypeError> {
-        Ok(typecheck::typecheck_with(self.0, ty.normalize_to_expr())?
+    /// Like `typecheck`, but meant for very large inputs, e.g. generated
+    /// ones, where the types kept for every sub-expression would dominate
+    /// memory use. Once a sub-expression is known to have an expected type,
+    /// its own copy of the type is dropped, along with the normal form that
+    /// was computed to compare them, and the expected one is used instead.
+    /// Nothing is computed again: the type that is kept is already
+    /// normalized, so this costs little time. It only saves memory when many
+    /// sub-expressions have equal types, e.g. the elements of a long list.
+    pub fn typecheck_low_memory(self) -> Result<Typed, TypeError> {
+        Ok(typecheck::typecheck_low_memory(self.0)?.into_typed())
+    }
+    /// Only checks that the expression typechecks, e.g. to report errors in
+    /// an editor, without consuming it. This typechecks like
+    /// `typecheck_low_memory` and drops the result: it saves the memory of
+    /// the types, but not the normalization that comparing types requires,
+    /// so it is not faster than `typecheck`.
+    pub fn check(&self) -> Result<(), TypeError> {
+        typecheck::typecheck_low_memory(self.0.clone())?;
+        Ok(())
+    }
+    /// Typechecks the expression, checking that it has type `ty`. Fails if
+    /// `ty` is not a type, e.g. `1`.
+    pub fn typecheck_with(self, ty: &Normalized) -> Result<Typed, TypeError> {
+        Ok(typecheck::typecheck_with(self.0, &(ty.0).0)?.into_typed())
+    }
+    /// Like `typecheck`, but gives back the untyped expression on failure.
+    pub fn try_typecheck(
+        self,
+    ) -> Result<Typed, PhaseError<Resolved, TypeError>> {
+        self.clone()
+            .typecheck()
+            .map_err(|error| PhaseError { input: self, error })
+    }
+    /// Like `typecheck_with`, but gives back the untyped expression on
+    /// failure.
+    pub fn try_typecheck_with(
+        self,
+        ty: &Normalized,
+    ) -> Result<Typed, PhaseError<Resolved, TypeError>> {
+        self.clone()
+            .typecheck_with(ty)
+            .map_err(|error| PhaseError { input: self, error })
+    }
+    /// Typechecks and normalizes the expression, but keeps its top-level
+    /// `let` bindings instead of inlining them: only the bound values and the
+    /// body are normalized. This is meant for reviewing an evaluated
+    /// configuration, where inlining everything can blow up its size.
+    ///
+    /// Bindings of types are still substituted where typechecking needs them,
+    /// as are bindings whose values a type depends on. Type annotations on the
+    /// bindings are kept as written.
+    pub fn normalize_keeping_lets(self) -> Result<NormalizedExpr, TypeError> {
+        keep_lets::normalize_keeping_lets(self.0)
+    }
+    /// Typecheck an expression whose free variables are bound in `ctx`, e.g.
+    /// to definitions made by earlier inputs of a REPL.
+    pub fn typecheck_in(self, ctx: &Context) -> Result<Typed, TypeError> {
+        Ok(typecheck::typecheck_in(self.0, ctx.as_typecheck_context())?
             .into_typed())
     }
+    /// Typecheck an expression that refers to some free variables, given as
+    /// pairs of a name and a type. Those inputs are kept abstract: normalizing
+    /// the result evaluates everything that doesn't depend on them, and
+    /// `Normalized::instantiate` can later fill them in.
+    pub fn typecheck_abstract(
+        self,
+        inputs: &[(&str, Typed)],
+    ) -> Result<Typed, TypeError> {
+        let inputs: Vec<_> = inputs
+            .iter()
+            .map(|(x, t)| (Label::from(*x), t.to_value()))
+            .collect();
+        Ok(typecheck::typecheck_abstract(self.0, &inputs)?.into_typed())
+    }
 }
 
 impl Typed {
@@ -89,6 +341,13 @@ impl Typed {
         self.normalize_mut();
         Normalized(self)
     }
+    /// Normalizes, then applies the rewrite passes of `rewriter`.
+    pub fn normalize_with(
+        self,
+        rewriter: &Rewriter,
+    ) -> Result<Normalized, TypeError> {
+        rewriter.rewrite(&self.normalize())
+    }
 
     pub(crate) fn from_const(c: Const) -> Self {
         Typed(Value::from_const(c))
@@ -121,6 +380,103 @@ impl Typed {
             normalize: true,
         })
     }
+    /// Iterates over the elements of a list, so that a very large list can
+    /// be printed one element per line without normalizing or printing the
+    /// whole list at once. Returns `None` if the expression is not a list.
+    pub fn list_elements(&self) -> Option<ListElements> {
+        match &*self.0.as_whnf() {
+            ValueF::EmptyListLit(_) => {
+                Some(ListElements(Vec::new().into_iter()))
+            }
+            ValueF::NEListLit(elts) => {
+                Some(ListElements(elts.clone().into_iter()))
+            }
+            _ => None,
+        }
+    }
+    /// Where the expression that produced this value is in the source, if
+    /// known. This is kept through normalization, so e.g. selecting a field
+    /// of the normalized output gives the location of the expression that set
+    /// it, with the name of the file it was imported from.
+    pub fn source_span(&self) -> Option<Span> {
+        self.0.span()
+    }
+    /// The normalized type of the expression. Fails only for `Sort`, which
+    /// has no type.
+    pub fn type_expr(&self) -> Result<NormalizedExpr, TypeError> {
+        Ok(self.get_type()?.normalize_to_expr())
+    }
+    /// Checks that this value has at least the fields of the record type
+    /// `ty`, with the same types, unlike `Resolved::typecheck_with` which
+    /// requires the exact type. Nested records may have extra fields too.
+    /// The error lists all the missing and mismatched fields.
+    pub fn conforms_to(&self, ty: &Typed) -> Result<(), TypeError> {
+        typecheck::conforms_to(&self.0, &ty.0)
+    }
+    /// Applies this function to an argument and typechecks the result.
+    pub fn apply(&self, arg: NormalizedExpr) -> Result<Typed, TypeError> {
+        let f = dhall_syntax::rc(ExprF::Embed(Normalized(self.clone())));
+        let expr = dhall_syntax::rc(ExprF::App(f, arg));
+        Ok(typecheck::typecheck(expr)?.into_typed())
+    }
+    /// The variables the expression refers to without binding them, as in an
+    /// expression typechecked against a context.
+    pub fn free_vars(&self) -> BTreeSet<V<Label>> {
+        self.to_expr().free_vars()
+    }
+    /// Like `Normalized::select_path`. Only the records along the path are
+    /// evaluated, so this avoids normalizing the whole expression.
+    pub fn select_path<S: AsRef<str>>(
+        &self,
+        path: &[S],
+    ) -> Result<Typed, SelectError> {
+        let mut value = self.0.clone();
+        for (i, segment) in path.iter().enumerate() {
+            let prefix =
+                || path[..=i].iter().map(|s| s.as_ref().to_owned()).collect();
+            let next = match &*value.as_whnf() {
+                ValueF::RecordLit(kvs) => {
+                    match kvs.get(&segment.as_ref().into()) {
+                        Some(v) => v.clone(),
+                        None => {
+                            let mut available: Vec<String> =
+                                kvs.keys().map(String::from).collect();
+                            available.sort();
+                            return Err(SelectError::MissingField {
+                                path: prefix(),
+                                available,
+                            });
+                        }
+                    }
+                }
+                _ => {
+                    let mut path: Vec<String> = prefix();
+                    path.pop();
+                    return Err(SelectError::NotARecord { path });
+                }
+            };
+            value = next;
+        }
+        Ok(Typed(value))
+    }
+    /// Like `Normalized::select`.
+    pub fn select(&self, path: &str) -> Result<Typed, SelectError> {
+        let mut segments = Vec::new();
+        let mut current = String::new();
+        let mut quoted = false;
+        for c in path.chars() {
+            match c {
+                '`' => quoted = !quoted,
+                '.' if !quoted => segments
+                    .push(std::mem::replace(&mut current, String::new())),
+                c => current.push(c),
+            }
+        }
+        if !path.is_empty() {
+            segments.push(current);
+        }
+        self.select_path(&segments)
+    }
     pub(crate) fn to_value(&self) -> Value {
         self.0.clone()
     }
@@ -171,9 +527,221 @@ impl Typed {
 }
 
 impl Normalized {
+    /// The binary encoding of the expression. Normal forms contain neither
+    /// imports nor resolved imports, so this only fails if the CBOR encoder
+    /// does.
     pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         crate::phase::binary::encode(&self.to_expr())
     }
+    /// The expression in the stable text form described in
+    /// `PrintOptions::CANONICAL`, e.g. to sign a rendered configuration.
+    /// Bound variables are renamed to `_` first, so that expressions that
+    /// are equal print the same.
+    ///
+    /// ```
+    /// # use dhall::phase::Parsed;
+    /// let expr = Parsed::parse_str("{ name = \"café\", id = 1 + 1 }")
+    ///     .unwrap()
+    ///     .skip_resolve()
+    ///     .unwrap()
+    ///     .typecheck()
+    ///     .unwrap()
+    ///     .normalize();
+    /// assert_eq!(
+    ///     expr.to_canonical_string(),
+    ///     r#"{ id = 2, name = "caf\u00e9" }"#
+    /// );
+    /// ```
+    pub fn to_canonical_string(&self) -> String {
+        self.to_expr_alpha()
+            .display_with(PrintOptions::CANONICAL)
+            .to_string()
+    }
+    /// The type of a builtin, e.g. `Natural → Bool` for `Natural/even`. See
+    /// `Builtin::doc` for a description of what it does.
+    pub fn type_of_builtin(b: Builtin) -> Normalized {
+        let ty = Value::from_builtin(b).get_type().unwrap();
+        ty.into_typed().normalize()
+    }
+    /// Like `Parsed::to_binary_file`.
+    pub fn encode_to_file(&self, path: &Path) -> Result<(), Error> {
+        binary::write_atomically(path, &self.encode()?)?;
+        Ok(())
+    }
+    /// The hash of the binary encoding of the alpha-normalized expression, as
+    /// used to protect imports with `sha256:...`.
+    pub fn semantic_hash(&self) -> Result<Hash, EncodeError> {
+        Ok(self.encode_with_hash()?.0)
+    }
+    /// Encodes the alpha-normalized expression, as stored in the standard
+    /// import cache, along with its semantic hash. Other implementations
+    /// expect the data in a file named after `Hash::multihash_hex`.
+    pub fn encode_with_hash(&self) -> Result<(Hash, Vec<u8>), EncodeError> {
+        let data = crate::phase::binary::encode(&self.to_expr_alpha())?;
+        Ok((binary::hash_bytes(&data), data))
+    }
+
+    /// Substitutes values for the abstract inputs of an expression obtained
+    /// with `Resolved::typecheck_abstract`, and normalizes the result.
+    pub fn instantiate(
+        &self,
+        inputs: &[(&str, Normalized)],
+    ) -> Result<Normalized, TypeError> {
+        let expr = inputs.iter().rev().fold(self.to_expr(), |body, (x, v)| {
+            dhall_syntax::rc(ExprF::Let(
+                Label::from(*x),
+                None,
+                dhall_syntax::rc(ExprF::Embed(v.clone())),
+                body,
+            ))
+        });
+        Ok(typecheck::typecheck(expr)?.into_typed().normalize())
+    }
+    /// Selects a field inside nested records, e.g. the `port` field of
+    /// `{ services = { web = { port = 80 } } }` with
+    /// `["services", "web", "port"]`.
+    pub fn select_path<S: AsRef<str>>(
+        &self,
+        path: &[S],
+    ) -> Result<Normalized, SelectError> {
+        Ok(Normalized(self.0.select_path(path)?))
+    }
+    /// Like `select_path`, but takes a dot-separated path such as
+    /// `services.web.port`. Segments may be quoted with backticks.
+    pub fn select(&self, path: &str) -> Result<Normalized, SelectError> {
+        Ok(Normalized(self.0.select(path)?))
+    }
+
+    /// Lists the fields of a `{ Type = ..., default = ... }` record schema,
+    /// sorted by name. Fields of `Type` missing from `default` are required.
+    pub fn schema_fields(&self) -> Result<Vec<SchemaField>, SchemaError> {
+        schema::schema_fields(self)
+    }
+    /// Makes a value of the schema's `Type`, using `default` for the fields
+    /// that have one and placeholder values for the required ones.
+    pub fn schema_example(&self) -> Result<Normalized, SchemaError> {
+        schema::schema_example(self)
+    }
+
+    /// Like `Typed::source_span`.
+    pub fn source_span(&self) -> Option<Span> {
+        self.0.source_span()
+    }
+    /// Like `Typed::list_elements`.
+    pub fn list_elements(&self) -> Option<ListElements> {
+        self.0.list_elements()
+    }
+    /// The outermost node of the value, with its children as values, e.g. to
+    /// inspect the children given to a `Rewriter` pass.
+    pub fn as_node(&self) -> Node {
+        rewrite::node(self)
+    }
+    /// The alternative and payload of a union value: `< A : Natural | B >.A 1`
+    /// gives `("A", Some(1))` and `< A : Natural | B >.B` gives `("B", None)`.
+    /// Returns `None` if the expression is not a union value.
+    pub fn as_union(&self) -> Option<(Label, Option<Normalized>)> {
+        match &*(self.0).0.as_whnf() {
+            ValueF::UnionLit(l, v, _) => Some((
+                l.clone(),
+                Some(Typed::from_value(v.clone()).normalize()),
+            )),
+            ValueF::UnionConstructor(l, kts)
+                if kts.get(l).map_or(false, Option::is_none) =>
+            {
+                Some((l.clone(), None))
+            }
+            _ => None,
+        }
+    }
+    /// The alternatives of a union type, sorted by name, with the type of
+    /// their payload if they have one. Returns `None` if the expression is
+    /// not a union type. This is meant to document the values allowed for a
+    /// field, e.g. in the help of a command-line tool:
+    ///
+    /// ```
+    /// # use dhall::phase::Parsed;
+    /// let ty = Parsed::parse_str("< Info | Debug | File : Text >")
+    ///     .unwrap()
+    ///     .skip_resolve()
+    ///     .unwrap()
+    ///     .typecheck()
+    ///     .unwrap()
+    ///     .normalize();
+    /// let help: Vec<String> = ty
+    ///     .union_alternatives()
+    ///     .unwrap()
+    ///     .into_iter()
+    ///     .map(|(label, payload)| match payload {
+    ///         Some(t) => format!("{} : {}", label, t),
+    ///         None => label.to_string(),
+    ///     })
+    ///     .collect();
+    /// assert_eq!(help, ["Debug", "File : Text", "Info"]);
+    /// ```
+    pub fn union_alternatives(
+        &self,
+    ) -> Option<Vec<(Label, Option<Normalized>)>> {
+        match &*(self.0).0.as_whnf() {
+            ValueF::UnionType(kts) => {
+                let mut alternatives: Vec<_> = kts
+                    .iter()
+                    .map(|(l, t)| {
+                        let t = t
+                            .as_ref()
+                            .map(|t| Typed::from_value(t.clone()).normalize());
+                        (l.clone(), t)
+                    })
+                    .collect();
+                alternatives.sort_by(|a, b| a.0.cmp(&b.0));
+                Some(alternatives)
+            }
+            _ => None,
+        }
+    }
+    /// Makes a value of the union type `ty`, the inverse of `as_union`. Fails
+    /// if `ty` has no such alternative or the payload doesn't match it.
+    pub fn make_union_value(
+        ty: &Normalized,
+        alternative: &str,
+        payload: Option<Normalized>,
+    ) -> Result<Normalized, TypeError> {
+        use dhall_syntax::rc;
+        let constructor = rc(ExprF::Field(
+            rc(ExprF::Embed(ty.clone())),
+            Label::from(alternative),
+        ));
+        let expr = match payload {
+            Some(v) => rc(ExprF::App(constructor, rc(ExprF::Embed(v)))),
+            None => constructor,
+        };
+        Ok(typecheck::typecheck(expr)?.into_typed().normalize())
+    }
+
+    /// Measures the size of the expression, without printing it.
+    pub fn stats(&self) -> ExprStats {
+        fn go(e: &NormalizedExpr, depth: usize, stats: &mut ExprStats) {
+            stats.nodes += 1;
+            stats.max_depth = stats.max_depth.max(depth);
+            match e.as_ref() {
+                ExprF::TextLit(t) => {
+                    for chunk in t.iter() {
+                        if let InterpolatedTextContents::Text(s) = chunk {
+                            stats.text_bytes += s.len();
+                        }
+                    }
+                }
+                ExprF::NEListLit(es) => {
+                    stats.list_elements += es.len();
+                    stats.max_list_len = stats.max_list_len.max(es.len());
+                }
+                _ => {}
+            }
+            e.as_ref().map_ref(|e| go(e, depth + 1, stats));
+        }
+        let mut stats = ExprStats::default();
+        go(&self.to_expr(), 1, &mut stats);
+        stats
+    }
 
     pub(crate) fn to_expr(&self) -> NormalizedExpr {
         self.0.normalize_to_expr()
@@ -186,6 +754,18 @@ impl Normalized {
     }
 }
 
+impl Iterator for ListElements {
+    type Item = Normalized;
+    fn next(&mut self) -> Option<Normalized> {
+        self.0.next().map(|v| Typed::from_value(v).normalize())
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for ListElements {}
+
 impl Shift for Typed {
     fn shift(&self, delta: isize, var: &AlphaVar) -> Option<Self> {
         Some(Typed(self.0.shift(delta, var)?))
@@ -229,13 +809,19 @@ derive_traits_for_wrapper_struct!(Parsed);
 derive_traits_for_wrapper_struct!(Resolved);
 derive_traits_for_wrapper_struct!(Normalized);
 
+/// Consistent with `Eq`, which doesn't tell apart alpha-equivalent
+/// expressions.
 impl std::hash::Hash for Normalized {
     fn hash<H>(&self, state: &mut H)
     where
         H: std::hash::Hasher,
     {
-        if let Ok(vec) = self.encode() {
-            vec.hash(state)
+        let expr = self.to_expr_alpha();
+        match binary::encode(&expr) {
+            Ok(data) => data.hash(state),
+            // Printing is a fallback that still tells apart most values, in
+            // case encoding fails.
+            Err(_) => expr.to_string().hash(state),
         }
     }
 }
@@ -252,3 +838,138 @@ impl Display for Typed {
         self.to_expr().fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use dhall_syntax::{Builtin, Label, V};
+
+    use super::{binary, Normalized, Parsed, Typed};
+    use crate::error::SelectError;
+
+    fn typed(s: &str) -> Typed {
+        Parsed::parse_str(s)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+    }
+
+    fn eval(s: &str) -> Normalized {
+        typed(s).normalize()
+    }
+
+    #[test]
+    fn select_fields() {
+        let config = eval("{ services = { web = { port = 80 } } }");
+        let port = config.select_path(&["services", "web", "port"]).unwrap();
+        assert_eq!(port.to_string(), "80");
+        assert_eq!(config.select("services.web.port").unwrap(), port);
+        assert_eq!(
+            config.select("services.db"),
+            Err(SelectError::MissingField {
+                path: vec!["services".to_owned(), "db".to_owned()],
+                available: vec!["web".to_owned()],
+            })
+        );
+        assert_eq!(
+            config.select("services.web.port.number"),
+            Err(SelectError::NotARecord {
+                path: vec![
+                    "services".to_owned(),
+                    "web".to_owned(),
+                    "port".to_owned()
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn abstract_expressions() {
+        let natural = Typed::make_builtin_type(Builtin::Natural);
+        let template = Parsed::parse_str("{ name = \"web\", port = port + 1 }")
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck_abstract(&[("port", natural)])
+            .unwrap();
+        let port = V(Label::from("port"), 0);
+        assert_eq!(
+            template.free_vars().into_iter().collect::<Vec<_>>(),
+            [port]
+        );
+        assert!(typed("λ(port : Natural) → port").free_vars().is_empty());
+
+        let template = template.normalize();
+        let config = template.instantiate(&[("port", eval("8000"))]).unwrap();
+        assert_eq!(config.to_string(), "{ name = \"web\", port = 8001 }");
+        assert!(template.instantiate(&[("port", eval("True"))]).is_err());
+    }
+
+    #[test]
+    fn encode_with_hash() {
+        let (hash, data) =
+            eval("λ(x : Natural) → x + 1").encode_with_hash().unwrap();
+        let alpha_equivalent = eval("λ(y : Natural) → y + 1");
+        assert_eq!(hash, alpha_equivalent.semantic_hash().unwrap());
+        assert_eq!(hash, binary::hash_bytes(&data));
+        let decoded = Parsed::parse_binary(&data).unwrap();
+        assert_eq!(decoded.to_string(), "λ(_ : Natural) → _ + 1");
+    }
+
+    #[test]
+    fn canonical_strings() {
+        let canonical = |s: &str| eval(s).to_canonical_string();
+        assert_eq!(
+            canonical("λ(x : Natural) → x + 1"),
+            "\\(_ : Natural) -> _ + 1"
+        );
+        assert_eq!(
+            canonical("λ(x : Natural) → x + 1"),
+            canonical("λ(y : Natural) → y + 1")
+        );
+        assert_eq!(
+            canonical("∀(a : Type) → a → List a"),
+            "Type -> _ -> List _@1"
+        );
+        assert_eq!(
+            canonical("λ(r : { a : Bool }) → [ r ⫽ { b = 1 }, r ∧ { b = 1 } ]"),
+            "\\(_ : { a : Bool }) -> [_ // { b = 1 }, _ /\\ { b = 1 }]"
+        );
+        assert_eq!(
+            canonical("λ(x : Natural) → x ≡ x"),
+            "\\(_ : Natural) -> _ === _"
+        );
+        assert!(canonical("{ name = \"café\" }").is_ascii());
+    }
+
+    #[test]
+    fn try_phases_return_their_input() {
+        let err = Parsed::parse_str("./does-not-exist.dhall")
+            .unwrap()
+            .try_resolve()
+            .unwrap_err();
+        assert_eq!(err.input.to_string(), "./does-not-exist.dhall");
+
+        let err = Parsed::parse_str("1 + True")
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .try_typecheck()
+            .unwrap_err();
+        assert_eq!(err.input.into_expr().to_string(), "1 + True");
+    }
+
+    #[test]
+    fn list_elements() {
+        let list = typed("[1, 2] # [3]");
+        let elements: Vec<_> = list
+            .list_elements()
+            .unwrap()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(elements, ["1", "2", "3"]);
+        assert_eq!(eval("[] : List Bool").list_elements().unwrap().count(), 0);
+        assert!(eval("{=}").list_elements().is_none());
+    }
+}
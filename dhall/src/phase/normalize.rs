@@ -1,3 +1,4 @@
+use std::borrow::Borrow;
 use std::collections::HashMap;
 
 use dhall_syntax::Const::Type;
@@ -6,6 +7,7 @@ use dhall_syntax::{
     NaiveDouble,
 };
 
+use crate::core::budget;
 use crate::core::value::Value;
 use crate::core::valuef::ValueF;
 use crate::core::var::{AlphaLabel, Shift, Subst};
@@ -357,18 +359,16 @@ pub(crate) fn apply_builtin(
                     ),
             ),
         },
-        (NaturalFold, [n, t, succ, zero, r @ ..]) => match &*n.as_whnf() {
-            NaturalLit(0) => Ret::ValueWithRemainingArgs(r, zero.clone()),
+        (NaturalFold, [n, _, succ, zero, r @ ..]) => match &*n.as_whnf() {
             NaturalLit(n) => {
-                let fold = Value::from_builtin(NaturalFold)
-                    .app(
-                        NaturalLit(n - 1)
-                            .into_value_with_type(Value::from_builtin(Natural)),
-                    )
-                    .app(t.clone())
-                    .app(succ.clone())
-                    .app(zero.clone());
-                Ret::ValueWithRemainingArgs(r, succ.app(fold))
+                // Accumulate iteratively instead of unfolding `Natural/fold`
+                // once per step, which would recurse `n` levels deep.
+                let mut v = zero.clone();
+                for _ in 0..*n {
+                    budget::step();
+                    v = succ.app(v);
+                }
+                Ret::ValueWithRemainingArgs(r, v)
             }
             _ => Ret::DoneAsIs,
         },
@@ -409,25 +409,30 @@ pub(crate) fn apply_any(f: Value, a: Value, ty: &Value) -> ValueF {
     }
 }
 
-pub(crate) fn squash_textlit(
-    elts: impl Iterator<Item = InterpolatedTextContents<Value>>,
-) -> Vec<InterpolatedTextContents<Value>> {
+/// Flattens nested text literals and merges adjacent text chunks. The chunks
+/// are only borrowed, so that the text is copied once into the result.
+pub(crate) fn squash_textlit<T>(
+    elts: impl IntoIterator<Item = T>,
+) -> Vec<InterpolatedTextContents<Value>>
+where
+    T: Borrow<InterpolatedTextContents<Value>>,
+{
     use std::mem::replace;
     use InterpolatedTextContents::{Expr, Text};
 
-    fn inner(
-        elts: impl Iterator<Item = InterpolatedTextContents<Value>>,
+    fn inner<T: Borrow<InterpolatedTextContents<Value>>>(
+        elts: impl IntoIterator<Item = T>,
         crnt_str: &mut String,
         ret: &mut Vec<InterpolatedTextContents<Value>>,
     ) {
         for contents in elts {
-            match contents {
-                Text(s) => crnt_str.push_str(&s),
+            match contents.borrow() {
+                Text(s) => crnt_str.push_str(s),
                 Expr(e) => {
                     let e_borrow = e.as_whnf();
                     match &*e_borrow {
                         ValueF::TextLit(elts2) => {
-                            inner(elts2.iter().cloned(), crnt_str, ret)
+                            inner(elts2.iter(), crnt_str, ret)
                         }
                         _ => {
                             drop(e_borrow);
@@ -446,7 +451,7 @@ pub(crate) fn squash_textlit(
     let mut ret = Vec::new();
     inner(elts, &mut crnt_str, &mut ret);
     if !crnt_str.is_empty() {
-        ret.push(Text(replace(&mut crnt_str, String::new())))
+        ret.push(Text(crnt_str))
     }
     ret
 }
@@ -458,21 +463,35 @@ pub(crate) fn merge_maps<K, V, F, Err>(
 ) -> Result<HashMap<K, V>, Err>
 where
     F: FnMut(&K, &V, &V) -> Result<V, Err>,
-    K: std::hash::Hash + Eq + Clone,
+    K: std::hash::Hash + Ord + Clone,
     V: Clone,
 {
-    let mut kvs = HashMap::new();
-    for (x, v2) in map2 {
-        let newv = if let Some(v1) = map1.get(x) {
-            f(x, v1, v2)?
-        } else {
-            v2.clone()
-        };
-        kvs.insert(x.clone(), newv);
-    }
-    for (x, v1) in map1 {
-        // Insert only if key not already present
-        kvs.entry(x.clone()).or_insert_with(|| v1.clone());
+    use std::collections::hash_map::Entry;
+    // Start from the larger map, so that merging a few fields into a wide
+    // record only looks up those few fields.
+    let (mut kvs, other, swapped) = if map1.len() >= map2.len() {
+        (map1.clone(), map2, false)
+    } else {
+        (map2.clone(), map1, true)
+    };
+    // Merge in key order, so that when `f` fails, the key it fails on
+    // doesn't depend on how the keys are hashed.
+    let mut other: Vec<_> = other.iter().collect();
+    other.sort_by(|(x, _), (y, _)| x.cmp(y));
+    for (x, v) in other {
+        match kvs.entry(x.clone()) {
+            Entry::Occupied(mut e) => {
+                let newv = if swapped {
+                    f(x, v, e.get())?
+                } else {
+                    f(x, e.get(), v)?
+                };
+                e.insert(newv);
+            }
+            Entry::Vacant(e) => {
+                e.insert(v.clone());
+            }
+        }
     }
     Ok(kvs)
 }
@@ -543,22 +562,18 @@ fn apply_binop<'a>(
 
         (TextAppend, TextLit(x), _) if x.is_empty() => Ret::ValueRef(y),
         (TextAppend, _, TextLit(y)) if y.is_empty() => Ret::ValueRef(x),
-        (TextAppend, TextLit(x), TextLit(y)) => Ret::ValueF(TextLit(
-            squash_textlit(x.iter().chain(y.iter()).cloned()),
-        )),
+        (TextAppend, TextLit(x), TextLit(y)) => {
+            Ret::ValueF(TextLit(squash_textlit(x.iter().chain(y.iter()))))
+        }
         (TextAppend, TextLit(x), _) => {
             use std::iter::once;
             let y = InterpolatedTextContents::Expr(y.clone());
-            Ret::ValueF(TextLit(squash_textlit(
-                x.iter().cloned().chain(once(y)),
-            )))
+            Ret::ValueF(TextLit(squash_textlit(x.iter().chain(once(&y)))))
         }
         (TextAppend, _, TextLit(y)) => {
             use std::iter::once;
             let x = InterpolatedTextContents::Expr(x.clone());
-            Ret::ValueF(TextLit(squash_textlit(
-                once(x).chain(y.iter().cloned()),
-            )))
+            Ret::ValueF(TextLit(squash_textlit(once(&x).chain(y.iter()))))
         }
 
         (RightBiasedRecordMerge, _, RecordLit(kvs)) if kvs.is_empty() => {
@@ -568,11 +583,20 @@ fn apply_binop<'a>(
             Ret::ValueRef(y)
         }
         (RightBiasedRecordMerge, RecordLit(kvs1), RecordLit(kvs2)) => {
-            let mut kvs = kvs2.clone();
-            for (x, v) in kvs1 {
-                // Insert only if key not already present
-                kvs.entry(x.clone()).or_insert_with(|| v.clone());
-            }
+            let kvs = if kvs1.len() >= kvs2.len() {
+                let mut kvs = kvs1.clone();
+                for (x, v) in kvs2 {
+                    kvs.insert(x.clone(), v.clone());
+                }
+                kvs
+            } else {
+                let mut kvs = kvs2.clone();
+                for (x, v) in kvs1 {
+                    // Insert only if key not already present
+                    kvs.entry(x.clone()).or_insert_with(|| v.clone());
+                }
+                kvs
+            };
             Ret::ValueF(RecordLit(kvs))
         }
 
@@ -609,6 +633,48 @@ fn apply_binop<'a>(
     })
 }
 
+/// What selecting a field of a `⫽` or `∧` with a neutral operand reduces to.
+enum FieldOfMerge {
+    /// The field is known
+    Value(Value),
+    /// The field comes from the selection of the same field in this operand
+    Field(Value),
+}
+
+/// Selects the field `l` of `x op y`, where one of `x` or `y` is neutral, if
+/// the record literal operand determines where the field comes from:
+/// `(x ⫽ { l = v }).l` is `v`, and `(x ⫽ { m = v }).l` or `(x ∧ { m = v }).l`
+/// are `x.l`.
+fn field_of_merge(
+    op: BinOp,
+    x: &Value,
+    y: &Value,
+    l: &Label,
+) -> Option<FieldOfMerge> {
+    let x_borrow = x.as_whnf();
+    let y_borrow = y.as_whnf();
+    match (op, &*x_borrow, &*y_borrow) {
+        (BinOp::RightBiasedRecordMerge, _, ValueF::RecordLit(kvs)) => {
+            Some(match kvs.get(l) {
+                Some(v) => FieldOfMerge::Value(v.clone()),
+                None => FieldOfMerge::Field(x.clone()),
+            })
+        }
+        (BinOp::RightBiasedRecordMerge, ValueF::RecordLit(kvs), _)
+        | (BinOp::RecursiveRecordMerge, ValueF::RecordLit(kvs), _)
+            if !kvs.contains_key(l) =>
+        {
+            Some(FieldOfMerge::Field(y.clone()))
+        }
+        (BinOp::RecursiveRecordMerge, _, ValueF::RecordLit(kvs))
+            if !kvs.contains_key(l) =>
+        {
+            Some(FieldOfMerge::Field(x.clone()))
+        }
+        _ => None,
+    }
+}
+
 pub(crate) fn normalize_one_layer(
     expr: ExprF<Value, Normalized>,
     ty: &Value,
@@ -732,6 +798,18 @@ pub(crate) fn normalize_one_layer(
                 UnionType(kts) => {
                     Ret::ValueF(UnionConstructor(l.clone(), kts.clone()))
                 }
+                ValueF::PartialExpr(ExprF::BinOp(op, x, y)) => {
+                    match field_of_merge(*op, x, y, l) {
+                        Some(FieldOfMerge::Value(r)) => Ret::Value(r),
+                        Some(FieldOfMerge::Field(r)) => Ret::ValueF(
+                            normalize_one_layer(ExprF::Field(r, l.clone()), ty),
+                        ),
+                        None => {
+                            drop(v_borrow);
+                            Ret::Expr(expr)
+                        }
+                    }
+                }
                 _ => {
                     drop(v_borrow);
                     Ret::Expr(expr)
@@ -739,31 +817,23 @@ pub(crate) fn normalize_one_layer(
             }
         }
 
+        // As the standard specifies, `merge` only reduces when both the
+        // handlers and the union are literals.
         ExprF::Merge(ref handlers, ref variant, _) => {
             let handlers_borrow = handlers.as_whnf();
             let variant_borrow = variant.as_whnf();
-            match (&*handlers_borrow, &*variant_borrow) {
-                (RecordLit(kvs), UnionConstructor(l, _)) => match kvs.get(l) {
-                    Some(h) => Ret::Value(h.clone()),
-                    None => {
-                        drop(handlers_borrow);
-                        drop(variant_borrow);
-                        Ret::Expr(expr)
-                    }
-                },
-                (RecordLit(kvs), UnionLit(l, v, _)) => match kvs.get(l) {
-                    Some(h) => Ret::Value(h.app(v.clone())),
-                    None => {
-                        drop(handlers_borrow);
-                        drop(variant_borrow);
-                        Ret::Expr(expr)
-                    }
-                },
-                _ => {
-                    drop(handlers_borrow);
-                    drop(variant_borrow);
-                    Ret::Expr(expr)
+            let handled = match (&*handlers_borrow, &*variant_borrow) {
+                (RecordLit(kvs), UnionConstructor(l, _)) => kvs.get(l).cloned(),
+                (RecordLit(kvs), UnionLit(l, v, _)) => {
+                    kvs.get(l).map(|h| h.app(v.clone()))
                 }
+                _ => None,
+            };
+            drop(handlers_borrow);
+            drop(variant_borrow);
+            match handled {
+                Some(v) => Ret::Value(v),
+                None => Ret::Expr(expr),
             }
         }
         ExprF::ToMap(_, _) => unimplemented!("toMap"),
@@ -779,13 +849,128 @@ pub(crate) fn normalize_one_layer(
 
 /// Normalize a ValueF into WHNF
 pub(crate) fn normalize_whnf(v: ValueF, ty: &Value) -> ValueF {
+    budget::step();
     match v {
         ValueF::AppliedBuiltin(b, args) => apply_builtin(b, args, ty),
         ValueF::PartialExpr(e) => normalize_one_layer(e, ty),
-        ValueF::TextLit(elts) => {
-            ValueF::TextLit(squash_textlit(elts.into_iter()))
-        }
+        ValueF::TextLit(elts) => ValueF::TextLit(squash_textlit(elts)),
         // All other cases are already in WHNF
         v => v,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::phase::Parsed;
+
+    fn normalize(s: &str) -> String {
+        Parsed::parse_str(s)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize()
+            .to_string()
+    }
+
+    #[test]
+    fn select_fields_of_abstract_merges() {
+        let r = "λ(r : { a : Natural, b : Bool }) → ";
+        assert_eq!(
+            normalize(&format!("{}(r ⫽ {{ a = 1 }}).a", r)),
+            "λ(r : { a : Natural, b : Bool }) → 1"
+        );
+        assert_eq!(
+            normalize(&format!("{}(r ⫽ {{ a = 1 }}).b", r)),
+            "λ(r : { a : Natural, b : Bool }) → r.b"
+        );
+        assert_eq!(
+            normalize(&format!("{}({{ c = 1 }} ∧ r).b", r)),
+            "λ(r : { a : Natural, b : Bool }) → r.b"
+        );
+    }
+
+    #[test]
+    fn merge_only_reduces_literals() {
+        assert_eq!(
+            normalize("merge { A = λ(n : Natural) → n + 1, B = 0 } (< A : Natural | B >.A 1)"),
+            "2"
+        );
+        assert_eq!(
+            normalize("merge { A = λ(n : Natural) → n + 1, B = 0 } < A : Natural | B >.B"),
+            "0"
+        );
+        // Handlers that aren't a record literal block the reduction, even
+        // when the handler that would be used is known
+        let src = "λ(h : { A : Natural → Text, B : Text }) → \
+                   merge (h ⫽ { B = \"b\" }) < A : Natural | B >.B";
+        assert_eq!(
+            normalize(src),
+            "λ(h : { A : Natural → Text, B : Text }) → \
+             merge (h ⫽ { B = \"b\" }) < A: Natural | B >.B"
+        );
+        // So does a union that isn't a literal
+        let src = "λ(u : < A : Natural | B >) → \
+                   merge { A = λ(n : Natural) → n, B = 0 } u";
+        assert_eq!(
+            normalize(src),
+            "λ(u : < A: Natural | B >) → \
+             merge { A = λ(n : Natural) → n, B = 0 } u"
+        );
+    }
+
+    #[test]
+    fn list_builtins_on_abstract_lists_are_not_reduced() {
+        // The standard doesn't simplify list builtins applied to lists that
+        // aren't literals, so neither does normalization: the hashes and
+        // equivalences of other implementations depend on it.
+        let xs = "λ(xs : List Natural) → ";
+        for src in &[
+            "List/length Natural ([ 1, 2 ] # xs)",
+            "List/length Natural (List/reverse Natural xs)",
+            "List/head Natural ([ 1, 2 ] # xs)",
+            "List/last Natural (xs # [ 1, 2 ])",
+            "List/reverse Natural (List/reverse Natural xs)",
+        ] {
+            let src = format!("{}{}", xs, src);
+            let unchanged = Parsed::parse_str(&src).unwrap().to_string();
+            assert_eq!(normalize(&src), unchanged);
+        }
+    }
+
+    #[test]
+    fn equivalences_and_asserts() {
+        assert_eq!(normalize("(1 + 1) === 2"), "2 ≡ 2");
+        assert_eq!(
+            normalize("λ(n : Natural) → assert : (n + 0) === n"),
+            "λ(n : Natural) → assert : n ≡ n"
+        );
+        // Functions are compared up to alpha-equivalence
+        assert_eq!(
+            normalize(
+                "let f = λ(x : Text) → x ++ \"\" \
+                 let example0 = assert : f === (λ(y : Text) → y) \
+                 in  f \"a\""
+            ),
+            "\"a\""
+        );
+
+        let typecheck = |s: &str| {
+            Parsed::parse_str(s)
+                .unwrap()
+                .skip_resolve()
+                .unwrap()
+                .typecheck()
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            typecheck("assert : (1 + 1) === 3"),
+            Err("Assertion failed: 2 is not equivalent to 3".to_owned())
+        );
+        assert!(typecheck("assert : 0.0 === -0.0").is_err());
+        assert!(typecheck("assert : Natural === Natural").is_err());
+        assert!(typecheck("assert : True").is_err());
+    }
+}
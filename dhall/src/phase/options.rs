@@ -0,0 +1,186 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::core::budget::{self, Exceeded};
+use crate::error::Error;
+use crate::phase::{parse, Normalized, Parsed, ResolutionLimits};
+
+/// How to evaluate an expression, from parsing to normalization. The default
+/// options evaluate like `Parsed::resolve`, `Resolved::typecheck` and
+/// `Typed::normalize` do, without any limits.
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    /// The limits on resolving the imports of the expression
+    pub limits: ResolutionLimits,
+    /// How many bytes the expression to evaluate can have, not counting the
+    /// files it imports.
+    pub max_input_size: Option<usize>,
+    /// Whether to typecheck with `Resolved::typecheck_low_memory`.
+    pub low_memory: bool,
+    /// How many normalization steps typechecking and normalizing can take,
+    /// so that a small expression can't keep evaluating for a long time.
+    pub max_steps: Option<usize>,
+    /// How many values typechecking and normalizing can allocate. Each takes
+    /// around a hundred bytes, so this bounds the memory evaluation uses.
+    pub max_values: Option<usize>,
+}
+
+impl EvalOptions {
+    pub fn new() -> Self {
+        EvalOptions::default()
+    }
+
+    /// Options for evaluating untrusted expressions: remote and environment
+    /// variable imports are refused, local imports must be inside `root`,
+    /// imports can be nested 8 deep and 64 in total, the input and each
+    /// imported file can have at most 1 MiB, typechecking and normalizing
+    /// can take at most 2^20 steps and allocate at most 2^20 values, and
+    /// types are not kept in memory once checked.
+    pub fn sandboxed(root: &Path) -> Self {
+        EvalOptions {
+            limits: ResolutionLimits {
+                max_depth: Some(8),
+                max_imports: Some(64),
+                max_file_size: Some(1 << 20),
+                forbid_remote: true,
+                forbid_env: true,
+                root: Some(root.to_owned()),
+            },
+            max_input_size: Some(1 << 20),
+            low_memory: true,
+            max_steps: Some(1 << 20),
+            max_values: Some(1 << 20),
+        }
+    }
+
+    pub fn eval_str(&self, s: &str) -> Result<Normalized, Error> {
+        self.check_input_size(s.len())?;
+        self.eval(Parsed::parse_str(s)?)
+    }
+
+    pub fn eval_file(&self, f: &Path) -> Result<Normalized, Error> {
+        let mut file = File::open(f)?;
+        let mut buffer = Vec::new();
+        // Reading one byte past the limit is enough to tell that the file is
+        // too large, even if it grows while it is read.
+        match self.max_input_size {
+            Some(max) => file
+                .by_ref()
+                .take(max as u64 + 1)
+                .read_to_end(&mut buffer)?,
+            None => file.read_to_end(&mut buffer)?,
+        };
+        self.check_input_size(buffer.len())?;
+        self.eval(parse::parse_file_contents(f, &buffer)?)
+    }
+
+    fn check_input_size(&self, size: usize) -> Result<(), Error> {
+        match self.max_input_size {
+            Some(max) if size > max => Err(Error::InputTooLarge(max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn eval(&self, parsed: Parsed) -> Result<Normalized, Error> {
+        let resolved = parsed.resolve_with_limits(&self.limits)?;
+        let low_memory = self.low_memory;
+        let normalized =
+            budget::with_budget(self.max_steps, self.max_values, || {
+                let typed = if low_memory {
+                    resolved.typecheck_low_memory()?
+                } else {
+                    resolved.typecheck()?
+                };
+                Ok::<_, Error>(typed.normalize())
+            });
+        match normalized {
+            Ok(normalized) => normalized,
+            Err(Exceeded::Steps) => {
+                Err(Error::TooManySteps(self.max_steps.unwrap_or(0)))
+            }
+            Err(Exceeded::Values) => {
+                Err(Error::TooManyValues(self.max_values.unwrap_or(0)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::EvalOptions;
+    use crate::error::{Error, ImportError};
+    use crate::tests::TempDir;
+
+    #[test]
+    fn sandboxed_evaluation() {
+        let dir = TempDir::new();
+        let root = dir.join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(dir.join("secret.dhall"), "42").unwrap();
+        fs::write(root.join("ok.dhall"), "./one.dhall + 1").unwrap();
+        fs::write(root.join("one.dhall"), "1").unwrap();
+        fs::write(root.join("escape.dhall"), "../secret.dhall").unwrap();
+        fs::write(root.join("env.dhall"), "env:HOME as Text").unwrap();
+        fs::write(root.join("remote.dhall"), "https://example.com/x").unwrap();
+        fs::write(root.join("big.dhall"), "./huge.dhall").unwrap();
+        fs::write(root.join("huge.dhall"), " ".repeat(2 << 20) + "1").unwrap();
+
+        let options = EvalOptions::sandboxed(&root);
+        let eval = |name: &str| options.eval_file(&root.join(name));
+        assert_eq!(eval("ok.dhall").unwrap().to_string(), "2");
+        for name in &["escape.dhall", "env.dhall", "remote.dhall"] {
+            match eval(name) {
+                Err(Error::Resolve(ImportError::NotAllowed(_))) => {}
+                res => panic!("Expected NotAllowed, got: {:?}", res),
+            }
+        }
+        match eval("big.dhall") {
+            Err(Error::Resolve(ImportError::TooLarge(_, _))) => {}
+            res => panic!("Expected TooLarge, got: {:?}", res),
+        }
+        match eval("huge.dhall") {
+            Err(Error::InputTooLarge(_)) => {}
+            res => panic!("Expected InputTooLarge, got: {:?}", res),
+        }
+        assert!(EvalOptions::new()
+            .eval_file(&root.join("escape.dhall"))
+            .is_ok());
+    }
+
+    #[test]
+    fn step_and_value_budgets() {
+        let src = "Natural/fold 100000000 Natural (λ(n : Natural) → n + 1) 0";
+        let steps = EvalOptions {
+            max_steps: Some(1000),
+            ..EvalOptions::new()
+        };
+        match steps.eval_str(src) {
+            Err(Error::TooManySteps(1000)) => {}
+            res => panic!("Expected TooManySteps, got: {:?}", res),
+        }
+        let values = EvalOptions {
+            max_values: Some(1000),
+            ..EvalOptions::new()
+        };
+        match values.eval_str(src) {
+            Err(Error::TooManyValues(1000)) => {}
+            res => panic!("Expected TooManyValues, got: {:?}", res),
+        }
+        let sandboxed = EvalOptions::sandboxed(&std::env::temp_dir());
+        match sandboxed.eval_str(src) {
+            Err(Error::TooManySteps(_)) | Err(Error::TooManyValues(_)) => {}
+            res => panic!("Expected a budget to run out, got: {:?}", res),
+        }
+        // Small expressions are within budget, and the budget doesn't
+        // outlive the evaluation
+        let small = "Natural/fold 10 Natural (λ(n : Natural) → n + 1) 0";
+        assert_eq!(sandboxed.eval_str(small).unwrap().to_string(), "10");
+        assert_eq!(
+            EvalOptions::new().eval_str(small).unwrap().to_string(),
+            "10"
+        );
+    }
+}
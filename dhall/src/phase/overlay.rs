@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use dhall_syntax::map::DupTreeMap;
+use dhall_syntax::{parse_expr, rc, BinOp, ExprF, Label, V};
+
+use crate::phase::{Normalized, ResolvedExpr};
+
+/// Separates the nested fields in the name of an overriding variable
+const NESTING_SEPARATOR: &str = "__";
+
+/// The overrides to apply, as a tree of field names.
+enum Override {
+    Value(ResolvedExpr),
+    Fields(BTreeMap<String, Override>),
+}
+
+/// Parses the value of an overriding variable. Values that are closed Dhall
+/// expressions without imports are used as such, so that `8080` is a
+/// `Natural`; anything else is used as `Text`.
+fn parse_override(s: &str) -> ResolvedExpr {
+    let text = || rc(ExprF::TextLit(s.to_owned().into()));
+    match parse_expr::<Normalized>(s) {
+        Ok(e) => {
+            let has_imports = e.traverse_resolve(&|_| Err(())).is_err();
+            if has_imports || !e.free_vars().is_empty() {
+                text()
+            } else {
+                e
+            }
+        }
+        Err(_) => text(),
+    }
+}
+
+fn insert(fields: &mut BTreeMap<String, Override>, path: &[&str], value: &str) {
+    let (name, rest) = match path.split_first() {
+        Some(x) => x,
+        None => return,
+    };
+    let name = name.to_lowercase();
+    if rest.is_empty() {
+        fields.insert(name, Override::Value(parse_override(value)));
+        return;
+    }
+    let entry = fields
+        .entry(name)
+        .or_insert_with(|| Override::Fields(BTreeMap::new()));
+    if let Override::Value(_) = entry {
+        *entry = Override::Fields(BTreeMap::new());
+    }
+    if let Override::Fields(fields) = entry {
+        insert(fields, rest, value)
+    }
+}
+
+/// Builds `base // { x = v, y = base.y // { ... } }`.
+fn apply(
+    base: ResolvedExpr,
+    fields: BTreeMap<String, Override>,
+) -> ResolvedExpr {
+    let mut kvs = DupTreeMap::new();
+    for (name, o) in fields {
+        let label = Label::from(name);
+        let value = match o {
+            Override::Value(e) => e,
+            Override::Fields(fields) => {
+                let field = rc(ExprF::Field(base.clone(), label.clone()));
+                apply(field, fields)
+            }
+        };
+        kvs.insert(label, value);
+    }
+    rc(ExprF::BinOp(
+        BinOp::RightBiasedRecordMerge,
+        base,
+        rc(ExprF::RecordLit(kvs)),
+    ))
+}
+
+/// Overrides fields of `expr` with the variables whose name starts with
+/// `prefix`. The rest of the name is split on `__` to get the path to the
+/// overridden field, lowercased: `APP_DB__PORT=5432` with prefix `APP_`
+/// overrides `db.port`.
+///
+/// Each level is merged with `//`, so records along the path must exist in
+/// `expr` but the overridden fields themselves need not.
+pub(crate) fn overlay_vars(
+    expr: ResolvedExpr,
+    prefix: &str,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> ResolvedExpr {
+    let mut fields = BTreeMap::new();
+    let mut vars: Vec<_> = vars
+        .into_iter()
+        .filter(|(k, _)| k.starts_with(prefix) && k.len() > prefix.len())
+        .collect();
+    vars.sort();
+    for (name, value) in &vars {
+        let path: Vec<_> =
+            name[prefix.len()..].split(NESTING_SEPARATOR).collect();
+        insert(&mut fields, &path, value);
+    }
+    if fields.is_empty() {
+        return expr;
+    }
+    // Bind the base expression so that it isn't duplicated for each nested
+    // field. Overrides are closed, so they can't capture the binding.
+    let base = Label::from("base");
+    let body = apply(rc(ExprF::Var(V(base.clone(), 0))), fields);
+    rc(ExprF::Let(base, None, expr, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::phase::Parsed;
+
+    #[test]
+    fn overlay_nested_fields() {
+        let vars = vec![
+            ("APP_DB__PORT", "5432"),
+            ("APP_NAME", "hello world"),
+            ("APP_DEBUG", "True"),
+            ("APP_PATH", "./etc/passwd"),
+            ("OTHER", "1"),
+        ];
+        let vars = vars.into_iter().map(|(k, v)| (k.to_owned(), v.to_owned()));
+        let expr = Parsed::parse_str(
+            "{ db = { host = \"localhost\", port = 1 }, name = \"x\" }",
+        )
+        .unwrap()
+        .skip_resolve()
+        .unwrap()
+        .overlay_vars("APP_", vars)
+        .typecheck()
+        .unwrap()
+        .normalize();
+        assert_eq!(
+            expr.to_string(),
+            "{ db = { host = \"localhost\", port = 5432 }, debug = True, name = \"hello world\", path = \"./etc/passwd\" }"
+        );
+    }
+}
@@ -2,22 +2,52 @@ use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
-use dhall_syntax::parse_expr;
+use dhall_syntax::{parse_expr, parse_expr_with_name};
 
 use crate::error::Error;
 use crate::phase::resolve::ImportRoot;
 use crate::phase::Parsed;
 
+/// Editors on Windows often start UTF-8 files with a byte order mark, which is
+/// not part of the Dhall grammar. Only one is stripped: a second one is part
+/// of the text.
+fn strip_bom(s: &str) -> &str {
+    if s.starts_with('\u{FEFF}') {
+        &s['\u{FEFF}'.len_utf8()..]
+    } else {
+        s
+    }
+}
+
 pub(crate) fn parse_file(f: &Path) -> Result<Parsed, Error> {
-    let mut buffer = String::new();
-    File::open(f)?.read_to_string(&mut buffer)?;
-    let expr = parse_expr(&*buffer)?;
+    let mut buffer = Vec::new();
+    File::open(f)?.read_to_end(&mut buffer)?;
+    parse_file_contents(f, &buffer)
+}
+
+/// Parses the given contents as if they had been read from `f`.
+pub(crate) fn parse_file_contents(
+    f: &Path,
+    buffer: &[u8],
+) -> Result<Parsed, Error> {
+    let text = std::str::from_utf8(buffer)
+        .map_err(|e| Error::InvalidUtf8(f.to_owned(), e.valid_up_to()))?;
+    let expr = parse_expr_with_name(&f.to_string_lossy(), strip_bom(text))?;
     let root = ImportRoot::LocalDir(f.parent().unwrap().to_owned());
     Ok(Parsed(expr, root))
 }
 
 pub(crate) fn parse_str(s: &str) -> Result<Parsed, Error> {
-    let expr = parse_expr(s)?;
+    let expr = parse_expr(strip_bom(s))?;
+    let root = ImportRoot::LocalDir(std::env::current_dir()?);
+    Ok(Parsed(expr, root))
+}
+
+pub(crate) fn parse_str_with_name(
+    name: &str,
+    s: &str,
+) -> Result<Parsed, Error> {
+    let expr = parse_expr_with_name(name, strip_bom(s))?;
     let root = ImportRoot::LocalDir(std::env::current_dir()?);
     Ok(Parsed(expr, root))
 }
@@ -35,3 +65,382 @@ pub(crate) fn parse_binary_file(f: &Path) -> Result<Parsed, Error> {
     let root = ImportRoot::LocalDir(f.parent().unwrap().to_owned());
     Ok(Parsed(expr, root))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use dhall_syntax::{Hash, HashAlgorithm, HashError};
+
+    use crate::error::Error;
+    use crate::phase::Parsed;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn parse_file_handles_bom_and_invalid_utf8() {
+        let dir = TempDir::new();
+        let bom = dir.join("bom.dhall");
+        fs::write(&bom, b"\xEF\xBB\xBF{ x = 1 }").unwrap();
+        let parsed = Parsed::parse_file(&bom).unwrap();
+        assert_eq!(parsed.to_string(), "{ x = 1 }");
+        fs::write(&bom, b"\xEF\xBB\xBF\xEF\xBB\xBF{ x = 1 }").unwrap();
+        match Parsed::parse_file(&bom) {
+            Err(Error::Parse(_)) => {}
+            res => panic!("Expected a parse error, got: {:?}", res),
+        }
+
+        let invalid = dir.join("invalid.dhall");
+        fs::write(&invalid, b"{ x = \"caf\xE9\" }").unwrap();
+        match Parsed::parse_file(&invalid) {
+            Err(Error::InvalidUtf8(path, 10)) => assert_eq!(path, invalid),
+            res => panic!("Expected InvalidUtf8, got: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn reject_malformed_hashes() {
+        let hex = "ab".repeat(32);
+        for hash in &[
+            "sha256:zz".to_owned(),
+            format!("sha256:{}", &hex[2..]),
+            format!("sha256:{}ab", hex),
+            format!("sha256:{}zz", &hex[2..]),
+            format!("md5:{}", hex),
+        ] {
+            match Parsed::parse_str(&format!("./x.dhall {}", hash)) {
+                Err(Error::Parse(_)) => {}
+                res => panic!("Expected a parse error, got: {:?}", res),
+            }
+        }
+
+        let parse = |s: &str| s.parse::<Hash>();
+        assert!(parse(&format!("sha256:{}", hex)).is_ok());
+        assert_eq!(parse(&hex), Err(HashError::MissingAlgorithm));
+        assert_eq!(
+            parse("md5:00"),
+            Err(HashError::UnknownAlgorithm("md5".to_owned()))
+        );
+        assert_eq!(
+            parse("sha256:zz"),
+            Err(HashError::InvalidHex("zz".to_owned()))
+        );
+        assert_eq!(
+            parse("sha256:abcd"),
+            Err(HashError::WrongLength {
+                algorithm: HashAlgorithm::SHA256,
+                expected: 32,
+                found: 2,
+            })
+        );
+        assert!(Hash::new(HashAlgorithm::SHA256, vec![0; 31]).is_err());
+        let hash = Hash::new(HashAlgorithm::SHA256, vec![0xab; 32]).unwrap();
+        assert_eq!(hash.to_string(), format!("sha256:{}", hex));
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        let parse = |s: &str| Parsed::parse_str(s).map(|p| p.to_string());
+        assert_eq!(
+            parse(r#""\u0041\u{1F600}\u{0000041}""#).unwrap(),
+            "\"A😀A\""
+        );
+        for s in &[
+            r#""\uD800""#,
+            r#""\u{110000}""#,
+            r#""\u{FFFFFFFFF}""#,
+            r#""\uFFFF""#,
+        ] {
+            match parse(s) {
+                Err(Error::Parse(e)) => {
+                    // The error points at the escape sequence
+                    let msg = e.to_string();
+                    assert!(msg.contains("Escape sequences can't"), "{}", msg);
+                }
+                res => panic!("Expected a parse error, got: {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn minimal_text_escapes() {
+        let print = |s: &str| Parsed::parse_str(s).unwrap().to_string();
+        let src = r#""a/b $x \${y} \u0024{z} ${ "w" }$""#;
+        assert_eq!(print(src), r#""a/b $x \${y} \${z} ${ "w" }$""#);
+        assert_eq!(print(&print(src)), print(src));
+        // `Text/show` still escapes every `$`, as the standard specifies
+        let shown = Parsed::parse_str(r#"Text/show "$x""#)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize()
+            .to_string();
+        assert_eq!(shown, r#""\"\\u0024x\"""#);
+    }
+
+    #[test]
+    fn hexadecimal_literals() {
+        let parse = |s: &str| Parsed::parse_str(s).unwrap();
+        for (hex, decimal) in &[
+            ("0xFF", "255"),
+            ("0x0", "0"),
+            ("+0x10", "+16"),
+            ("-0xa", "-10"),
+            ("[ 0x1, 0x2 ]", "[1, 2]"),
+        ] {
+            assert_eq!(parse(hex).to_string(), *decimal);
+            assert_eq!(
+                parse(hex).encode().unwrap(),
+                parse(decimal).encode().unwrap()
+            );
+        }
+        assert!(Parsed::parse_str("0xFFFFFFFFFFFFFFFFFF").is_err());
+    }
+
+    #[test]
+    fn legacy_union_literals() {
+        match Parsed::parse_str("< A : Natural | B = True >") {
+            Err(Error::Parse(e)) => {
+                let msg = e.to_string();
+                assert!(msg.contains("union literals were removed"), "{}", msg);
+                assert!(msg.contains("`< B : T | ... >.B value`"), "{}", msg);
+            }
+            res => panic!("Expected a parse error, got: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn spans_are_comparable() {
+        use dhall_syntax::ExprF;
+        use std::collections::BTreeMap;
+        let field_spans = |name: &str| {
+            let parsed =
+                Parsed::parse_str_with_name(name, "{ a = 1, b = 2 }").unwrap();
+            match parsed.0.as_ref() {
+                ExprF::RecordLit(kvs) => kvs
+                    .iter()
+                    .map(|(_, v)| v.span().unwrap().clone())
+                    .collect::<Vec<_>>(),
+                e => panic!("Expected a record, got: {:?}", e),
+            }
+        };
+        let first = field_spans("first.dhall");
+        let second = field_spans("second.dhall");
+        assert!(first[0] < first[1]);
+        assert_ne!(first[0], second[0]);
+        assert_eq!(first[0].as_str(), second[0].as_str());
+        let names: BTreeMap<_, _> = first
+            .iter()
+            .chain(&second)
+            .map(|span| (span.clone(), span.source_name().unwrap()))
+            .collect();
+        assert_eq!(names.len(), 4);
+        assert_eq!(names[&second[1]], "second.dhall");
+    }
+
+    #[test]
+    fn let_blocks_print_flat() {
+        let print = |s: &str| Parsed::parse_str(s).unwrap().to_string();
+        assert_eq!(
+            print("let x = 1 in let y = x in y"),
+            "let x = 1 let y = x in y"
+        );
+        assert_eq!(
+            print("let x = 1 let y = x in y"),
+            "let x = 1 let y = x in y"
+        );
+    }
+
+    #[test]
+    fn print_parentheses_by_precedence() {
+        let print = |s: &str| Parsed::parse_str(s).unwrap().to_string();
+        for (src, printed) in &[
+            // Operators are left-associative, so only the right operand
+            // needs parentheses when it uses the same operator
+            ("a + (b + c)", "a + (b + c)"),
+            ("(a + b) + c", "a + b + c"),
+            ("(a || b) + c * (d + e)", "(a || b) + c * (d + e)"),
+            ("a == (b == c) == d", "a == (b == c) == d"),
+            ("f (assert : True)", "f (assert : True)"),
+            ("[] : (List Natural : Type)", "[] : (List Natural : Type)"),
+        ] {
+            assert_eq!(print(src), *printed);
+            assert_eq!(print(printed), *printed);
+        }
+    }
+
+    #[test]
+    fn check_reports_diagnostics() {
+        assert!(dhall_syntax::check("{ x = 1 }").is_empty());
+        let diagnostics = dhall_syntax::check("{ x = 1,\n  y = }");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let expr: dhall_syntax::Expr<()> =
+            dhall_syntax::parse_expr("λ(x : { a : Text }) → [ x.a, \"b\" ]")
+                .unwrap();
+        let data = serde_cbor::to_vec(&expr).unwrap();
+        let decoded: dhall_syntax::Expr<()> =
+            serde_cbor::from_slice(&data).unwrap();
+        assert_eq!(decoded, expr);
+        assert!(decoded.span().is_none());
+    }
+
+    #[test]
+    fn preserve_field_order() {
+        use dhall_syntax::PrintOptions;
+        let parsed = Parsed::parse_str("{ b = 1, a = 2 }").unwrap();
+        let options = PrintOptions {
+            preserve_field_order: true,
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            parsed.0.display_with(options).to_string(),
+            "{ b = 1, a = 2 }"
+        );
+        assert_eq!(parsed.to_string(), "{ a = 2, b = 1 }");
+    }
+
+    #[test]
+    fn canonical_printing_is_ascii() {
+        use dhall_syntax::PrintOptions;
+        let print = |s: &str| {
+            let parsed = Parsed::parse_str(s).unwrap();
+            parsed.0.display_with(PrintOptions::CANONICAL).to_string()
+        };
+        assert_eq!(
+            print("λ(t : Type) → ∀(x : t) → { a : t } ⩓ { b : t }"),
+            "\\(t : Type) -> forall(x : t) -> { a : t } //\\\\ { b : t }"
+        );
+        assert_eq!(print("a ⫽ b ∧ c"), "a // b /\\ c");
+        assert_eq!(print("(λ(x : Bool) → x) ≡ f"), "(\\(x : Bool) -> x) === f");
+        // The default options print the Unicode forms
+        let parsed = Parsed::parse_str("a ⫽ b").unwrap();
+        assert_eq!(parsed.to_string(), "a ⫽ b");
+    }
+
+    #[test]
+    fn gated_features_are_rejected_under_older_versions() {
+        use dhall_syntax::{parse_expr_with_version, Feature, StandardVersion};
+        let parse = |s: &str, version| {
+            parse_expr_with_version::<()>(s, version).map(|_| ())
+        };
+        assert!(parse("toMap {=} : List {}", StandardVersion::V8_0_0).is_ok());
+        let msg = parse("toMap {=} : List {}", StandardVersion::V7_0_0)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            msg.contains(
+                &Feature::ToMap.unavailable_message(StandardVersion::V7_0_0)
+            ),
+            "{}",
+            msg
+        );
+
+        let parsed = Parsed::parse_str("assert : 1 === 1").unwrap();
+        assert!(parsed
+            .check_standard_version(StandardVersion::V9_0_0)
+            .is_ok());
+        match parsed.check_standard_version(StandardVersion::V8_0_0) {
+            Err(Error::UnsupportedFeature(Feature::Assert, _)) => {}
+            res => panic!("Expected UnsupportedFeature, got: {:?}", res),
+        }
+        assert_eq!(
+            Feature::Assert.check_available_in(StandardVersion::V8_0_0),
+            Err(Feature::Assert.unavailable_message(StandardVersion::V8_0_0))
+        );
+    }
+
+    #[test]
+    fn standard_versions() {
+        use dhall_syntax::{Feature, StandardVersion};
+        use StandardVersion::*;
+        // The match fails to compile if a version is added without being
+        // listed here.
+        let all = [V7_0_0, V8_0_0, V9_0_0, V10_0_0, V17_0_0];
+        let index = |version| match version {
+            V7_0_0 => 0,
+            V8_0_0 => 1,
+            V9_0_0 => 2,
+            V10_0_0 => 3,
+            V17_0_0 => 4,
+        };
+        for &version in &all {
+            assert_eq!(all[index(version)], version);
+        }
+        assert_eq!(all.iter().max(), Some(&StandardVersion::latest()));
+        assert!(StandardVersion::implemented() <= StandardVersion::latest());
+
+        assert_eq!(
+            Feature::OptionalFold.unavailable_message(V17_0_0),
+            "`Optional/fold` is not available in version 17.0.0 of the \
+             standard; it was removed in 17.0.0; use `merge` on the \
+             `Optional` instead"
+        );
+        assert!(Feature::OptionalBuild
+            .unavailable_message(V17_0_0)
+            .ends_with(
+                "; build the `Optional` with `Some` and `None` instead"
+            ));
+        assert!(Feature::ToMap
+            .unavailable_message(V7_0_0)
+            .ends_with("; it was introduced in 8.0.0"));
+    }
+
+    #[test]
+    fn operator_precedence_follows_the_grammar() {
+        use dhall_syntax::BinOp::{self, *};
+        // The token of each operator in the standard grammar. The match
+        // fails to compile if an operator is added without its token.
+        let token = |op: BinOp| match op {
+            ImportAlt => "\"?\"",
+            BoolOr => "\"||\"",
+            NaturalPlus => "\"+\"",
+            TextAppend => "\"++\"",
+            ListAppend => "\"#\"",
+            BoolAnd => "\"&&\"",
+            RecursiveRecordMerge => "combine",
+            RightBiasedRecordMerge => "prefer",
+            RecursiveRecordTypeMerge => "combine-types",
+            NaturalTimes => "\"*\"",
+            BoolEQ => "\"==\"",
+            BoolNE => "\"!=\"",
+            Equivalence => "equivalent",
+        };
+        // The grammar nests one rule per operator, from the loosest to the
+        // tightest binding, e.g.
+        // `or-expression = plus-expression *(whsp "||" whsp plus-expression)`
+        let grammar =
+            include_str!("../../../dhall_generated_parser/src/dhall.abnf");
+        let mut tokens = vec![];
+        let mut rule = "import-alt-expression";
+        while rule != "application-expression" {
+            let rhs = grammar
+                .lines()
+                .find_map(|line| {
+                    let mut sides = line.splitn(2, '=');
+                    match (sides.next(), sides.next()) {
+                        (Some(lhs), Some(rhs)) if lhs.trim() == rule => {
+                            Some(rhs)
+                        }
+                        _ => None,
+                    }
+                })
+                .unwrap_or_else(|| panic!("No rule `{}`", rule));
+            let mut words = rhs.split_whitespace();
+            let next = words.next().unwrap();
+            assert_eq!(words.next(), Some("*(whsp"), "{}", rhs);
+            tokens.push(words.next().unwrap());
+            rule = next;
+        }
+        let expected: Vec<_> = BinOp::ALL.iter().map(|&op| token(op)).collect();
+        assert_eq!(tokens, expected);
+        for (i, op) in BinOp::ALL.iter().enumerate() {
+            assert_eq!(op.precedence(), i);
+        }
+    }
+}
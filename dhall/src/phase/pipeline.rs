@@ -0,0 +1,179 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use dhall_syntax::ExprF;
+
+use crate::error::Error;
+use crate::phase::{Normalized, NormalizedExpr, Parsed, ResolvedImport};
+
+/// How long each phase of an evaluation took and how large its result was,
+/// see `eval_file_with_report`. Phases that were not reached because an
+/// earlier one failed are `None`.
+///
+/// It is printed with one tab-separated line per phase that ran: the name of
+/// the phase, its duration in microseconds and its node count, followed by
+/// one `import` line per import with its duration and location.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineReport {
+    /// Counts the nodes of the parsed expression
+    pub parse: Option<PhaseReport>,
+    /// Counts the nodes of the resolved expression, including those of the
+    /// imported expressions
+    pub resolve: Option<PhaseReport>,
+    /// Counts the nodes of the normalized type
+    pub typecheck: Option<PhaseReport>,
+    /// Counts the nodes of the normal form
+    pub normalize: Option<PhaseReport>,
+    /// Every import that was resolved, including those in imported files,
+    /// in the order they were resolved. Each has the time it took.
+    pub imports: Vec<ResolvedImport>,
+}
+
+/// The measurements of one phase in a `PipelineReport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseReport {
+    /// Wall-clock time spent in the phase
+    pub duration: Duration,
+    /// Number of nodes in the syntax tree of the result of the phase
+    pub nodes: usize,
+}
+
+/// Evaluates a file like `eval_files_sequentially`, measuring each phase as it
+/// goes. The report is returned even if evaluation fails, with the phases that
+/// ran.
+///
+/// Counting the nodes of the type and of the normal form reads them back into
+/// expressions, as do the nodes of imported files, which costs about as much
+/// as the phase itself: this can take up to twice as long as evaluating the
+/// file. The durations don't include the counting.
+pub fn eval_file_with_report(
+    path: &Path,
+) -> (Result<Normalized, Error>, PipelineReport) {
+    let mut report = PipelineReport::default();
+    let result = eval_file(path, &mut report);
+    (result, report)
+}
+
+fn eval_file(
+    path: &Path,
+    report: &mut PipelineReport,
+) -> Result<Normalized, Error> {
+    let start = Instant::now();
+    let parsed = Parsed::parse_file(path)?;
+    report.parse = Some(PhaseReport {
+        duration: start.elapsed(),
+        nodes: count_nodes(&parsed.0),
+    });
+
+    let start = Instant::now();
+    let (resolved, resolution) = parsed.resolve_with_report()?;
+    report.imports = resolution.imports;
+    report.resolve = Some(PhaseReport {
+        duration: start.elapsed(),
+        nodes: count_nodes(&resolved.0),
+    });
+
+    let start = Instant::now();
+    let typed = resolved.typecheck()?;
+    report.typecheck = Some(PhaseReport {
+        duration: start.elapsed(),
+        nodes: count_nodes(&typed.type_expr()?),
+    });
+
+    let start = Instant::now();
+    let normalized = typed.normalize();
+    report.normalize = Some(PhaseReport {
+        duration: start.elapsed(),
+        nodes: normalized.stats().nodes,
+    });
+    Ok(normalized)
+}
+
+/// Counts the nodes of an expression, including those of the normalized
+/// expressions embedded in it.
+fn count_nodes(e: &NormalizedExpr) -> usize {
+    fn go(e: &NormalizedExpr, nodes: &mut usize) {
+        match e.as_ref() {
+            ExprF::Embed(n) => *nodes += n.stats().nodes,
+            e => {
+                *nodes += 1;
+                e.map_ref(|e| go(e, nodes));
+            }
+        }
+    }
+    let mut nodes = 0;
+    go(e, &mut nodes);
+    nodes
+}
+
+impl std::fmt::Display for PipelineReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let phases = [
+            ("parse", &self.parse),
+            ("resolve", &self.resolve),
+            ("typecheck", &self.typecheck),
+            ("normalize", &self.normalize),
+        ];
+        for (name, phase) in phases.iter() {
+            if let Some(phase) = phase {
+                writeln!(
+                    f,
+                    "{}\t{}\t{}",
+                    name,
+                    phase.duration.as_micros(),
+                    phase.nodes
+                )?;
+            }
+        }
+        for import in &self.imports {
+            writeln!(
+                f,
+                "import\t{}\t{}",
+                import.duration.as_micros(),
+                import.location
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::eval_file_with_report;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn report_each_phase() {
+        let dir = TempDir::new();
+        fs::write(dir.join("common.dhall"), "{ port = 80 }").unwrap();
+        fs::write(dir.join("good.dhall"), "(./common.dhall).port + 1").unwrap();
+        fs::write(dir.join("bad.dhall"), "./common.dhall + 1").unwrap();
+
+        let (result, report) = eval_file_with_report(&dir.join("good.dhall"));
+        assert_eq!(result.unwrap().to_string(), "81");
+        // `+`, the selection, the import and `1`, then the import is
+        // replaced by the two nodes of `{ port = 80 }`
+        assert_eq!(report.parse.unwrap().nodes, 4);
+        assert_eq!(report.resolve.unwrap().nodes, 5);
+        assert_eq!(report.typecheck.unwrap().nodes, 1);
+        assert_eq!(report.normalize.unwrap().nodes, 1);
+        assert_eq!(report.imports.len(), 1);
+        let lines: Vec<_> = report
+            .to_string()
+            .lines()
+            .map(|l| l.split('\t').next().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            lines,
+            vec!["parse", "resolve", "typecheck", "normalize", "import"]
+        );
+
+        let (result, report) = eval_file_with_report(&dir.join("bad.dhall"));
+        assert!(result.is_err());
+        assert!(report.resolve.is_some());
+        assert!(report.typecheck.is_none());
+        assert!(report.normalize.is_none());
+    }
+}
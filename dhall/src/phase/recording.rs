@@ -0,0 +1,165 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::phase::binary;
+
+const INDEX: &str = "index";
+
+/// How a `Recording` is used when resolving imports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecordingMode {
+    /// Replay the imports that were recorded, and record the others.
+    Auto,
+    /// Only replay: an import that was not recorded is an error, so nothing
+    /// is read from its actual location.
+    Replay,
+    /// Read every import from its actual location, and record it again.
+    Record,
+}
+
+/// The contents of imported files, recorded to make later resolutions
+/// hermetic.
+///
+/// Each import is stored as a file named after the SHA-256 of its location,
+/// holding the exact bytes that were read. An `index` file records the
+/// location of each entry, one `<name> <location>` line per entry. Local files
+/// under the directory that was current when the recording was opened are
+/// recorded by their path relative to it, so that a recording checked in with
+/// the sources replays in another checkout.
+///
+/// Only local files are recorded and replayed. Remote imports cannot be
+/// fetched yet, so they cannot be recorded either; recording them is what
+/// will make configs that import from the network hermetic, and it is still
+/// to be done.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    dir: PathBuf,
+    mode: RecordingMode,
+    base: Option<PathBuf>,
+}
+
+impl Recording {
+    /// Opens the recording in the given directory, creating it if needed.
+    pub fn open(
+        dir: impl Into<PathBuf>,
+        mode: RecordingMode,
+    ) -> Result<Recording, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let base = std::env::current_dir()
+            .ok()
+            .and_then(|d| fs::canonicalize(d).ok());
+        Ok(Recording { dir, mode, base })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn mode(&self) -> RecordingMode {
+        self.mode
+    }
+
+    /// The location under which a local file is recorded.
+    fn local_location(&self, path: &Path) -> String {
+        let relative =
+            self.base.as_ref().and_then(|b| path.strip_prefix(b).ok());
+        match relative {
+            Some(relative) => format!("./{}", relative.display()),
+            None => path.display().to_string(),
+        }
+    }
+
+    /// The file that holds, or would hold, the entry for this location.
+    fn entry_path(&self, location: &str) -> PathBuf {
+        self.dir.join(binary::hash_bytes(location.as_bytes()).hex())
+    }
+
+    /// The contents of the local file at `path`, replayed or recorded
+    /// according to the mode. Returns `None` if the file was not recorded and
+    /// the mode is `Replay`.
+    pub(crate) fn read_file(
+        &self,
+        path: &Path,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let location = self.local_location(path);
+        let entry = self.entry_path(&location);
+        if self.mode != RecordingMode::Record && entry.is_file() {
+            return Ok(Some(fs::read(&entry)?));
+        }
+        if self.mode == RecordingMode::Replay {
+            return Ok(None);
+        }
+        let contents = fs::read(path)?;
+        let is_new = !entry.is_file();
+        binary::write_atomically(&entry, &contents)?;
+        if is_new {
+            let mut index = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(INDEX))?;
+            let name = entry.file_name().unwrap().to_string_lossy();
+            writeln!(index, "{} {}", name, location)?;
+        }
+        Ok(Some(contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Recording, RecordingMode};
+    use crate::error::ImportError;
+    use crate::phase::Parsed;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn record_and_replay_imports() {
+        let dir = TempDir::new();
+        let sources = dir.join("sources");
+        fs::create_dir_all(&sources).unwrap();
+        fs::write(sources.join("a.dhall"), "./b.dhall + 1").unwrap();
+        fs::write(sources.join("b.dhall"), "1").unwrap();
+        let eval = |recording: &Recording| {
+            let parsed = Parsed::parse_file(&sources.join("a.dhall")).unwrap();
+            parsed.resolve_with_recording(recording).map(|resolved| {
+                resolved.typecheck().unwrap().normalize().to_string()
+            })
+        };
+
+        let recording =
+            Recording::open(dir.join("recording"), RecordingMode::Auto)
+                .unwrap();
+        assert_eq!(eval(&recording).unwrap(), "2");
+        let index = fs::read_to_string(dir.join("recording/index")).unwrap();
+        assert_eq!(index.lines().count(), 1);
+
+        // Replaying doesn't read the file again
+        fs::write(sources.join("b.dhall"), "2").unwrap();
+        assert_eq!(eval(&recording).unwrap(), "2");
+        let replay =
+            Recording::open(dir.join("recording"), RecordingMode::Replay)
+                .unwrap();
+        assert_eq!(eval(&replay).unwrap(), "2");
+
+        // Recording again picks up the change
+        let record =
+            Recording::open(dir.join("recording"), RecordingMode::Record)
+                .unwrap();
+        assert_eq!(eval(&record).unwrap(), "3");
+        assert_eq!(eval(&replay).unwrap(), "3");
+
+        // Nothing is read in replay mode
+        let empty =
+            Recording::open(dir.join("empty"), RecordingMode::Replay).unwrap();
+        match eval(&empty) {
+            Err(ImportError::NotRecorded(import)) => {
+                assert_eq!(import.to_string(), "./b.dhall")
+            }
+            res => panic!("Expected NotRecorded, got: {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::cell::Cell;
+
+use dhall_syntax::{rc, Builtin, ExprF, Label};
+
+use crate::core::value::Value;
+use crate::core::valuef::ValueF;
+use crate::error::TypeError;
+use crate::phase::{typecheck, Normalized, Typed};
+
+/// One node of a normalized value, with its children as values.
+pub type Node = ExprF<Normalized, Normalized>;
+
+type Pass = Box<dyn Fn(&Node) -> Option<Node>>;
+
+/// User-supplied rewrites to run on normalized values, e.g. to remove the
+/// fields that are `None` or to add metadata to every record.
+///
+/// The passes are run on every node of the value, children first, and
+/// return the node to replace it with, if any. On each node, the passes run
+/// one after the other, in the order they were added; `ExprF::Embed` replaces
+/// a node with a whole value.
+///
+/// The value is not read back into an expression: only the nodes that were
+/// replaced, or whose children were, are typechecked again, one node at a
+/// time. The other nodes are kept as they are, with their source spans.
+#[derive(Default)]
+pub struct Rewriter {
+    passes: Vec<Pass>,
+}
+
+impl Rewriter {
+    pub fn new() -> Self {
+        Rewriter::default()
+    }
+
+    pub fn add_pass(
+        &mut self,
+        pass: impl Fn(&Node) -> Option<Node> + 'static,
+    ) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn rewrite(&self, v: &Normalized) -> Result<Normalized, TypeError> {
+        if self.passes.is_empty() {
+            return Ok(v.clone());
+        }
+        Ok(self.rewrite_value(v)?.unwrap_or_else(|| v.clone()))
+    }
+
+    /// Returns `None` if nothing changed.
+    fn rewrite_value(
+        &self,
+        v: &Normalized,
+    ) -> Result<Option<Normalized>, TypeError> {
+        let changed = Cell::new(false);
+        let rewrite_child = |child: &Normalized| {
+            Ok(match self.rewrite_value(child)? {
+                Some(child) => {
+                    changed.set(true);
+                    child
+                }
+                None => child.clone(),
+            })
+        };
+        let mut node = node(v).traverse_ref_with_special_handling_of_binders(
+            &rewrite_child,
+            |_, child| rewrite_child(child),
+        )?;
+        for pass in &self.passes {
+            if let Some(new) = pass(&node) {
+                node = new;
+                changed.set(true);
+            }
+        }
+        if !changed.get() {
+            return Ok(None);
+        }
+
+        let value = match node {
+            ExprF::Embed(new) => new.0.into_value(),
+            node => typecheck::typecheck(rc(
+                node.map_ref(|child| rc(ExprF::Embed(child.clone())))
+            ))?,
+        };
+        let value = match v.0.to_value().span() {
+            Some(span) => value.with_span_if_missing(&span),
+            None => value,
+        };
+        Ok(Some(Typed::from_value(value).normalize()))
+    }
+}
+
+/// See `Normalized::as_node`. Nodes that can't hold values, like types and
+/// functions, are returned whole as `ExprF::Embed`.
+pub(crate) fn node(v: &Normalized) -> Node {
+    let child = |v: &Value| Typed::from_value(v.clone()).normalize();
+    let value = v.0.to_value();
+    let valuef = value.as_whnf();
+    match &*valuef {
+        ValueF::BoolLit(b) => ExprF::BoolLit(*b),
+        ValueF::NaturalLit(n) => ExprF::NaturalLit(*n),
+        ValueF::IntegerLit(n) => ExprF::IntegerLit(*n),
+        ValueF::DoubleLit(n) => ExprF::DoubleLit(*n),
+        ValueF::EmptyOptionalLit(t) => ExprF::App(
+            child(&Value::from_builtin(Builtin::OptionalNone)),
+            child(t),
+        ),
+        ValueF::NEOptionalLit(x) => ExprF::SomeLit(child(x)),
+        ValueF::EmptyListLit(t) => ExprF::EmptyListLit(child(
+            &Value::from_builtin(Builtin::List).app(t.clone()),
+        )),
+        ValueF::NEListLit(xs) => {
+            ExprF::NEListLit(xs.iter().map(child).collect())
+        }
+        ValueF::RecordLit(kvs) => ExprF::RecordLit(
+            kvs.iter().map(|(k, v)| (k.clone(), child(v))).collect(),
+        ),
+        ValueF::TextLit(elts) => ExprF::TextLit(
+            elts.iter()
+                .map(|contents| contents.map_ref(child))
+                .collect(),
+        ),
+        ValueF::UnionLit(l, x, _) => {
+            ExprF::App(constructor(&value, l.clone()), child(x))
+        }
+        _ => ExprF::Embed(v.clone()),
+    }
+}
+
+/// The constructor of the alternative `l` of the union value `v`.
+fn constructor(v: &Value, l: Label) -> Normalized {
+    let ty = Typed::from_value(v.get_type_not_sort()).normalize();
+    typecheck::typecheck(rc(ExprF::Field(rc(ExprF::Embed(ty)), l)))
+        .expect("Internal type error")
+        .into_typed()
+        .normalize()
+}
+
+impl std::fmt::Debug for Rewriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Rewriter({} passes)", self.passes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dhall_syntax::ExprF;
+
+    use super::Rewriter;
+    use crate::phase::{Normalized, Parsed};
+
+    fn normalized(src: &str) -> Normalized {
+        Parsed::parse_str(src)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize()
+    }
+
+    #[test]
+    fn rewrite_normalized_records() {
+        let e = normalized(
+            "{ a = None Natural, b = { c = Some 1, d = None Text } }",
+        );
+        let is_none = |v: &Normalized| match v.as_node() {
+            ExprF::App(f, _) => f.to_string() == "None",
+            _ => false,
+        };
+        let rewritten = Rewriter::new()
+            .add_pass(move |node| match node {
+                ExprF::RecordLit(kvs) => Some(ExprF::RecordLit(
+                    kvs.iter()
+                        .filter(|(_, v)| !is_none(v))
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect(),
+                )),
+                _ => None,
+            })
+            .add_pass(|node| match node {
+                ExprF::RecordLit(kvs) => {
+                    let mut kvs = kvs.clone();
+                    kvs.insert("audited".into(), normalized("True"));
+                    Some(ExprF::RecordLit(kvs))
+                }
+                _ => None,
+            })
+            .rewrite(&e)
+            .unwrap();
+        assert_eq!(
+            rewritten.to_string(),
+            "{ audited = True, b = { audited = True, c = Some 1 } }"
+        );
+    }
+
+    #[test]
+    fn rewrite_keeps_untouched_values() {
+        let e = normalized(
+            "{ a = [ < A : Natural | B >.A 1 ], b = \\(x : Bool) -> x }",
+        );
+        let rewritten = Rewriter::new()
+            .add_pass(|node| match node {
+                ExprF::NaturalLit(n) => Some(ExprF::NaturalLit(n + 1)),
+                _ => None,
+            })
+            .rewrite(&e)
+            .unwrap();
+        assert_eq!(
+            rewritten.to_string(),
+            "{ a = [< A: Natural | B >.A 2], b = λ(x : Bool) → x }"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use dhall_syntax::map::DupTreeMap;
+use dhall_syntax::{rc, Builtin as B, ExprF, Label};
+
+use crate::error::SchemaError;
+use crate::phase::{typecheck, Normalized, NormalizedExpr};
+
+/// A field of a record schema, as returned by `Normalized::schema_fields`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaField {
+    pub name: String,
+    /// The type of the field, from the `Type` record
+    pub ty: NormalizedExpr,
+    /// The value of the field in the `default` record, if any. Fields without
+    /// one are required.
+    pub default: Option<NormalizedExpr>,
+}
+
+impl SchemaField {
+    pub fn is_required(&self) -> bool {
+        self.default.is_none()
+    }
+}
+
+/// Splits a `{ Type = ..., default = ... }` record into its two parts.
+fn schema_parts(
+    schema: &Normalized,
+) -> Result<
+    (
+        Vec<(Label, NormalizedExpr)>,
+        BTreeMap<Label, NormalizedExpr>,
+    ),
+    SchemaError,
+> {
+    let expr = schema.to_expr();
+    let kvs = match expr.as_ref() {
+        ExprF::RecordLit(kvs) => kvs,
+        _ => return Err(SchemaError::NotASchema),
+    };
+    let mut fields = None;
+    let mut defaults = None;
+    for (k, v) in kvs.iter() {
+        match (String::from(k).as_str(), v.as_ref()) {
+            ("Type", ExprF::RecordType(kts)) => {
+                fields = Some(
+                    kts.iter().map(|(k, t)| (k.clone(), t.clone())).collect(),
+                )
+            }
+            ("default", ExprF::RecordLit(kvs)) => {
+                defaults = Some(
+                    kvs.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                )
+            }
+            _ => {}
+        }
+    }
+    match (fields, defaults) {
+        (Some(fields), Some(defaults)) => Ok((fields, defaults)),
+        _ => Err(SchemaError::NotASchema),
+    }
+}
+
+pub(crate) fn schema_fields(
+    schema: &Normalized,
+) -> Result<Vec<SchemaField>, SchemaError> {
+    let (fields, mut defaults) = schema_parts(schema)?;
+    Ok(fields
+        .into_iter()
+        .map(|(k, ty)| SchemaField {
+            name: String::from(&k),
+            ty,
+            default: defaults.remove(&k),
+        })
+        .collect())
+}
+
+/// Builds a placeholder value of the given type: `False`, `0`, `""`, empty
+/// lists, `None`, and the first alternative of unions.
+fn example_of(
+    ty: &NormalizedExpr,
+    path: &mut Vec<String>,
+) -> Result<NormalizedExpr, SchemaError> {
+    use ExprF::*;
+    let e = match ty.as_ref() {
+        Builtin(B::Bool) => BoolLit(false),
+        Builtin(B::Natural) => NaturalLit(0),
+        Builtin(B::Integer) => IntegerLit(0),
+        Builtin(B::Double) => DoubleLit(0.0.into()),
+        Builtin(B::Text) => TextLit(String::new().into()),
+        App(f, t) => match f.as_ref() {
+            Builtin(B::List) => EmptyListLit(ty.clone()),
+            Builtin(B::Optional) => {
+                App(rc(Builtin(B::OptionalNone)), t.clone())
+            }
+            _ => return Err(no_example(ty, path)),
+        },
+        RecordType(kts) => {
+            let mut kvs = DupTreeMap::new();
+            for (k, t) in kts.iter() {
+                path.push(String::from(k));
+                kvs.insert(k.clone(), example_of(t, path)?);
+                path.pop();
+            }
+            RecordLit(kvs)
+        }
+        UnionType(kts) => match kts.iter().next() {
+            Some((k, None)) => Field(ty.clone(), k.clone()),
+            Some((k, Some(t))) => {
+                path.push(String::from(k));
+                let x = example_of(t, path)?;
+                path.pop();
+                App(rc(Field(ty.clone(), k.clone())), x)
+            }
+            None => return Err(no_example(ty, path)),
+        },
+        _ => return Err(no_example(ty, path)),
+    };
+    Ok(rc(e))
+}
+
+fn no_example(ty: &NormalizedExpr, path: &[String]) -> SchemaError {
+    SchemaError::NoExample {
+        path: path.to_vec(),
+        ty: ty.to_string(),
+    }
+}
+
+pub(crate) fn schema_example(
+    schema: &Normalized,
+) -> Result<Normalized, SchemaError> {
+    let (fields, mut defaults) = schema_parts(schema)?;
+    let mut kts = DupTreeMap::new();
+    let mut kvs = DupTreeMap::new();
+    let mut path = Vec::new();
+    for (k, ty) in fields {
+        let value = match defaults.remove(&k) {
+            Some(v) => v,
+            None => {
+                path.push(String::from(&k));
+                let v = example_of(&ty, &mut path)?;
+                path.pop();
+                v
+            }
+        };
+        kvs.insert(k.clone(), value);
+        kts.insert(k, ty);
+    }
+    // Annotate with the schema's type to catch defaults of the wrong type.
+    let expr = rc(ExprF::Annot(
+        rc(ExprF::RecordLit(kvs)),
+        rc(ExprF::RecordType(kts)),
+    ));
+    let typed = typecheck::typecheck(expr).map_err(SchemaError::Typecheck)?;
+    Ok(typed.into_typed().normalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::phase::Parsed;
+
+    #[test]
+    fn schema_required_fields_and_example() {
+        let schema = Parsed::parse_str(
+            "{ Type = { name : Text, port : Natural, tags : List Text, \
+             mode : < Fast | Slow > }, default = { port = 80 } }",
+        )
+        .unwrap()
+        .skip_resolve()
+        .unwrap()
+        .typecheck()
+        .unwrap()
+        .normalize();
+        let required: Vec<_> = schema
+            .schema_fields()
+            .unwrap()
+            .into_iter()
+            .filter(|f| f.is_required())
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(required, vec!["mode", "name", "tags"]);
+        assert_eq!(
+            schema.schema_example().unwrap().to_string(),
+            "{ mode = < Fast | Slow >.Fast, name = \"\", port = 80, tags = [] : List Text }"
+        );
+    }
+}
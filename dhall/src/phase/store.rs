@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use dhall_syntax::Hash;
+
+use crate::error::{DecodeError, Error, ImportError};
+use crate::phase::{binary, Normalized, Parsed};
+
+const MANIFEST: &str = "manifest";
+
+/// A content-addressed store of normalized expressions.
+///
+/// Entries are laid out like the standard import cache: each one is a file
+/// named `1220<hex>` holding the binary encoding of the alpha-normalized
+/// expression. A `manifest` file records where each entry was vendored from,
+/// one `sha256:<hex> <name>` line per entry.
+///
+/// When resolving with a store, imports protected by a hash are looked up in
+/// the store first and only fetched if missing. Only local files can be
+/// fetched and vendored for now: remote imports are not supported yet.
+#[derive(Debug, Clone)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    /// Opens the store in the given directory, creating it if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Store, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Store { dir })
+    }
+
+    /// Opens the standard import cache, shared with other implementations
+    /// such as dhall-haskell, see `standard_cache_dir`.
+    pub fn open_standard_cache() -> Result<Store, Error> {
+        let dir = Store::standard_cache_dir().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no cache directory: set DHALL_CACHE, XDG_CACHE_HOME or HOME",
+            )
+        })?;
+        Store::open(dir)
+    }
+
+    /// Where the standard import cache is: `$DHALL_CACHE` if set, otherwise
+    /// `$XDG_CACHE_HOME/dhall`, `$HOME/.cache/dhall`, or on Windows
+    /// `%LOCALAPPDATA%/dhall`.
+    pub fn standard_cache_dir() -> Option<PathBuf> {
+        cache_dir_from(|name| env::var_os(name).filter(|v| !v.is_empty()))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file that holds, or would hold, the entry with this hash. Writing
+    /// the binary encoding of the alpha-normalized expression there, e.g.
+    /// with `Normalized::encode_with_hash`, pre-seeds the cache.
+    pub fn entry_path(&self, hash: &Hash) -> PathBuf {
+        self.dir.join(hash.multihash_hex())
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.entry_path(hash).is_file()
+    }
+
+    /// Looks up an entry, checking that its contents match the hash and are
+    /// the encoding of a well-typed, normalized expression.
+    pub fn get(&self, hash: &Hash) -> Result<Option<Normalized>, Error> {
+        let path = self.entry_path(hash);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        File::open(&path)?.read_to_end(&mut data)?;
+        if !binary::verify_hash(&data, hash) {
+            let found = binary::hash_bytes(&data);
+            return Err(ImportError::HashMismatch(hash.clone(), found).into());
+        }
+        let expr = Parsed::parse_binary(&data)?
+            .skip_resolve()?
+            .typecheck()?
+            .normalize();
+        // Bytes that aren't in normal form could still match the hash, but
+        // wouldn't be the expression the hash protects.
+        let found = expr.semantic_hash()?;
+        if &found != hash {
+            return Err(ImportError::HashMismatch(hash.clone(), found).into());
+        }
+        Ok(Some(expr))
+    }
+
+    /// Adds an expression to the store without recording it in the manifest.
+    /// The entry is written atomically, since other implementations may read
+    /// the same directory concurrently.
+    pub fn insert(&self, expr: &Normalized) -> Result<Hash, Error> {
+        let (hash, data) = expr.encode_with_hash()?;
+        let path = self.entry_path(&hash);
+        if !path.is_file() {
+            binary::write_atomically(&path, &data)?;
+        }
+        Ok(hash)
+    }
+
+    /// Adds an expression to the store and records in the manifest that it
+    /// was obtained from `name`, typically the import it was resolved from.
+    pub fn vendor(&self, name: &str, expr: &Normalized) -> Result<Hash, Error> {
+        let hash = self.insert(expr)?;
+        if self.manifest()?.get(name) != Some(&hash) {
+            let mut manifest = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(MANIFEST))?;
+            writeln!(manifest, "{} {}", hash, name)?;
+        }
+        Ok(hash)
+    }
+
+    /// Resolves, typechecks and normalizes the given file, and vendors the
+    /// result under the file's path.
+    pub fn vendor_file(&self, path: &Path) -> Result<Hash, Error> {
+        let expr = Parsed::parse_file(path)?
+            .resolve_with_store(self)?
+            .typecheck()?
+            .normalize();
+        self.vendor(&path.to_string_lossy(), &expr)
+    }
+
+    /// The vendored entries, by name. Later lines override earlier ones.
+    pub fn manifest(&self) -> Result<BTreeMap<String, Hash>, Error> {
+        let path = self.dir.join(MANIFEST);
+        if !path.is_file() {
+            return Ok(BTreeMap::new());
+        }
+        let mut contents = String::new();
+        File::open(&path)?.read_to_string(&mut contents)?;
+        let mut entries = BTreeMap::new();
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let mut parts = line.splitn(2, ' ');
+            let hash = parts.next().unwrap_or("");
+            let name = parts.next().ok_or_else(|| {
+                DecodeError::WrongFormatError(format!(
+                    "store/manifest/missing_name: {}",
+                    line
+                ))
+            })?;
+            let hash = hash.parse().map_err(|e| {
+                DecodeError::WrongFormatError(format!("store/manifest/{}", e))
+            })?;
+            entries.insert(name.to_owned(), hash);
+        }
+        Ok(entries)
+    }
+}
+
+fn cache_dir_from(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    if let Some(dir) = var("DHALL_CACHE") {
+        return Some(dir.into());
+    }
+    let base = match var("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(var("LOCALAPPDATA")?),
+        None => PathBuf::from(var("HOME")?).join(".cache"),
+    };
+    Some(base.join("dhall"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    use super::{cache_dir_from, Store};
+    use crate::phase::Parsed;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn vendor_and_resolve_from_store() {
+        let dir = TempDir::new();
+        let store = Store::open(dir.to_path_buf()).unwrap();
+        let expr = Parsed::parse_str("{ x = 1, y = [ True ] }")
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize();
+        let hash = store.vendor("example", &expr).unwrap();
+        store.vendor("example", &expr).unwrap();
+
+        assert_eq!(store.get(&hash).unwrap(), Some(expr.clone()));
+        let manifest = store.manifest().unwrap();
+        assert_eq!(manifest.len(), 1);
+        assert_eq!(manifest.get("example"), Some(&hash));
+
+        // `missing` can only be resolved through the store
+        let resolved = Parsed::parse_str(&format!("(missing {}).x", hash))
+            .unwrap()
+            .resolve_with_store(&store)
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize();
+        assert_eq!(resolved.to_string(), "1");
+    }
+
+    #[test]
+    fn standard_cache_layout() {
+        let vars = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| OsString::from(v))
+            }
+        };
+        assert_eq!(
+            cache_dir_from(vars(&[("DHALL_CACHE", "/c"), ("HOME", "/h")])),
+            Some(PathBuf::from("/c"))
+        );
+        assert_eq!(
+            cache_dir_from(vars(&[("XDG_CACHE_HOME", "/x"), ("HOME", "/h")])),
+            Some(PathBuf::from("/x/dhall"))
+        );
+        if !cfg!(windows) {
+            assert_eq!(
+                cache_dir_from(vars(&[("HOME", "/h")])),
+                Some(PathBuf::from("/h/.cache/dhall"))
+            );
+        }
+        assert_eq!(cache_dir_from(vars(&[])), None);
+
+        // Entries are named like in dhall-haskell's cache. This is the hash
+        // of `True` from the standard's test suite.
+        let store = Store {
+            dir: PathBuf::from("/c"),
+        };
+        let expr = Parsed::parse_str("True")
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize();
+        let (hash, data) = expr.encode_with_hash().unwrap();
+        assert_eq!(data, vec![0xf5]);
+        assert_eq!(
+            store.entry_path(&hash),
+            PathBuf::from("/c/122027abdeddfe8503496adeb623466caa47da5f63abd2bc6fa19f6cfcb73ecfed70")
+        );
+    }
+}
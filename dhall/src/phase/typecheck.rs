@@ -2,7 +2,7 @@ use std::cmp::max;
 use std::collections::HashMap;
 
 use dhall_syntax::{
-    rc, Builtin, Const, Expr, ExprF, InterpolatedTextContents, Label,
+    rc, Builtin, Const, Expr, ExprF, InterpolatedTextContents, Label, Span,
 };
 
 use crate::core::context::TypecheckContext;
@@ -50,7 +50,8 @@ fn tck_record_type(
 ) -> Result<Value, TypeError> {
     use crate::error::TypeMessage::*;
     use std::collections::hash_map::Entry;
-    let mut new_kts = HashMap::new();
+    let kts = kts.into_iter();
+    let mut new_kts = HashMap::with_capacity(kts.size_hint().0);
     // An empty record type has type Type
     let mut k = Const::Type;
     for e in kts {
@@ -85,7 +86,8 @@ where
 {
     use crate::error::TypeMessage::*;
     use std::collections::hash_map::Entry;
-    let mut new_kts = HashMap::new();
+    let kts = kts.into_iter();
+    let mut new_kts = HashMap::with_capacity(kts.size_hint().0);
     // Check that all types are the same const
     let mut k = None;
     for e in kts {
@@ -285,7 +287,7 @@ pub(crate) fn builtin_to_value(b: Builtin) -> Value {
     let ctx = TypecheckContext::new();
     Value::from_valuef_and_type(
         ValueF::from_builtin(b),
-        type_with(&ctx, type_of_builtin(b)).unwrap(),
+        type_with(&ctx, &type_of_builtin(b), false).unwrap(),
     )
 }
 
@@ -293,17 +295,20 @@ pub(crate) fn builtin_to_value(b: Builtin) -> Value {
 /// succeeded, or an error if type-checking failed.
 /// Some normalization is done while typechecking, so the returned expression might be partially
 /// normalized as well.
+/// With `share_types`, a sub-expression whose type was found equal to an expected type is made to
+/// use that type instead of its own copy, see `typecheck_low_memory`.
 fn type_with(
     ctx: &TypecheckContext,
-    e: Expr<Normalized>,
+    e: &Expr<Normalized>,
+    share_types: bool,
 ) -> Result<Value, TypeError> {
     use dhall_syntax::ExprF::{Annot, Embed, Lam, Let, Pi, Var};
 
-    Ok(match e.as_ref() {
+    let v = match e.as_ref() {
         Lam(var, annot, body) => {
-            let annot = type_with(ctx, annot.clone())?;
+            let annot = type_with(ctx, annot, share_types)?;
             let ctx2 = ctx.insert_type(var, annot.clone());
-            let body = type_with(&ctx2, body.clone())?;
+            let body = type_with(&ctx2, body, share_types)?;
             let body_type = body.get_type()?;
             Value::from_valuef_and_type(
                 ValueF::Lam(var.clone().into(), annot.clone(), body),
@@ -311,24 +316,26 @@ fn type_with(
             )
         }
         Pi(x, ta, tb) => {
-            let ta = type_with(ctx, ta.clone())?;
+            let ta = type_with(ctx, ta, share_types)?;
             let ctx2 = ctx.insert_type(x, ta.clone());
-            let tb = type_with(&ctx2, tb.clone())?;
-            return tck_pi_type(ctx, x.clone(), ta, tb);
+            let tb = type_with(&ctx2, tb, share_types)?;
+            tck_pi_type(ctx, x.clone(), ta, tb)?
         }
         Let(x, t, v, e) => {
             let v = if let Some(t) = t {
-                t.rewrap(Annot(v.clone(), t.clone()))
+                type_with(
+                    ctx,
+                    &t.rewrap(Annot(v.clone(), t.clone())),
+                    share_types,
+                )?
             } else {
-                v.clone()
+                type_with(ctx, v, share_types)?
             };
-
-            let v = type_with(ctx, v)?;
-            return type_with(&ctx.insert_value(x, v.clone())?, e.clone());
+            return type_with(&ctx.insert_value(x, v), e, share_types);
         }
         Embed(p) => p.clone().into_typed().into_value(),
-        Var(var) => match ctx.lookup(&var) {
-            Some(typed) => typed.clone(),
+        Var(var) => match ctx.lookup(var) {
+            Some(typed) => typed,
             None => {
                 return Err(TypeError::new(
                     ctx,
@@ -339,12 +346,49 @@ fn type_with(
         e => {
             // Typecheck recursively all subexpressions
             let expr = e.traverse_ref_with_special_handling_of_binders(
-                |e| type_with(ctx, e.clone()),
+                |e| type_with(ctx, e, share_types),
                 |_, _| unreachable!(),
             )?;
-            type_last_layer(ctx, expr)?
+            type_last_layer(ctx, expr, share_types).map_err(|mut err| {
+                // A variable annotation has the span of its definition, but
+                // the annotation itself is more helpful.
+                if let (
+                    TypeMessage::InvalidEmptyListAnnotation { span, .. },
+                    ExprF::EmptyListLit(t),
+                ) = (err.message_mut(), e)
+                {
+                    if let Some(t_span) = t.span() {
+                        *span = Some(t_span.clone());
+                    }
+                }
+                // The interpolated value may be a variable, whose span is
+                // that of its definition; point at the interpolation instead.
+                if let (
+                    TypeMessage::InvalidTextInterpolation {
+                        index, span, ..
+                    },
+                    ExprF::TextLit(interpolated),
+                ) = (err.message_mut(), e)
+                {
+                    *span = interpolated
+                        .iter()
+                        .filter_map(|contents| match contents {
+                            InterpolatedTextContents::Expr(x) => x.span(),
+                            InterpolatedTextContents::Text(_) => None,
+                        })
+                        .nth(*index)
+                        .cloned();
+                }
+                err
+            })?
         }
-    })
+    };
+    // Values that were already typed, such as variables, keep the span of
+    // their definition.
+    match e.span() {
+        Some(span) => Ok(v.with_span_if_missing(span)),
+        None => Ok(v),
+    }
 }
 
 /// When all sub-expressions have been typed, check the remaining toplevel
@@ -352,6 +396,7 @@ fn type_with(
 fn type_last_layer(
     ctx: &TypecheckContext,
     e: ExprF<Value, Normalized>,
+    share_types: bool,
 ) -> Result<Value, TypeError> {
     use crate::error::TypeMessage::*;
     use dhall_syntax::BinOp::*;
@@ -359,6 +404,13 @@ fn type_last_layer(
     use dhall_syntax::Const::Type;
     use dhall_syntax::ExprF::*;
     let mkerr = |msg: TypeMessage| Err(TypeError::new(ctx, msg));
+    // Once `x` is known to have type `t`, drop its own copy of the type along
+    // with the normal form computed to compare them.
+    let share_type = |x: &Value, t: &Value| {
+        if share_types {
+            x.share_type(t)
+        }
+    };
 
     /// Intermediary return type
     enum Ret {
@@ -384,6 +436,7 @@ fn type_last_layer(
             if &a.get_type()? != tx {
                 return mkerr(TypeMismatch(f.clone(), tx.clone(), a.clone()));
             }
+            share_type(a, tx);
 
             RetTypeOnly(tb.subst_shift(&x.into(), a))
         }
@@ -391,6 +444,7 @@ fn type_last_layer(
             if &x.get_type()? != t {
                 return mkerr(AnnotMismatch(x.clone(), t.clone()));
             }
+            share_type(x, t);
             RetWhole(x.clone())
         }
         Assert(t) => {
@@ -419,6 +473,7 @@ fn type_last_layer(
             if y.get_type()? != z.get_type()? {
                 return mkerr(IfBranchMismatch(y.clone(), z.clone()));
             }
+            share_type(z, &y.get_type()?);
 
             RetTypeOnly(y.get_type()?)
         }
@@ -426,7 +481,12 @@ fn type_last_layer(
             match &*t.as_whnf() {
                 ValueF::AppliedBuiltin(dhall_syntax::Builtin::List, args)
                     if args.len() == 1 => {}
-                _ => return mkerr(InvalidListType(t.clone())),
+                _ => {
+                    return mkerr(InvalidEmptyListAnnotation {
+                        annotation: t.clone(),
+                        span: t.span(),
+                    })
+                }
             }
             RetTypeOnly(t.clone())
         }
@@ -441,6 +501,7 @@ fn type_last_layer(
                         y.clone(),
                     ));
                 }
+                share_type(y, &x.get_type()?);
             }
             let t = x.get_type()?;
             if t.get_type()?.as_const() != Some(Type) {
@@ -499,10 +560,16 @@ fn type_last_layer(
                                 RetTypeOnly(r.clone())
                             },
                             None => {
-                                return mkerr(MissingUnionField(
-                                    x.clone(),
-                                    r.clone(),
-                                ))
+                                let mut alternatives: Vec<_> =
+                                    kts.keys().cloned().collect();
+                                alternatives.sort();
+                                return mkerr(MissingUnionField {
+                                    label: x.clone(),
+                                    union: r.clone(),
+                                    suggestion: closest_label(x, &alternatives),
+                                    alternatives,
+                                    span: r.span(),
+                                })
                             },
                         },
                         _ => {
@@ -527,12 +594,32 @@ fn type_last_layer(
         DoubleLit(_) => RetTypeOnly(builtin_to_value(Double)),
         TextLit(interpolated) => {
             let text_type = builtin_to_value(Text);
-            for contents in interpolated.iter() {
-                use InterpolatedTextContents::Expr;
-                if let Expr(x) = contents {
-                    if x.get_type()? != text_type {
-                        return mkerr(InvalidTextInterpolation(x.clone()));
-                    }
+            let interpolations =
+                interpolated.iter().filter_map(|contents| match contents {
+                    InterpolatedTextContents::Expr(x) => Some(x),
+                    InterpolatedTextContents::Text(_) => None,
+                });
+            for (index, x) in interpolations.enumerate() {
+                let ty = x.get_type()?;
+                if ty != text_type {
+                    let conversion = match &*ty.as_whnf() {
+                        ValueF::AppliedBuiltin(b, args) if args.is_empty() => {
+                            match b {
+                                Natural => Some(NaturalShow),
+                                Integer => Some(IntegerShow),
+                                Double => Some(DoubleShow),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    return mkerr(InvalidTextInterpolation {
+                        expr: x.clone(),
+                        ty,
+                        conversion,
+                        index,
+                        span: None,
+                    });
                 }
             }
             RetTypeOnly(text_type)
@@ -569,53 +656,15 @@ fn type_last_layer(
                 kts.into_iter().map(|(x, v)| Ok((x.clone(), v))),
             )?)
         }
-        BinOp(RecursiveRecordMerge, l, r) => RetTypeOnly(type_last_layer(
+        BinOp(RecursiveRecordMerge, l, r) => RetTypeOnly(merge_record_types(
             ctx,
-            ExprF::BinOp(
-                RecursiveRecordTypeMerge,
-                l.get_type()?,
-                r.get_type()?,
-            ),
+            &l.get_type()?,
+            &r.get_type()?,
+            (l, r),
+            &mut Vec::new(),
         )?),
         BinOp(RecursiveRecordTypeMerge, l, r) => {
-            use crate::phase::normalize::merge_maps;
-
-            // Extract the LHS record type
-            let borrow_l = l.as_whnf();
-            let kts_x = match &*borrow_l {
-                ValueF::RecordType(kts) => kts,
-                _ => {
-                    return mkerr(RecordTypeMergeRequiresRecordType(l.clone()))
-                }
-            };
-
-            // Extract the RHS record type
-            let borrow_r = r.as_whnf();
-            let kts_y = match &*borrow_r {
-                ValueF::RecordType(kts) => kts,
-                _ => {
-                    return mkerr(RecordTypeMergeRequiresRecordType(r.clone()))
-                }
-            };
-
-            // Ensure that the records combine without a type error
-            let kts = merge_maps(
-                kts_x,
-                kts_y,
-                // If the Label exists for both records, then we hit the recursive case.
-                |_, l: &Value, r: &Value| {
-                    type_last_layer(
-                        ctx,
-                        ExprF::BinOp(
-                            RecursiveRecordTypeMerge,
-                            l.clone(),
-                            r.clone(),
-                        ),
-                    )
-                },
-            )?;
-
-            RetWhole(tck_record_type(ctx, kts.into_iter().map(Ok))?)
+            RetWhole(merge_record_types(ctx, l, r, (l, r), &mut Vec::new())?)
         }
         BinOp(o @ ListAppend, l, r) => {
             match &*l.get_type()?.as_whnf() {
@@ -626,6 +675,7 @@ fn type_last_layer(
             if l.get_type()? != r.get_type()? {
                 return mkerr(BinOpTypeMismatch(*o, r.clone()));
             }
+            share_type(r, &l.get_type()?);
 
             RetTypeOnly(l.get_type()?)
         }
@@ -688,6 +738,27 @@ fn type_last_layer(
                 _ => return mkerr(Merge2ArgMustBeUnion(union.clone())),
             };
 
+            let mut missing: Vec<_> = variants
+                .keys()
+                .filter(|x| !handlers.contains_key(x))
+                .cloned()
+                .collect();
+            let mut extra: Vec<_> = handlers
+                .keys()
+                .filter(|x| !variants.contains_key(x))
+                .cloned()
+                .collect();
+            if !missing.is_empty() || !extra.is_empty() {
+                missing.sort();
+                extra.sort();
+                return mkerr(MergeHandlersMismatch {
+                    union: union_type.clone(),
+                    missing,
+                    extra,
+                    span: union_type.span(),
+                });
+            }
+
             let mut inferred_type = None;
             for (x, handler_type) in handlers {
                 let handler_return_type =
@@ -722,9 +793,7 @@ fn type_last_layer(
                         }
                         // Union alternative without type
                         Some(None) => handler_type.clone(),
-                        None => {
-                            return mkerr(MergeHandlerMissingVariant(x.clone()))
-                        }
+                        None => unreachable!(),
                     };
                 match &inferred_type {
                     None => inferred_type = Some(handler_return_type),
@@ -735,11 +804,6 @@ fn type_last_layer(
                     }
                 }
             }
-            for x in variants.keys() {
-                if !handlers.contains_key(x) {
-                    return mkerr(MergeVariantMissingHandler(x.clone()));
-                }
-            }
 
             match (inferred_type, type_annot) {
                 (Some(ref t1), Some(t2)) => {
@@ -762,7 +826,7 @@ fn type_last_layer(
                 _ => return mkerr(ProjectionMustBeRecord),
             };
 
-            let mut new_kts = HashMap::new();
+            let mut new_kts = HashMap::with_capacity(labels.len());
             for l in labels {
                 match kts.get(l) {
                     None => return mkerr(ProjectionMissingEntry),
@@ -785,16 +849,560 @@ fn type_last_layer(
     })
 }
 
+/// Merges the record types `l` and `r` recursively, for `∧` and `⩓`. `path`
+/// is the path of the fields being merged inside `sides`, the operands of the
+/// merge, so that a collision of two fields that aren't both records can be
+/// reported with their location.
+fn merge_record_types(
+    ctx: &TypecheckContext,
+    l: &Value,
+    r: &Value,
+    sides: (&Value, &Value),
+    path: &mut Vec<Label>,
+) -> Result<Value, TypeError> {
+    use crate::phase::normalize::merge_maps;
+
+    let borrow_l = l.as_whnf();
+    let borrow_r = r.as_whnf();
+    let (kts_x, kts_y) = match (&*borrow_l, &*borrow_r) {
+        (ValueF::RecordType(x), ValueF::RecordType(y)) => (x, y),
+        _ if !path.is_empty() => {
+            return Err(TypeError::new(
+                ctx,
+                TypeMessage::RecordMergeCollision {
+                    path: path.clone(),
+                    left: l.clone(),
+                    right: r.clone(),
+                    left_span: field_span(sides.0, path),
+                    right_span: field_span(sides.1, path),
+                },
+            ))
+        }
+        (ValueF::RecordType(_), _) => {
+            return Err(TypeError::new(
+                ctx,
+                TypeMessage::RecordTypeMergeRequiresRecordType(r.clone()),
+            ))
+        }
+        _ => {
+            return Err(TypeError::new(
+                ctx,
+                TypeMessage::RecordTypeMergeRequiresRecordType(l.clone()),
+            ))
+        }
+    };
+
+    // Ensure that the records combine without a type error
+    let kts = merge_maps(kts_x, kts_y, |x, l: &Value, r: &Value| {
+        path.push(x.clone());
+        let t = merge_record_types(ctx, l, r, sides, path);
+        path.pop();
+        t
+    })?;
+
+    tck_record_type(ctx, kts.into_iter().map(Ok))
+}
+
+/// The label closest to `x` by edit distance, if it is close enough to be a
+/// likely typo.
+fn closest_label(x: &Label, labels: &[Label]) -> Option<Label> {
+    let x = String::from(x);
+    labels
+        .iter()
+        .map(|l| (edit_distance(&x, &String::from(l)), l))
+        .filter(|(d, _)| *d <= std::cmp::max(1, x.chars().count() / 3))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, l)| l.clone())
+}
+
+/// The edit distance between two strings, counted in characters, where
+/// swapping two adjacent characters counts as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j] is the distance between the first i chars of a and the first j
+    // chars of b
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        d[0][j] = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// The span of the field at `path` in a record literal or record type.
+fn field_span(v: &Value, path: &[Label]) -> Option<Span> {
+    let mut v = v.clone();
+    for x in path {
+        let next = match &*v.as_whnf() {
+            ValueF::RecordLit(kvs) | ValueF::RecordType(kvs) => {
+                kvs.get(x)?.clone()
+            }
+            _ => return None,
+        };
+        v = next;
+    }
+    v.span()
+}
+
 /// `type_of` is the same as `type_with` with an empty context, meaning that the
 /// expression must be closed (i.e. no free variables), otherwise type-checking
 /// will fail.
 pub(crate) fn typecheck(e: Expr<Normalized>) -> Result<Value, TypeError> {
-    type_with(&TypecheckContext::new(), e)
+    type_with(&TypecheckContext::new(), &e, false)
+}
+
+/// Like `typecheck`, but values whose type has been checked against another
+/// one share it, instead of each keeping a copy with its normal form. E.g.
+/// the elements of a list end up with a single type between them.
+pub(crate) fn typecheck_low_memory(
+    e: Expr<Normalized>,
+) -> Result<Value, TypeError> {
+    type_with(&TypecheckContext::new(), &e, true)
+}
+
+/// Typecheck an expression whose free variables are bound in `ctx`.
+pub(crate) fn typecheck_in(
+    e: Expr<Normalized>,
+    ctx: &TypecheckContext,
+) -> Result<Value, TypeError> {
+    type_with(ctx, &e, false)
+}
+
+/// Typecheck an expression in which the given variables are free. They stay
+/// opaque during normalization, so the result is a residual expression.
+pub(crate) fn typecheck_abstract(
+    e: Expr<Normalized>,
+    inputs: &[(Label, Value)],
+) -> Result<Value, TypeError> {
+    let ctx = inputs.iter().fold(TypecheckContext::new(), |ctx, (x, t)| {
+        ctx.insert_type(x, t.clone())
+    });
+    type_with(&ctx, &e, false)
 }
 
 pub(crate) fn typecheck_with(
     expr: Expr<Normalized>,
-    ty: Expr<Normalized>,
+    ty: &Value,
 ) -> Result<Value, TypeError> {
-    typecheck(expr.rewrap(ExprF::Annot(expr.clone(), ty)))
+    let ctx = TypecheckContext::new();
+    if ty.get_type()?.as_const().is_none() {
+        return Err(TypeError::new(
+            &ctx,
+            TypeMessage::AnnotMustBeType(ty.clone()),
+        ));
+    }
+    let x = type_with(&ctx, &expr, false)?;
+    if x.get_type()? != *ty {
+        return Err(TypeError::new(
+            &ctx,
+            TypeMessage::AnnotMismatch(x, ty.clone()),
+        ));
+    }
+    Ok(x)
+}
+
+/// Checks that `value` has at least the fields of the record type `ty`, with
+/// the same types. Fields of nested records are checked the same way, so the
+/// value may have extra fields at any depth.
+pub(crate) fn conforms_to(value: &Value, ty: &Value) -> Result<(), TypeError> {
+    let mut missing = Vec::new();
+    let mut mismatched = Vec::new();
+    check_fields(
+        &value.get_type()?,
+        ty,
+        &mut Vec::new(),
+        &mut missing,
+        &mut mismatched,
+    );
+    if missing.is_empty() && mismatched.is_empty() {
+        Ok(())
+    } else {
+        Err(TypeError::new(
+            &TypecheckContext::new(),
+            TypeMessage::RecordDoesNotConform {
+                missing,
+                mismatched,
+            },
+        ))
+    }
+}
+
+fn check_fields(
+    found: &Value,
+    expected: &Value,
+    path: &mut Vec<Label>,
+    missing: &mut Vec<Vec<Label>>,
+    mismatched: &mut Vec<(Vec<Label>, Value, Value)>,
+) {
+    let record_types = match (&*found.as_whnf(), &*expected.as_whnf()) {
+        (ValueF::RecordType(found), ValueF::RecordType(expected)) => {
+            Some((found.clone(), expected.clone()))
+        }
+        _ => None,
+    };
+    match record_types {
+        Some((found, expected)) => {
+            let mut labels: Vec<_> = expected.keys().collect();
+            labels.sort();
+            for label in labels {
+                path.push(label.clone());
+                match found.get(label) {
+                    Some(t) => check_fields(
+                        t,
+                        &expected[label],
+                        path,
+                        missing,
+                        mismatched,
+                    ),
+                    None => missing.push(path.clone()),
+                }
+                path.pop();
+            }
+        }
+        None if found != expected => {
+            mismatched.push((path.clone(), expected.clone(), found.clone()))
+        }
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use crate::phase::{Normalized, Parsed, Typed};
+
+    fn typed(src: &str) -> Typed {
+        Parsed::parse_str(src)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+    }
+
+    fn normalized(src: &str) -> Normalized {
+        typed(src).normalize()
+    }
+
+    fn type_error(src: &str) -> String {
+        Parsed::parse_str(src)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn normalized_values_keep_source_span() {
+        let src = "let defaults = { port = 80, host = \"a\" }\n\
+                   in  { web = defaults // { host = \"b\" } }";
+        let e = Parsed::parse_str_with_name("config.dhall", src)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize();
+        let span = |path: &str| e.select(path).unwrap().source_span().unwrap();
+        let port = span("web.port");
+        assert_eq!(port.as_str(), "80");
+        assert_eq!(port.start_line_col(), (1, 25));
+        assert_eq!(port.source_name(), Some("config.dhall"));
+        assert_eq!(span("web.host").start_line_col(), (2, 34));
+    }
+
+    #[test]
+    fn record_merge_collision_names_path() {
+        let src = "{ a = { b = { c = 1 } } } ∧\n{ a = { b = { c = True } } }";
+        let msg = type_error(src);
+        assert!(msg.contains("Field `a.b.c` is defined on both sides"), msg);
+        assert!(msg.contains("(defined at 1:19 and 2:19)"), msg);
+
+        // With several collisions, the first field is always reported
+        let src = "{ d = 1, c = 1, b = 1, a = 1 } ∧ \
+                   { b = True, d = True, c = True }";
+        for _ in 0..8 {
+            let msg = type_error(src);
+            assert!(msg.contains("Field `b` is defined on both sides"), msg);
+        }
+    }
+
+    #[test]
+    fn empty_literals_keep_element_type() {
+        let src = "let T = List Bool \
+                   let f = \\(a : Type) -> Optional a \
+                   in  { x = [] : T, y = None (f Natural), \
+                   z = List/reverse Text ([] : List Text) }";
+        let e = Parsed::parse_str(src)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize();
+        let out = e.to_string();
+        assert_eq!(
+            out,
+            "{ x = [] : List Bool, y = None (Optional Natural), z = [] : List Text }"
+        );
+        Parsed::parse_str(&out)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap();
+    }
+
+    #[test]
+    fn union_values() {
+        let ty = normalized("< Left : Natural | Right >");
+        let (l, v) = normalized("< Left : Natural | Right >.Left 1")
+            .as_union()
+            .unwrap();
+        assert_eq!(
+            (String::from(&l), v.unwrap().to_string()),
+            ("Left".to_owned(), "1".to_owned())
+        );
+        let (l, v) = normalized("< Left : Natural | Right >.Right")
+            .as_union()
+            .unwrap();
+        assert_eq!(String::from(&l), "Right");
+        assert!(v.is_none());
+        assert!(normalized("< Left : Natural | Right >.Left")
+            .as_union()
+            .is_none());
+        assert!(normalized("1").as_union().is_none());
+
+        let left =
+            Normalized::make_union_value(&ty, "Left", Some(normalized("2")));
+        assert_eq!(
+            left.unwrap().to_string(),
+            "< Left: Natural | Right >.Left 2"
+        );
+        let right = Normalized::make_union_value(&ty, "Right", None).unwrap();
+        assert_eq!(String::from(&right.as_union().unwrap().0), "Right");
+        Normalized::make_union_value(&ty, "Left", Some(normalized("True")))
+            .unwrap_err();
+        Normalized::make_union_value(&ty, "Middle", None).unwrap_err();
+    }
+
+    #[test]
+    fn builtin_signatures() {
+        use crate::phase::Normalized;
+        use dhall_syntax::Builtin;
+
+        let show = |b| Normalized::type_of_builtin(b).to_string();
+        assert_eq!(show(Builtin::Natural), "Type");
+        assert_eq!(show(Builtin::NaturalEven), "Natural → Bool");
+        assert_eq!(show(Builtin::ListLength), "∀(a : Type) → List a → Natural");
+        for b in Builtin::ALL.iter() {
+            assert!(!b.doc().is_empty());
+        }
+    }
+
+    #[test]
+    fn missing_union_alternative_suggestion() {
+        let src = "let U = < Left : Natural | Right >\nin  U.Rigth";
+        assert_eq!(
+            type_error(src),
+            "Missing union alternative `Rigth` in < Left: Natural | Right > \
+             (defined at 1:11); the alternatives are `Left`, `Right`. Did you \
+             mean `Right`?"
+        );
+    }
+
+    #[test]
+    fn merge_handlers_must_cover_the_union() {
+        assert_eq!(
+            type_error(
+                "let U = < A : Natural | B | C >\n\
+                 in  merge { A = λ(n : Natural) → n, D = 0 } U.B"
+            ),
+            "The handlers of `merge` don't match the alternatives of \
+             < A: Natural | B | C > (defined at 1:11); no handler for `B`, \
+             `C`; handlers for unknown alternatives `D`"
+        );
+        assert!(type_error("merge { A = 0 } < A | B >.A")
+            .ends_with("no handler for `B`"));
+    }
+
+    #[test]
+    fn interpolation_suggests_conversion() {
+        assert_eq!(
+            type_error("\\(port : Natural) -> \"localhost:${port}\""),
+            "Only `Text` can be interpolated, but `${port}` (at 1:35) has \
+             type Natural; convert it with `${Natural/show port}`"
+        );
+        assert_eq!(
+            type_error("\"${True}\""),
+            "Only `Text` can be interpolated, but `${True}` (at 1:4) has type \
+             Bool"
+        );
+        // Indentation trimming doesn't shift the spans of multi-line
+        // literals
+        assert!(type_error(
+            "\\(n : Natural) ->\n  ''\n  a ${\"b\"}\n  c ${n}\n  ''"
+        )
+        .contains("`${n}` (at 4:7)"));
+    }
+
+    #[test]
+    fn legacy_syntax_hints() {
+        assert!(type_error("constructors < A : Natural >").contains("`U.A`"));
+        assert!(type_error("[] : Optional Natural")
+            .ends_with("use `None Natural` instead"));
+        assert!(type_error("[ 1 ] : Optional Natural").contains("use `Some x`"));
+    }
+
+    #[test]
+    fn empty_list_annotation() {
+        assert_eq!(
+            type_error("let T = Optional Natural\nin  [] : T"),
+            "An empty list must be annotated with `List a`, but the \
+             annotation is Optional Natural (at 2:10); empty lists annotated \
+             with `Optional` were removed, use `None Natural` instead"
+        );
+        assert_eq!(
+            type_error("[] : Bool"),
+            "An empty list must be annotated with `List a`, but the \
+             annotation is Bool (at 1:6)"
+        );
+    }
+
+    #[test]
+    fn records_conform_to_record_types() {
+        let ty = typed("{ name : Text, server : { port : Natural } }");
+        let conforms =
+            |src| typed(src).conforms_to(&ty).map_err(|e| e.to_string());
+        assert_eq!(
+            conforms(
+                "{ name = \"a\", debug = True, server = { port = 80, host = \"h\" } }"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            conforms("{ server = { port = \"80\" } }"),
+            Err("The value doesn't conform to the record type: missing \
+                 `name`; `server.port` should have type Natural, but has type \
+                 Text"
+                .to_owned())
+        );
+        assert_eq!(
+            conforms("1"),
+            Err("The value doesn't conform to the record type: expected \
+                 { name : Text, server : { port : Natural } }, but found \
+                 Natural"
+                .to_owned())
+        );
+    }
+
+    #[test]
+    fn typecheck_with_checks_the_annotation() {
+        let check = |src, ty| {
+            Parsed::parse_str(src)
+                .unwrap()
+                .skip_resolve()
+                .unwrap()
+                .typecheck_with(&normalized(ty))
+                .map(|t| t.normalize().to_string())
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(check("1 + 1", "Natural"), Ok("2".to_owned()));
+        assert_eq!(
+            check("1", "Text"),
+            Err("Expression doesn't match its annotation: 1 is not of type \
+                 Text"
+                .to_owned())
+        );
+        assert_eq!(
+            check("1", "0 + 1"),
+            Err("Expected a type to check against, but got 1".to_owned())
+        );
+    }
+
+    #[test]
+    fn low_memory_typecheck_gives_the_same_result() {
+        let src = "let f = \\(i : Natural) -> { a = i, b = [ Some i ] } \
+                   in  [ f 0, f 1, if True then f 2 else f 3 ] \
+                   # [ f 4 : { a : Natural, b : List (Optional Natural) } ]";
+        let resolved = Parsed::parse_str(src).unwrap().skip_resolve().unwrap();
+        let low_memory = resolved.clone().typecheck_low_memory().unwrap();
+        let typed = resolved.typecheck().unwrap();
+        assert_eq!(
+            low_memory.type_expr().unwrap().to_string(),
+            typed.type_expr().unwrap().to_string()
+        );
+        assert_eq!(low_memory.normalize(), typed.normalize());
+        let err = Parsed::parse_str("[ 1, True ]")
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck_low_memory()
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("List element 1 should have type Natural"));
+    }
+
+    /// A record literal like the ones in machine-generated configurations,
+    /// with the given number of fields.
+    fn wide_record(fields: usize) -> String {
+        let fields: Vec<_> =
+            (0..fields).map(|i| format!("field{} = {}", i, i)).collect();
+        format!("{{ {} }}", fields.join(", "))
+    }
+
+    #[test]
+    fn wide_records() {
+        let src = format!(
+            "let r = {} in (r // {{ field7 = True }}) /\\ {{ extra = 1 }}",
+            wide_record(20_000)
+        );
+        let e = Parsed::parse_str(&src)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap()
+            .normalize();
+        assert_eq!(e.select("field7").unwrap().to_string(), "True");
+        assert_eq!(e.select("field19999").unwrap().to_string(), "19999");
+        assert_eq!(e.to_string().matches(" = ").count(), 20_001);
+    }
+
+    #[bench]
+    fn typecheck_wide_record(b: &mut test::Bencher) {
+        let src = format!("{} // {{ field7 = True }}", wide_record(20_000));
+        let resolved = Parsed::parse_str(&src).unwrap().skip_resolve().unwrap();
+        b.iter(|| resolved.clone().typecheck().unwrap())
+    }
+
+    #[bench]
+    fn normalize_wide_record(b: &mut test::Bencher) {
+        let src = format!("{} /\\ {{ extra = 1 }}", wide_record(20_000));
+        let typed = Parsed::parse_str(&src)
+            .unwrap()
+            .skip_resolve()
+            .unwrap()
+            .typecheck()
+            .unwrap();
+        b.iter(|| typed.clone().normalize().to_string())
+    }
 }
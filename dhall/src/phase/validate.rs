@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use dhall_syntax::{Const, ExprF};
+
+use crate::error::Error;
+use crate::phase::{ImportCache, Normalized, Parsed};
+
+/// Checks all the `.dhall` files in a directory, as done in CI.
+///
+/// Each file is parsed, has its imports resolved and is typechecked. Files
+/// can be expected to have a given type, either with `expect_type` or from a
+/// manifest.
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    expected_types: BTreeMap<String, Normalized>,
+}
+
+/// The outcome of checking one file.
+#[derive(Debug)]
+pub struct FileReport {
+    /// The path of the file, relative to the validated directory and with
+    /// `/` as separator
+    pub path: String,
+    pub error: Option<Error>,
+}
+
+/// The outcome of `Validator::validate_dir`, with one entry per file sorted by
+/// path.
+///
+/// It is printed with one tab-separated line per file: the path, then `ok`,
+/// or `error`, the phase that failed and the message with newlines escaped.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub files: Vec<FileReport>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Validator::default()
+    }
+
+    /// Expects the file at `path`, relative to the validated directory and
+    /// with `/` as separator, to have type `ty`.
+    pub fn expect_type(&mut self, path: &str, ty: Normalized) -> &mut Self {
+        self.expected_types.insert(path.to_owned(), ty);
+        self
+    }
+
+    /// Reads expected types from a manifest: a Dhall record from paths to
+    /// types, such as ``{ `config/app.dhall` = { port : Natural } }``. Files
+    /// listed in the manifest must exist.
+    pub fn read_manifest(
+        &mut self,
+        manifest: &Path,
+    ) -> Result<&mut Self, Error> {
+        let invalid = |message: String| {
+            Error::InvalidManifest(manifest.to_owned(), message)
+        };
+        let typed = Parsed::parse_file(manifest)?.resolve()?.typecheck()?;
+        let ty = typed.type_expr()?;
+        let kts = match ty.as_ref() {
+            ExprF::RecordType(kts) => kts,
+            _ => {
+                let message = format!(
+                    "expected a record of types, found a value of type `{}`",
+                    ty
+                );
+                return Err(invalid(message));
+            }
+        };
+        let mut paths = Vec::new();
+        for (k, t) in kts.iter() {
+            match t.as_ref() {
+                ExprF::Const(Const::Type) => paths.push(String::from(k)),
+                _ => {
+                    let message =
+                        format!("`{}` is not a type", String::from(k));
+                    return Err(invalid(message));
+                }
+            }
+        }
+        for path in paths {
+            let ty = typed
+                .select_path(&[&path])
+                .map_err(|e| invalid(e.to_string()))?
+                .normalize();
+            self.expected_types.insert(path, ty);
+        }
+        Ok(self)
+    }
+
+    pub fn validate_dir(&self, dir: &Path) -> Result<ValidationReport, Error> {
+        let mut files = Vec::new();
+        find_dhall_files(dir, &mut Vec::new(), &mut files)?;
+        let mut paths: BTreeMap<String, PathBuf> = files
+            .into_iter()
+            .map(|f| (relative_path(dir, &f), f))
+            .collect();
+        for path in self.expected_types.keys() {
+            paths.entry(path.clone()).or_insert_with(|| dir.join(path));
+        }
+        // Files often import the same ones, which are then resolved once
+        let mut cache = ImportCache::new();
+        let files = paths
+            .into_iter()
+            .map(|(path, file)| {
+                let error = self.validate_file(&path, &file, &mut cache).err();
+                FileReport { path, error }
+            })
+            .collect();
+        Ok(ValidationReport { files })
+    }
+
+    fn validate_file(
+        &self,
+        path: &str,
+        file: &Path,
+        cache: &mut ImportCache,
+    ) -> Result<(), Error> {
+        let resolved = Parsed::parse_file(file)?.resolve_with_cache(cache)?;
+        match self.expected_types.get(path) {
+            Some(ty) => resolved.typecheck_with(ty)?,
+            None => resolved.typecheck()?,
+        };
+        Ok(())
+    }
+}
+
+/// Evaluates each of the given files, sharing `cache` so that the files they
+/// have in common are resolved only once. The results are in the same order as
+/// the paths.
+///
+/// The files are evaluated one after the other on the calling thread. There
+/// is no parallel version: values are reference-counted without
+/// synchronization, so neither they nor the cache are `Send`, and evaluating
+/// files on a thread pool needs that to change first.
+pub fn eval_files_sequentially<P: AsRef<Path>>(
+    paths: &[P],
+    cache: &mut ImportCache,
+) -> Vec<Result<Normalized, Error>> {
+    paths
+        .iter()
+        .map(|path| {
+            let resolved =
+                Parsed::parse_file(path.as_ref())?.resolve_with_cache(cache)?;
+            Ok(resolved.typecheck()?.normalize())
+        })
+        .collect()
+}
+
+/// Collects the `.dhall` files under `dir`, following symbolic links.
+/// `ancestors` holds the canonical paths of the directories being walked, so
+/// that a link to one of them is not followed again.
+fn find_dhall_files(
+    dir: &Path,
+    ancestors: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let canonical = dir.canonicalize()?;
+    if ancestors.contains(&canonical) {
+        return Ok(());
+    }
+    ancestors.push(canonical);
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_dhall_files(&path, ancestors, files)?;
+        } else if path.extension().map_or(false, |e| e == "dhall") {
+            files.push(path);
+        }
+    }
+    ancestors.pop();
+    Ok(())
+}
+
+fn relative_path(dir: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(dir).unwrap_or(file);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn phase_name(err: &Error) -> &'static str {
+    match err {
+        Error::IO(_) => "io",
+        Error::Parse(_) => "parse",
+        Error::Decode(_) => "decode",
+        Error::Encode(_) => "encode",
+        Error::Resolve(_) => "resolve",
+        Error::Typecheck(_) => "typecheck",
+        Error::UnsupportedFeature(_, _) => "version",
+        Error::InvalidUtf8(_, _) => "parse",
+        Error::InputTooLarge(_) => "parse",
+        Error::TooManySteps(_) | Error::TooManyValues(_) => "normalize",
+        Error::InvalidManifest(_, _) => "manifest",
+    }
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.files.iter().all(|f| f.error.is_none())
+    }
+    pub fn errors(&self) -> impl Iterator<Item = &FileReport> {
+        self.files.iter().filter(|f| f.error.is_some())
+    }
+}
+
+impl std::fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for file in &self.files {
+            match &file.error {
+                None => writeln!(f, "{}\tok", file.path)?,
+                Some(err) => {
+                    let message = err
+                        .to_string()
+                        .replace('\\', "\\\\")
+                        .replace('\t', "\\t")
+                        .replace('\n', "\\n");
+                    writeln!(
+                        f,
+                        "{}\terror\t{}\t{}",
+                        file.path,
+                        phase_name(err),
+                        message
+                    )?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{eval_files_sequentially, Validator};
+    use crate::error::Error;
+    use crate::phase::ImportCache;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn validate_directory_with_manifest() {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("good.dhall"), "1 + 1").unwrap();
+        fs::write(dir.join("bad.dhall"), "1 + True").unwrap();
+        fs::write(dir.join("sub/app.dhall"), "{ port = 80 }").unwrap();
+        fs::write(dir.join("other.txt"), "not dhall").unwrap();
+        let manifest = dir.join("manifest");
+        fs::write(
+            &manifest,
+            "{ `sub/app.dhall` = { port : Text }, `missing.dhall` = Bool }",
+        )
+        .unwrap();
+
+        let report = Validator::new()
+            .read_manifest(&manifest)
+            .unwrap()
+            .validate_dir(&dir)
+            .unwrap();
+        assert!(!report.is_ok());
+        let lines: Vec<_> = report
+            .to_string()
+            .lines()
+            .map(|l| l.split('\t').take(3).collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "bad.dhall error typecheck",
+                "good.dhall ok",
+                "missing.dhall error io",
+                "sub/app.dhall error typecheck",
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_manifests() {
+        let dir = TempDir::new();
+        let manifest = dir.join("manifest");
+        let err = |contents: &str| {
+            fs::write(&manifest, contents).unwrap();
+            match Validator::new().read_manifest(&manifest) {
+                Err(Error::InvalidManifest(path, message)) => {
+                    assert_eq!(path, manifest);
+                    message
+                }
+                res => panic!("Expected InvalidManifest, got: {:?}", res),
+            }
+        };
+        assert_eq!(
+            err("[ 1 ]"),
+            "expected a record of types, found a value of type `List Natural`"
+        );
+        assert_eq!(
+            err("{ `a.dhall` = Bool, `b.dhall` = 1 }"),
+            "`b.dhall` is not a type"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycles_are_walked_once() {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.dhall"), "1").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("sub/up")).unwrap();
+        std::os::unix::fs::symlink("sub", dir.join("alias")).unwrap();
+
+        let report = Validator::new().validate_dir(&dir).unwrap();
+        let paths: Vec<_> = report.files.iter().map(|f| &f.path[..]).collect();
+        // A link to a directory that is not being walked is followed
+        assert_eq!(paths, vec!["alias/a.dhall", "sub/a.dhall"]);
+    }
+
+    #[test]
+    fn eval_files_with_shared_cache() {
+        let dir = TempDir::new();
+        fs::write(dir.join("common.dhall"), "{ port = 80 }").unwrap();
+        fs::write(dir.join("a.dhall"), "(./common.dhall).port + 1").unwrap();
+        fs::write(dir.join("b.dhall"), "./common.dhall // { x = 1 }").unwrap();
+        fs::write(dir.join("c.dhall"), "1 + True").unwrap();
+
+        let mut cache = ImportCache::new();
+        let paths = ["a.dhall", "b.dhall", "c.dhall"];
+        let paths: Vec<_> = paths.iter().map(|p| dir.join(p)).collect();
+        let results: Vec<_> = eval_files_sequentially(&paths, &mut cache)
+            .into_iter()
+            .map(|r| r.map(|v| v.to_string()).ok())
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                Some("81".to_owned()),
+                Some("{ port = 80, x = 1 }".to_owned()),
+                None
+            ]
+        );
+        assert_eq!(cache.len(), 1);
+    }
+}
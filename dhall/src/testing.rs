@@ -0,0 +1,267 @@
+//! Helpers to exercise the pipeline on generated expressions, for fuzzers and
+//! property tests.
+//!
+//! `ExprGenerator` builds expressions covering all the syntax except imports,
+//! and the `check_*` functions check that they survive a round-trip through
+//! the binary encoding and through the printer and parser.
+//!
+//! ```
+//! use dhall::testing::{check_roundtrips, ExprGenerator};
+//!
+//! for seed in 0..100 {
+//!     let expr = ExprGenerator::from_seed(seed).expr();
+//!     check_roundtrips(&expr).unwrap();
+//! }
+//! ```
+
+use dhall_syntax::map::{DupTreeMap, DupTreeSet};
+use dhall_syntax::{
+    parse_expr, rc, BinOp, Builtin, Const, ExprF, InterpolatedText, Label, V,
+};
+
+use crate::error::RoundTripError;
+use crate::phase::{binary, ParsedExpr};
+
+const CONSTS: [Const; 3] = [Const::Type, Const::Kind, Const::Sort];
+
+const LABELS: [&str; 5] = ["x", "y", "_", "foo", "Bar"];
+
+const TEXTS: [&str; 8] = [
+    "",
+    "a",
+    "hello world",
+    "\"quoted\"",
+    "line\nbreak",
+    "tab\t$",
+    "é ∀",
+    "\\",
+];
+
+const DOUBLES: [f64; 7] = [
+    0.0,
+    1.5,
+    -2.25,
+    1e100,
+    std::f64::INFINITY,
+    std::f64::NEG_INFINITY,
+    std::f64::NAN,
+];
+
+enum Source {
+    Bytes(std::vec::IntoIter<u8>),
+    Seed(u64),
+}
+
+/// Generates expressions from a sequence of choices.
+///
+/// The choices come either from bytes, e.g. provided by a fuzzer, or from a
+/// pseudo-random generator. Once the bytes are exhausted, the smallest
+/// choice is always made, so every input gives a finite expression.
+pub struct ExprGenerator {
+    source: Source,
+    max_depth: usize,
+}
+
+impl ExprGenerator {
+    pub fn new(data: &[u8]) -> Self {
+        ExprGenerator {
+            source: Source::Bytes(data.to_vec().into_iter()),
+            max_depth: 4,
+        }
+    }
+
+    pub fn from_seed(seed: u64) -> Self {
+        ExprGenerator {
+            // xorshift gets stuck on zero
+            source: Source::Seed(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1),
+            max_depth: 4,
+        }
+    }
+
+    /// Sets how deeply expressions can be nested, 4 by default.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn expr(&mut self) -> ParsedExpr {
+        self.expr_at(self.max_depth)
+    }
+
+    fn byte(&mut self) -> u8 {
+        match &mut self.source {
+            Source::Bytes(bytes) => bytes.next().unwrap_or(0),
+            Source::Seed(state) => {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                (*state >> 32) as u8
+            }
+        }
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.byte() as usize % n
+    }
+
+    fn choose<T: Clone>(&mut self, xs: &[T]) -> T {
+        xs[self.below(xs.len())].clone()
+    }
+
+    fn label(&mut self) -> Label {
+        Label::from(self.choose(&LABELS))
+    }
+
+    /// Distinct labels, as duplicates are rejected by the parser.
+    fn labels(&mut self) -> Vec<Label> {
+        LABELS
+            .iter()
+            .filter(|_| self.byte() % 2 == 1)
+            .map(|l| Label::from(*l))
+            .collect()
+    }
+
+    fn leaf(&mut self) -> ParsedExpr {
+        rc(match self.below(8) {
+            0 => ExprF::Const(self.choose(&CONSTS)),
+            1 => ExprF::Var(V(self.label(), self.below(3))),
+            2 => ExprF::Builtin(self.choose(Builtin::ALL)),
+            3 => ExprF::BoolLit(self.byte() % 2 == 0),
+            4 => ExprF::NaturalLit(self.byte() as usize),
+            5 => ExprF::IntegerLit(self.byte() as isize - 128),
+            6 => ExprF::DoubleLit(self.choose(&DOUBLES).into()),
+            _ => ExprF::TextLit(self.choose(&TEXTS).to_owned().into()),
+        })
+    }
+
+    fn expr_at(&mut self, depth: usize) -> ParsedExpr {
+        if depth == 0 {
+            return self.leaf();
+        }
+        let depth = depth - 1;
+        let e = match self.below(24) {
+            0 => return self.leaf(),
+            1 => ExprF::Lam(
+                self.label(),
+                self.expr_at(depth),
+                self.expr_at(depth),
+            ),
+            2 => ExprF::Pi(
+                self.label(),
+                self.expr_at(depth),
+                self.expr_at(depth),
+            ),
+            3 => ExprF::App(self.expr_at(depth), self.expr_at(depth)),
+            4 => {
+                let t = self.optional(depth);
+                ExprF::Let(
+                    self.label(),
+                    t,
+                    self.expr_at(depth),
+                    self.expr_at(depth),
+                )
+            }
+            5 => ExprF::Annot(self.expr_at(depth), self.expr_at(depth)),
+            6 => ExprF::Assert(self.expr_at(depth)),
+            7 => {
+                let op = self.choose(&BinOp::ALL);
+                ExprF::BinOp(op, self.expr_at(depth), self.expr_at(depth))
+            }
+            8 => ExprF::BoolIf(
+                self.expr_at(depth),
+                self.expr_at(depth),
+                self.expr_at(depth),
+            ),
+            9 => {
+                let head = self.choose(&TEXTS).to_owned();
+                let tail = (0..self.below(3))
+                    .map(|_| {
+                        (self.expr_at(depth), self.choose(&TEXTS).to_owned())
+                    })
+                    .collect();
+                ExprF::TextLit(InterpolatedText::from((head, tail)))
+            }
+            10 => ExprF::EmptyListLit(self.expr_at(depth)),
+            11 => ExprF::NEListLit(
+                (0..=self.below(3)).map(|_| self.expr_at(depth)).collect(),
+            ),
+            12 => ExprF::SomeLit(self.expr_at(depth)),
+            13 => ExprF::RecordType(self.fields(depth)),
+            14 => ExprF::RecordLit(self.fields(depth)),
+            15 => {
+                let labels = self.labels();
+                let kts: DupTreeMap<_, _> = labels
+                    .into_iter()
+                    .map(|l| (l, self.optional(depth)))
+                    .collect();
+                ExprF::UnionType(kts)
+            }
+            16 => {
+                let t = self.optional(depth);
+                ExprF::Merge(self.expr_at(depth), self.expr_at(depth), t)
+            }
+            17 => {
+                let t = self.optional(depth);
+                ExprF::ToMap(self.expr_at(depth), t)
+            }
+            18 => ExprF::Field(self.expr_at(depth), self.label()),
+            19 => {
+                let ls: DupTreeSet<_> = self.labels().into_iter().collect();
+                ExprF::Projection(self.expr_at(depth), ls)
+            }
+            _ => return self.leaf(),
+        };
+        rc(e)
+    }
+
+    fn optional(&mut self, depth: usize) -> Option<ParsedExpr> {
+        if self.byte() % 2 == 0 {
+            None
+        } else {
+            Some(self.expr_at(depth))
+        }
+    }
+
+    fn fields(&mut self, depth: usize) -> DupTreeMap<Label, ParsedExpr> {
+        self.labels()
+            .into_iter()
+            .map(|l| (l, self.expr_at(depth)))
+            .collect()
+    }
+}
+
+/// Checks that decoding the binary encoding of `expr` gives it back.
+pub fn check_binary_roundtrip(expr: &ParsedExpr) -> Result<(), RoundTripError> {
+    let data = binary::encode(expr).map_err(RoundTripError::Encode)?;
+    let decoded = binary::decode(&data).map_err(RoundTripError::Decode)?;
+    check_same("binary", expr, &decoded)
+}
+
+/// Checks that parsing the printed `expr` gives it back.
+pub fn check_print_roundtrip(expr: &ParsedExpr) -> Result<(), RoundTripError> {
+    let parsed =
+        parse_expr(&expr.to_string()).map_err(RoundTripError::Parse)?;
+    check_same("text", expr, &parsed)
+}
+
+/// Runs all the round-trip checks on `expr`.
+pub fn check_roundtrips(expr: &ParsedExpr) -> Result<(), RoundTripError> {
+    check_binary_roundtrip(expr)?;
+    check_print_roundtrip(expr)
+}
+
+fn check_same(
+    format: &'static str,
+    expr: &ParsedExpr,
+    found: &ParsedExpr,
+) -> Result<(), RoundTripError> {
+    if expr == found {
+        Ok(())
+    } else {
+        Err(RoundTripError::Mismatch {
+            format,
+            original: format!("{:?}", expr),
+            roundtripped: format!("{:?}", found),
+        })
+    }
+}
@@ -158,7 +158,7 @@ pub fn run_test(test: Test<'_>) -> Result<()> {
                 .typecheck()?
                 .normalize();
 
-            expr.typecheck_with(&expected.into_typed())?.get_type()?;
+            expr.typecheck_with(&expected)?.get_type()?;
         }
         TypecheckFailure(file_path) => {
             let res = parse_file_str(&file_path)?.skip_resolve()?.typecheck();
@@ -219,6 +219,51 @@ pub fn run_test(test: Test<'_>) -> Result<()> {
     Ok(())
 }
 
+/// A fresh directory for the files of a test, removed with its contents
+/// when dropped, even if the test fails.
+#[cfg(test)]
+pub struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new() -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        // Tests run in parallel, and so may several test binaries
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "dhall-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        // Left over by a previous process with the same id
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = std::path::Path;
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<std::path::Path> for TempDir {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod spec {
     // See build.rs
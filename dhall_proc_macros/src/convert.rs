@@ -0,0 +1,272 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::Error;
+use syn::{parse_quote, DeriveInput};
+
+pub fn derive_from_dhall(input: TokenStream) -> TokenStream {
+    TokenStream::from(match derive_from_dhall_inner(input) {
+        Ok(tokens) => tokens,
+        Err(err) => err.to_compile_error(),
+    })
+}
+
+pub fn derive_to_dhall(input: TokenStream) -> TokenStream {
+    TokenStream::from(match derive_to_dhall_inner(input) {
+        Ok(tokens) => tokens,
+        Err(err) => err.to_compile_error(),
+    })
+}
+
+/// A field of a struct, with the name of the record field it maps to: its
+/// own name, or `_1`, `_2`, etc. for tuple structs.
+struct Field<'a> {
+    name: String,
+    member: syn::Member,
+    ty: &'a syn::Type,
+}
+
+fn fields(fields: &syn::Fields) -> Vec<Field> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| match &f.ident {
+            Some(ident) => Field {
+                name: ident.to_string(),
+                member: syn::Member::Named(ident.clone()),
+                ty: &f.ty,
+            },
+            None => Field {
+                name: format!("_{}", i + 1),
+                member: syn::Member::Unnamed(i.into()),
+                ty: &f.ty,
+            },
+        })
+        .collect()
+}
+
+/// The variants of an enum, with the type of their field if they have one.
+/// Only variants with at most one unnamed field can be mapped to a union.
+fn variants(
+    data: &syn::DataEnum,
+) -> Result<Vec<(&syn::Ident, Option<&syn::Type>)>, Error> {
+    data.variants
+        .iter()
+        .map(|v| match &v.fields {
+            syn::Fields::Unit => Ok((&v.ident, None)),
+            syn::Fields::Unnamed(fields) if fields.unnamed.is_empty() => {
+                Ok((&v.ident, None))
+            }
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                Ok((&v.ident, Some(&fields.unnamed[0].ty)))
+            }
+            syn::Fields::Unnamed(_) => Err(Error::new(
+                v.span(),
+                "Variants with more than one field are not supported",
+            )),
+            syn::Fields::Named(_) => {
+                Err(Error::new(v.span(), "Named variants are not supported"))
+            }
+        })
+        .collect()
+}
+
+/// Checks that the input is a struct or a non-empty enum, and returns the
+/// types of its fields.
+fn field_types(input: &DeriveInput) -> Result<Vec<syn::Type>, Error> {
+    match &input.data {
+        syn::Data::Struct(data) => {
+            Ok(fields(&data.fields).iter().map(|f| f.ty.clone()).collect())
+        }
+        syn::Data::Enum(data) if data.variants.is_empty() => {
+            Err(Error::new(input.span(), "Empty enums are not supported"))
+        }
+        syn::Data::Enum(data) => Ok(variants(data)?
+            .into_iter()
+            .filter_map(|(_, ty)| ty.cloned())
+            .collect()),
+        syn::Data::Union(x) => {
+            Err(Error::new(x.union_token.span(), "Unions are not supported"))
+        }
+    }
+}
+
+/// The generics of the input, requiring all its field types to implement
+/// `bound`.
+fn bounded_generics(
+    input: &DeriveInput,
+    bound: syn::Path,
+) -> Result<syn::Generics, Error> {
+    let mut generics = input.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in field_types(input)? {
+        where_clause.predicates.push(parse_quote!(#ty: #bound));
+    }
+    Ok(generics)
+}
+
+fn derive_from_dhall_inner(
+    input: TokenStream,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let input: DeriveInput = syn::parse_macro_input::parse(input)?;
+    let generics =
+        bounded_generics(&input, parse_quote!(::dhall::phase::FromDhall))?;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ident = &input.ident;
+
+    let body = match &input.data {
+        syn::Data::Struct(data) => {
+            let fields = fields(&data.fields).into_iter().map(|f| {
+                let (name, member) = (f.name, f.member);
+                quote! {
+                    #member: ::dhall::phase::FromDhall::from_dhall(
+                        &v.record_field(#name)?
+                    )?
+                }
+            });
+            quote! {
+                v.expect_record()?;
+                Ok(#ident { #(#fields),* })
+            }
+        }
+        syn::Data::Enum(data) => {
+            let arms = variants(data)?.into_iter().map(|(variant, ty)| {
+                let name = variant.to_string();
+                match ty {
+                    None => quote! {
+                        (#name, None) => Ok(#ident::#variant)
+                    },
+                    Some(_) => quote! {
+                        (#name, Some(payload)) => Ok(#ident::#variant(
+                            ::dhall::phase::FromDhall::from_dhall(&payload)?
+                        ))
+                    },
+                }
+            });
+            quote! {
+                let (alternative, payload) = v.union_alternative()?;
+                match (alternative.as_str(), payload) {
+                    #(#arms,)*
+                    _ => Err(v.conversion_error(format!(
+                        "Unexpected alternative `{}`",
+                        alternative
+                    ))),
+                }
+            }
+        }
+        syn::Data::Union(_) => unreachable!(),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::dhall::phase::FromDhall
+                for #ident #ty_generics
+                #where_clause {
+            fn from_dhall(
+                v: &::dhall::phase::Normalized,
+            ) -> ::std::result::Result<Self, ::dhall::error::ConversionError> {
+                #body
+            }
+        }
+    })
+}
+
+fn derive_to_dhall_inner(
+    input: TokenStream,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let input: DeriveInput = syn::parse_macro_input::parse(input)?;
+    let generics =
+        bounded_generics(&input, parse_quote!(::dhall::phase::ToDhall))?;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ident = &input.ident;
+
+    let (get_type, to_dhall) = match &input.data {
+        syn::Data::Struct(data) => {
+            let fields = fields(&data.fields);
+            let types = fields.iter().map(|f| {
+                let (name, ty) = (&f.name, f.ty);
+                quote! {
+                    (#name, <#ty as ::dhall::phase::ToDhall>::dhall_type())
+                }
+            });
+            let values = fields.iter().map(|f| {
+                let (name, member) = (&f.name, &f.member);
+                quote! {
+                    (#name, ::dhall::phase::ToDhall::to_dhall(&self.#member)?)
+                }
+            });
+            (
+                quote! {
+                    ::dhall::phase::Normalized::make_record_type(
+                        vec![ #(#types),* ]
+                    )
+                },
+                quote! {
+                    Ok(::dhall::phase::Normalized::make_record(
+                        vec![ #(#values),* ]
+                    ))
+                },
+            )
+        }
+        syn::Data::Enum(data) => {
+            let variants = variants(data)?;
+            let types = variants.iter().map(|(variant, ty)| {
+                let name = variant.to_string();
+                match ty {
+                    None => quote!( (#name, None) ),
+                    Some(ty) => {
+                        let ty = quote!(<#ty as ::dhall::phase::ToDhall>);
+                        quote!( (#name, Some(#ty::dhall_type())) )
+                    }
+                }
+            });
+            let arms = variants.iter().map(|(variant, ty)| {
+                let name = variant.to_string();
+                let (pattern, payload) = match ty {
+                    None => (quote!(#ident::#variant), quote!(None)),
+                    Some(_) => (
+                        quote!(#ident::#variant(payload)),
+                        quote!(Some(::dhall::phase::ToDhall::to_dhall(
+                            payload
+                        )?)),
+                    ),
+                };
+                quote! {
+                    #pattern => ::dhall::phase::Normalized::
+                        make_union_alternative(&ty, #name, #payload)
+                }
+            });
+            (
+                quote! {
+                    ::dhall::phase::Normalized::make_union_type(
+                        vec![ #(#types),* ]
+                    )
+                },
+                quote! {
+                    let ty = <Self as ::dhall::phase::ToDhall>::dhall_type();
+                    Ok(match self {
+                        #(#arms,)*
+                    })
+                },
+            )
+        }
+        syn::Data::Union(_) => unreachable!(),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::dhall::phase::ToDhall
+                for #ident #ty_generics
+                #where_clause {
+            fn dhall_type() -> ::dhall::phase::Normalized {
+                #get_type
+            }
+            fn to_dhall(
+                &self,
+            ) -> ::std::result::Result<
+                ::dhall::phase::Normalized,
+                ::dhall::error::ConversionError
+            > {
+                #to_dhall
+            }
+        }
+    })
+}
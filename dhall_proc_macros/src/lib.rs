@@ -6,6 +6,7 @@
 
 extern crate proc_macro;
 
+mod convert;
 mod derive;
 mod make_parser;
 mod parse_children;
@@ -17,6 +18,24 @@ pub fn derive_static_type(input: TokenStream) -> TokenStream {
     derive::derive_static_type(input)
 }
 
+#[proc_macro_derive(FromDhall)]
+pub fn derive_from_dhall(input: TokenStream) -> TokenStream {
+    convert::derive_from_dhall(input)
+}
+
+#[proc_macro_derive(ToDhall)]
+pub fn derive_to_dhall(input: TokenStream) -> TokenStream {
+    convert::derive_to_dhall(input)
+}
+
+/// Turns an impl block of rule functions into a parser. Rule functions can be
+/// annotated with:
+///
+/// - `#[alias(rule)]`: the function is also used when parsing `rule`;
+/// - `#[prec_climb(child_rule, climber)]`: the children are combined using the
+///   given precedence climber;
+/// - `#[handles(rule1, rule2, ..)]`: the same function is used for each of
+///   the listed rules.
 #[proc_macro_attribute]
 pub fn make_parser(attrs: TokenStream, input: TokenStream) -> TokenStream {
     TokenStream::from(match make_parser::make_parser(attrs, input) {
@@ -25,6 +44,13 @@ pub fn make_parser(attrs: TokenStream, input: TokenStream) -> TokenStream {
     })
 }
 
+/// Matches on the children of a node and parses them. Each branch is a list
+/// of pattern items:
+///
+/// - `rule(x)` matches one child;
+/// - `rule(x)..` matches any number of children, binding an iterator;
+/// - `opt(rule(x))` matches zero or one child, binding an `Option`;
+/// - `either(a|b)(x)` matches one child of any of the given rules.
 #[proc_macro]
 pub fn parse_children(input: TokenStream) -> TokenStream {
     TokenStream::from(match parse_children::parse_children(input) {
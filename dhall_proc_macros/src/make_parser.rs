@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 use std::iter;
 
-use quote::quote;
+use quote::{quote, quote_spanned};
 use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::{
     parse_quote, Error, Expr, FnArg, Ident, ImplItem, ImplItemMethod, ItemImpl,
@@ -72,6 +73,36 @@ impl Parse for PrecClimbArgs {
     }
 }
 
+/// Handles `#[handles(rule1, rule2, ..)]` attributes: the function is copied
+/// under the name of each of the listed rules, so that a single handler can
+/// serve several rules. Other attributes (like `alias`) are kept on the
+/// copies.
+fn expand_shared_handlers(imp: &mut ItemImpl) -> Result<()> {
+    let mut copies = Vec::new();
+    for item in imp.items.iter_mut() {
+        let function = match item {
+            ImplItem::Method(m) => m,
+            _ => continue,
+        };
+        let handles_attrs: Vec<_> = function
+            .attrs
+            .drain_filter(|attr| attr.path.is_ident("handles"))
+            .collect();
+        for attr in handles_attrs {
+            let rules = attr.parse_args_with(
+                Punctuated::<Ident, Token![,]>::parse_terminated,
+            )?;
+            for rule in rules {
+                let mut copy = function.clone();
+                copy.sig.ident = rule;
+                copies.push(ImplItem::Method(copy));
+            }
+        }
+    }
+    imp.items.extend(copies);
+    Ok(())
+}
+
 fn collect_aliases(
     imp: &mut ItemImpl,
 ) -> Result<HashMap<Ident, Vec<AliasSrc>>> {
@@ -170,12 +201,13 @@ fn apply_special_attrs(f: &mut ParsedFn, rule_enum: &Ident) -> Result<()> {
         // do nothing
     } else {
         let attr = prec_climb_attrs.into_iter().next().unwrap();
+        let attr_span = attr.span();
         let PrecClimbArgs {
             child_rule,
             climber,
         } = attr.parse_args()?;
 
-        function.block = parse_quote!({
+        let block = quote_spanned!(attr_span=> {
             #function
 
             #climber.climb(
@@ -186,6 +218,7 @@ fn apply_special_attrs(f: &mut ParsedFn, rule_enum: &Ident) -> Result<()> {
                 },
             )
         });
+        function.block = parse_quote!(#block);
         // Remove the 3 last arguments to keep only the `input` one
         function.sig.inputs.pop();
         function.sig.inputs.pop();
@@ -201,7 +234,12 @@ fn apply_special_attrs(f: &mut ParsedFn, rule_enum: &Ident) -> Result<()> {
 
     // `alias` attr
     if !f.alias_srcs.is_empty() {
-        let aliases = f.alias_srcs.iter().map(|src| &src.ident);
+        let aliases = f.alias_srcs.iter().map(|src| {
+            let ident = &src.ident;
+            quote_spanned!(ident.span()=>
+                #rule_enum::#ident => Self::#ident(#input_arg),
+            )
+        });
         let block = &function.block;
         function.block = parse_quote!({
             let mut #input_arg = #input_arg;
@@ -219,7 +257,7 @@ fn apply_special_attrs(f: &mut ParsedFn, rule_enum: &Ident) -> Result<()> {
             }
 
             match #input_arg.as_rule() {
-                #(#rule_enum::#aliases => Self::#aliases(#input_arg),)*
+                #(#aliases)*
                 #rule_enum::#fn_name => #block,
                 r => unreachable!(
                     "make_parser: called {} on {:?}",
@@ -240,6 +278,7 @@ pub fn make_parser(
     let rule_enum: Ident = syn::parse(attrs)?;
     let mut imp: ItemImpl = syn::parse(input)?;
 
+    expand_shared_handlers(&mut imp)?;
     let mut alias_map = collect_aliases(&mut imp)?;
     let rule_alias_branches: Vec<_> = alias_map
         .iter()
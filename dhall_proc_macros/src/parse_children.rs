@@ -1,5 +1,5 @@
 use proc_macro2::{Span, TokenStream};
-use quote::quote;
+use quote::{quote, quote_spanned};
 use syn::parse::{Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
@@ -14,8 +14,39 @@ struct ChildrenBranch {
 
 #[derive(Debug, Clone)]
 enum ChildrenBranchPatternItem {
+    /// `rule(x)`
     Single { rule_name: Ident, binder: Pat },
+    /// `rule(x)..`
     Multiple { rule_name: Ident, binder: Ident },
+    /// `opt(rule(x))`: `x` is an `Option`
+    Optional { rule_name: Ident, binder: Pat },
+    /// `either(a|b)(x)`: `x` is parsed with whichever rule matched
+    Alternative {
+        rule_names: Punctuated<Ident, Token![|]>,
+        binder: Pat,
+    },
+}
+
+/// A pattern item once optional and alternative items have been expanded.
+#[derive(Debug, Clone)]
+enum ExpandedItem<'a> {
+    Single {
+        rule_name: &'a Ident,
+        binder: &'a Pat,
+        wrap_some: bool,
+    },
+    Multiple {
+        rule_name: &'a Ident,
+        binder: &'a Ident,
+    },
+}
+
+/// A pattern made only of single and variable-length items, along with the
+/// optional binders that are absent from it.
+#[derive(Debug, Clone)]
+struct ExpandedPattern<'a> {
+    items: Vec<ExpandedItem<'a>>,
+    absent: Vec<&'a Pat>,
 }
 
 #[derive(Debug, Clone)]
@@ -45,7 +76,30 @@ impl Parse for ChildrenBranch {
 impl Parse for ChildrenBranchPatternItem {
     fn parse(input: ParseStream) -> Result<Self> {
         let contents;
-        let rule_name = input.parse()?;
+        let rule_name: Ident = input.parse()?;
+        if rule_name == "opt" {
+            parenthesized!(contents in input);
+            return match contents.parse()? {
+                ChildrenBranchPatternItem::Single { rule_name, binder } => {
+                    Ok(ChildrenBranchPatternItem::Optional {
+                        rule_name,
+                        binder,
+                    })
+                }
+                _ => Err(contents.error("expected a single pattern item")),
+            };
+        }
+        if rule_name == "either" {
+            let binder_contents;
+            parenthesized!(contents in input);
+            let rule_names = Punctuated::parse_separated_nonempty(&contents)?;
+            parenthesized!(binder_contents in input);
+            let binder = binder_contents.parse()?;
+            return Ok(ChildrenBranchPatternItem::Alternative {
+                rule_names,
+                binder,
+            });
+        }
         parenthesized!(contents in input);
         if input.peek(Token![..]) {
             let binder = contents.parse()?;
@@ -73,11 +127,73 @@ impl Parse for ParseChildrenInput {
     }
 }
 
+/// Expands optional and alternative items into the list of plain patterns
+/// they stand for. A pattern with `n` optional items expands to `2^n`
+/// patterns, the ones with more items coming first.
+fn expand_pattern(
+    pattern: &Punctuated<ChildrenBranchPatternItem, Token![,]>,
+) -> Vec<ExpandedPattern<'_>> {
+    use ChildrenBranchPatternItem::{Alternative, Multiple, Optional, Single};
+
+    let mut expanded = vec![ExpandedPattern {
+        items: Vec::new(),
+        absent: Vec::new(),
+    }];
+    for item in pattern {
+        expanded = expanded
+            .into_iter()
+            .flat_map(|pat| -> Vec<ExpandedPattern<'_>> {
+                let with = |new_item| {
+                    let mut pat = pat.clone();
+                    pat.items.push(new_item);
+                    pat
+                };
+                match item {
+                    Single { rule_name, binder } => {
+                        vec![with(ExpandedItem::Single {
+                            rule_name,
+                            binder,
+                            wrap_some: false,
+                        })]
+                    }
+                    Multiple { rule_name, binder } => {
+                        vec![with(ExpandedItem::Multiple { rule_name, binder })]
+                    }
+                    Optional { rule_name, binder } => {
+                        let mut without = pat.clone();
+                        without.absent.push(binder);
+                        vec![
+                            with(ExpandedItem::Single {
+                                rule_name,
+                                binder,
+                                wrap_some: true,
+                            }),
+                            without,
+                        ]
+                    }
+                    Alternative { rule_names, binder } => rule_names
+                        .iter()
+                        .map(|rule_name| {
+                            with(ExpandedItem::Single {
+                                rule_name,
+                                binder,
+                                wrap_some: false,
+                            })
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+    }
+    expanded
+}
+
 fn make_parser_branch(
     branch: &ChildrenBranch,
+    pattern: &ExpandedPattern<'_>,
     i_inputs: &Ident,
 ) -> Result<TokenStream> {
-    use ChildrenBranchPatternItem::{Multiple, Single};
+    use ExpandedItem::{Multiple, Single};
 
     let body = &branch.body;
 
@@ -87,11 +203,11 @@ fn make_parser_branch(
     // subslice and checks, in the if-guard, that its elements all match the chosen Rule.
     let i_variable_pattern =
         Ident::new("___variable_pattern", Span::call_site());
-    let match_pat = branch.pattern.iter().map(|item| match item {
+    let match_pat = pattern.items.iter().map(|item| match item {
         Single { rule_name, .. } => quote!(stringify!(#rule_name)),
         Multiple { .. } => quote!(#i_variable_pattern @ ..),
     });
-    let match_filter = branch.pattern.iter().map(|item| match item {
+    let match_filter = pattern.items.iter().map(|item| match item {
         Single { .. } => quote!(),
         Multiple { rule_name, .. } => quote!(
             {
@@ -111,15 +227,17 @@ fn make_parser_branch(
     let mut singles_before_multiple = Vec::new();
     let mut multiple = None;
     let mut singles_after_multiple = Vec::new();
-    for item in &branch.pattern {
+    for item in &pattern.items {
         match item {
             Single {
-                rule_name, binder, ..
+                rule_name,
+                binder,
+                wrap_some,
             } => {
                 if multiple.is_none() {
-                    singles_before_multiple.push((rule_name, binder))
+                    singles_before_multiple.push((rule_name, binder, wrap_some))
                 } else {
-                    singles_after_multiple.push((rule_name, binder))
+                    singles_after_multiple.push((rule_name, binder, wrap_some))
                 }
             }
             Multiple {
@@ -136,29 +254,48 @@ fn make_parser_branch(
             }
         }
     }
+    // The calls to the rule functions carry the span of the pattern item they
+    // come from, so that type errors point at the offending item instead of
+    // the whole macro invocation.
+    let parse_single = |rule_name: &Ident, binder, wrap_some: &bool, next| {
+        if *wrap_some {
+            let parse = quote_spanned!(rule_name.span()=>
+                Self::#rule_name(#i_inputs.#next().unwrap())?
+            );
+            quote!(let #binder = Some(#parse);)
+        } else {
+            let parse = quote_spanned!(rule_name.span()=>
+                Self::#rule_name(#i_inputs.#next().unwrap())?
+            );
+            quote!(let #binder = #parse;)
+        }
+    };
     let mut parses = Vec::new();
-    for (rule_name, binder) in singles_before_multiple.into_iter() {
+    for binder in &pattern.absent {
         parses.push(quote!(
-            let #binder = Self::#rule_name(
-                #i_inputs.next().unwrap()
-            )?;
+            let #binder = None;
         ))
     }
+    let i_next = Ident::new("next", Span::call_site());
+    for (rule_name, binder, wrap_some) in singles_before_multiple.into_iter() {
+        parses.push(parse_single(rule_name, binder, wrap_some, &i_next))
+    }
     // Note the `rev()`: we are taking inputs from the end of the iterator in reverse order, so that
     // only the unmatched inputs are left for the variable-length pattern, if any.
-    for (rule_name, binder) in singles_after_multiple.into_iter().rev() {
-        parses.push(quote!(
-            let #binder = Self::#rule_name(
-                #i_inputs.next_back().unwrap()
-            )?;
-        ))
+    let i_next_back = Ident::new("next_back", Span::call_site());
+    for (rule_name, binder, wrap_some) in
+        singles_after_multiple.into_iter().rev()
+    {
+        parses.push(parse_single(rule_name, binder, wrap_some, &i_next_back))
     }
     if let Some((rule_name, binder)) = multiple {
-        parses.push(quote!(
-            let #binder = #i_inputs
+        let parse = quote_spanned!(rule_name.span()=>
+            #i_inputs
                 .map(|i| Self::#rule_name(i))
                 .collect::<Result<Vec<_>, _>>()?
-                .into_iter();
+        );
+        parses.push(quote!(
+            let #binder = #parse.into_iter();
         ))
     }
 
@@ -182,7 +319,12 @@ pub fn parse_children(
     let branches = input
         .branches
         .iter()
-        .map(|br| make_parser_branch(br, &i_inputs))
+        .flat_map(|br| {
+            expand_pattern(&br.pattern)
+                .into_iter()
+                .map(move |pat| (br, pat))
+        })
+        .map(|(br, pat)| make_parser_branch(br, &pat, &i_inputs))
         .collect::<Result<Vec<_>>>()?;
 
     Ok(quote!({
@@ -204,7 +346,11 @@ pub fn parse_children(
             .into_inner()
             .map(|p| #input_expr.with_pair(p));
 
-        #[allow(unreachable_code)]
+        #[allow(
+            unreachable_code,
+            unreachable_patterns,
+            clippy::let_and_return
+        )]
         match #i_children_rules.as_slice() {
             #(#branches,)*
             [..] => return Err(#input_expr.error(
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 use std::rc::Rc;
 
 use crate::map::{DupTreeMap, DupTreeSet};
@@ -15,28 +17,110 @@ pub fn trivial_result<T>(x: Result<T, !>) -> T {
     }
 }
 
-/// A location in the source text
+/// A location in the source text. Spans share the source they are in, so
+/// they are cheap to clone. They can be compared, in the order the sources
+/// were parsed and then of position, so they can be used as map keys.
 #[derive(Debug, Clone)]
 pub struct Span {
-    input: Rc<str>,
+    source: Rc<SourceFile>,
     /// # Safety
     ///
-    /// Must be a valid character boundary index into `input`.
+    /// Must be a valid character boundary index into the text of `source`.
     start: usize,
     /// # Safety
     ///
-    /// Must be a valid character boundary index into `input`.
+    /// Must be a valid character boundary index into the text of `source`.
     end: usize,
 }
 
 impl Span {
-    pub(crate) fn make(input: Rc<str>, sp: pest::Span) -> Self {
+    pub(crate) fn make(source: Rc<SourceFile>, sp: pest::Span) -> Self {
         Span {
-            input,
+            source,
             start: sp.start(),
             end: sp.end(),
         }
     }
+    /// The smallest span that contains both spans. They must come from the
+    /// same input.
+    pub(crate) fn union(&self, other: &Span) -> Self {
+        debug_assert!(Rc::ptr_eq(&self.source, &other.source));
+        Span {
+            source: self.source.clone(),
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+    fn key(&self) -> (usize, usize, usize) {
+        (self.source.id(), self.start, self.end)
+    }
+    /// The source the span is in
+    pub fn source(&self) -> &SourceFile {
+        &self.source
+    }
+    /// The name the source was given when parsing, if any.
+    pub fn source_name(&self) -> Option<&str> {
+        self.source.name()
+    }
+    /// Byte offset of the start of the span in the source
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    /// Byte offset of the end of the span in the source
+    pub fn end(&self) -> usize {
+        self.end
+    }
+    /// The source text covered by the span
+    pub fn as_str(&self) -> &str {
+        &self.source.text()[self.start..self.end]
+    }
+    /// 1-based line and column of the start of the span. Columns count
+    /// characters, not bytes.
+    pub fn start_line_col(&self) -> (usize, usize) {
+        line_col(self.source.text(), self.start)
+    }
+    /// 1-based line and column of the end of the span. Columns count
+    /// characters, not bytes.
+    pub fn end_line_col(&self) -> (usize, usize) {
+        line_col(self.source.text(), self.end)
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Span {}
+
+impl PartialOrd for Span {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Span {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl std::hash::Hash for Span {
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: std::hash::Hasher,
+    {
+        self.key().hash(state)
+    }
+}
+
+fn line_col(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
 }
 
 /// Double with bitwise equality
@@ -73,7 +157,18 @@ impl From<NaiveDouble> for f64 {
 }
 
 /// Constants for a pure type system
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
 pub enum Const {
     Type,
     Kind,
@@ -85,7 +180,9 @@ pub enum Const {
 /// The `Label` field is the variable's name (i.e. \"`x`\").
 /// The `Int` field is a DeBruijn index.
 /// See dhall-lang/standard/semantics.md for details
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
 pub struct V<Label>(pub Label, pub usize);
 
 // This is only for the specific `Label` type, not generic
@@ -102,7 +199,18 @@ impl<'a> From<&'a Label> for V<Label> {
 
 // Definition order must match precedence order for
 // pretty-printing to work correctly
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
 pub enum BinOp {
     /// `x ? y`
     ImportAlt,
@@ -132,9 +240,72 @@ pub enum BinOp {
     Equivalence,
 }
 
-/// Built-ins
+/// How a chain of the same binary operator is grouped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Associativity {
+    /// `a + b + c` is `(a + b) + c`
+    Left,
+    /// `a + b + c` is `a + (b + c)`
+    Right,
+}
+
+impl BinOp {
+    /// All the binary operators, from the loosest to the tightest binding.
+    pub const ALL: [BinOp; 13] = [
+        BinOp::ImportAlt,
+        BinOp::BoolOr,
+        BinOp::NaturalPlus,
+        BinOp::TextAppend,
+        BinOp::ListAppend,
+        BinOp::BoolAnd,
+        BinOp::RecursiveRecordMerge,
+        BinOp::RightBiasedRecordMerge,
+        BinOp::RecursiveRecordTypeMerge,
+        BinOp::NaturalTimes,
+        BinOp::BoolEQ,
+        BinOp::BoolNE,
+        BinOp::Equivalence,
+    ];
+
+    /// The precedence of this operator. Operators with a higher precedence
+    /// bind more tightly.
+    pub fn precedence(self) -> usize {
+        BinOp::ALL.iter().position(|op| *op == self).unwrap()
+    }
+
+    pub fn associativity(self) -> Associativity {
+        Associativity::Left
+    }
+}
+
+/// The operand of an `x ? y` import alternative that was kept during
+/// resolution.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum Builtin {
+pub enum ImportAltBranch {
+    Left,
+    Right,
+}
+
+/// Declares the `Builtin` enum along with `Builtin::ALL`, so that the list
+/// can't miss a variant.
+macro_rules! builtins {
+    ($($b:ident,)*) => {
+        /// Built-ins
+        #[derive(
+            Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize,
+        )]
+        pub enum Builtin {
+            $($b,)*
+        }
+
+        impl Builtin {
+            /// All the builtins, in the order they are declared.
+            pub const ALL: &'static [Builtin] = &[$(Builtin::$b,)*];
+        }
+    };
+}
+
+builtins! {
     Bool,
     Natural,
     Integer,
@@ -166,6 +337,44 @@ pub enum Builtin {
     TextShow,
 }
 
+impl Builtin {
+    /// A short description of the builtin, e.g. to show next to its type.
+    pub fn doc(self) -> &'static str {
+        use Builtin::*;
+        match self {
+            Bool => "The type of `True` and `False`",
+            Natural => "The type of natural numbers, e.g. `0` or `42`",
+            Integer => "The type of signed integers, e.g. `+1` or `-3`",
+            Double => "The type of IEEE-754 double precision floating point numbers, e.g. `1.5`",
+            Text => "The type of text literals, e.g. `\"hello\"`",
+            List => "The type of lists of the given type",
+            Optional => "The type of optional values of the given type",
+            OptionalNone => "The empty optional value of the given type",
+            NaturalBuild => "Builds a natural number from its fold",
+            NaturalFold => "Applies a function the given number of times to a starting value",
+            NaturalIsZero => "Whether a natural number is `0`",
+            NaturalEven => "Whether a natural number is even",
+            NaturalOdd => "Whether a natural number is odd",
+            NaturalToInteger => "Converts a natural number to the same non-negative integer",
+            NaturalShow => "Renders a natural number as text",
+            NaturalSubtract => "`Natural/subtract m n` is `n - m`, or `0` if `m` is larger",
+            IntegerToDouble => "Converts an integer to the nearest double",
+            IntegerShow => "Renders an integer as text, always with a sign",
+            DoubleShow => "Renders a double as text",
+            ListBuild => "Builds a list from its fold",
+            ListFold => "Folds a list from the right",
+            ListLength => "The number of elements of a list",
+            ListHead => "The first element of a list, if any",
+            ListLast => "The last element of a list, if any",
+            ListIndexed => "Pairs each element of a list with its index",
+            ListReverse => "Reverses a list",
+            OptionalFold => "Folds an optional value",
+            OptionalBuild => "Builds an optional value from its fold",
+            TextShow => "Renders text as a Dhall text literal, with quotes and escapes",
+        }
+    }
+}
+
 // Each node carries an annotation.
 #[derive(Debug, Clone)]
 pub struct Expr<Embed>(Box<(RawExpr<Embed>, Option<Span>)>);
@@ -193,7 +402,7 @@ impl<Embed: std::hash::Hash> std::hash::Hash for Expr<Embed> {
 // Having the recursion out of the enum definition enables writing
 // much more generic code and improves pattern-matching behind
 // smart pointers.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ExprF<SubExpr, Embed> {
     Const(Const),
     ///  `x`
@@ -329,6 +538,17 @@ impl<E> Expr<E> {
         Expr(Box::new((x, Some(n))))
     }
 
+    /// The location of this expression in the source it was parsed from, if
+    /// known.
+    pub fn span(&self) -> Option<&Span> {
+        (self.0).1.as_ref()
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        (self.0).1 = Some(span);
+        self
+    }
+
     pub fn from_expr_no_span(x: RawExpr<E>) -> Self {
         Expr(Box::new((x, None)))
     }
@@ -349,21 +569,119 @@ impl<E> Expr<E> {
         E: Clone,
         F1: FnMut(Import<Expr<E>>) -> Result<E, Err>,
     {
-        match self.as_mut() {
+        self.traverse_resolve_mut_with_alternatives(f, &mut |_, _, _| {})
+    }
+
+    /// The variables that are not bound within the expression. Their indices
+    /// are relative to the expression as a whole, so `λ(x : T) → x@1` has
+    /// `x` (i.e. `x@0`) as its only free variable.
+    pub fn free_vars(&self) -> BTreeSet<V<Label>>
+    where
+        E: Clone,
+    {
+        let mut vars = BTreeSet::new();
+        self.collect_free_vars(&mut HashMap::new(), &mut vars);
+        vars
+    }
+
+    fn collect_free_vars(
+        &self,
+        bound: &mut HashMap<Label, usize>,
+        vars: &mut BTreeSet<V<Label>>,
+    ) where
+        E: Clone,
+    {
+        fn under_binder<E: Clone>(
+            x: &Label,
+            e: &Expr<E>,
+            bound: &mut HashMap<Label, usize>,
+            vars: &mut BTreeSet<V<Label>>,
+        ) {
+            *bound.entry(x.clone()).or_insert(0) += 1;
+            e.collect_free_vars(bound, vars);
+            *bound.get_mut(x).unwrap() -= 1;
+        }
+        match self.as_ref() {
+            ExprF::Var(V(x, n)) => {
+                let depth = bound.get(x).cloned().unwrap_or(0);
+                if *n >= depth {
+                    vars.insert(V(x.clone(), n - depth));
+                }
+            }
+            ExprF::Lam(x, t, e) | ExprF::Pi(x, t, e) => {
+                t.collect_free_vars(bound, vars);
+                under_binder(x, e, bound, vars);
+            }
+            ExprF::Let(x, t, v, e) => {
+                if let Some(t) = t {
+                    t.collect_free_vars(bound, vars);
+                }
+                v.collect_free_vars(bound, vars);
+                under_binder(x, e, bound, vars);
+            }
+            e => {
+                trivial_result(e.traverse_ref(|e| {
+                    e.collect_free_vars(bound, vars);
+                    Ok(())
+                }));
+            }
+        }
+    }
+
+    /// Like `traverse_resolve_mut`, but calls `on_alt` for each `x ? y` once
+    /// it is resolved, with the span of the whole expression, the operand
+    /// that was kept, and the error from `x` if `y` had to be used.
+    ///
+    /// When `x` is a bare `missing`, resolving it is not even attempted.
+    /// When both `x` and `y` fail, the error from `x` is returned.
+    pub fn traverse_resolve_mut_with_alternatives<Err, F1, F2>(
+        &mut self,
+        f: &mut F1,
+        on_alt: &mut F2,
+    ) -> Result<(), Err>
+    where
+        E: Clone,
+        F1: FnMut(Import<Expr<E>>) -> Result<E, Err>,
+        F2: FnMut(Option<&Span>, ImportAltBranch, Option<Err>),
+    {
+        let (raw, span) = &mut *self.0;
+        match raw {
             ExprF::BinOp(BinOp::ImportAlt, l, r) => {
                 let garbage_expr = ExprF::BoolLit(false);
-                let new_self = if l.traverse_resolve_mut(f).is_ok() {
-                    l
-                } else {
-                    r.traverse_resolve_mut(f)?;
+                let new_self = if l.is_missing() {
+                    r.traverse_resolve_mut_with_alternatives(f, on_alt)?;
+                    on_alt(span.as_ref(), ImportAltBranch::Right, None);
                     r
+                } else {
+                    match l.traverse_resolve_mut_with_alternatives(f, on_alt) {
+                        Ok(()) => {
+                            on_alt(span.as_ref(), ImportAltBranch::Left, None);
+                            l
+                        }
+                        Err(err) => {
+                            if r.traverse_resolve_mut_with_alternatives(
+                                f, on_alt,
+                            )
+                            .is_err()
+                            {
+                                return Err(err);
+                            }
+                            on_alt(
+                                span.as_ref(),
+                                ImportAltBranch::Right,
+                                Some(err),
+                            );
+                            r
+                        }
+                    }
                 };
-                *self.as_mut() =
-                    std::mem::replace(new_self.as_mut(), garbage_expr);
+                *raw = std::mem::replace(new_self.as_mut(), garbage_expr);
             }
             _ => {
-                self.as_mut().traverse_mut(|e| e.traverse_resolve_mut(f))?;
-                if let ExprF::Import(import) = self.as_mut() {
+                raw.traverse_mut(|e| {
+                    e.traverse_resolve_mut_with_alternatives(f, on_alt)
+                })?;
+                if let ExprF::Import(import) = raw {
                     let garbage_import = Import {
                         mode: ImportMode::Code,
                         location: ImportLocation::Missing,
@@ -371,12 +689,51 @@ impl<E> Expr<E> {
                     };
                     // Move out of &mut import
                     let import = std::mem::replace(import, garbage_import);
-                    *self.as_mut() = ExprF::Embed(f(import)?);
+                    *raw = ExprF::Embed(f(import)?);
                 }
             }
         }
         Ok(())
     }
+
+    /// Whether this is a bare `missing` import, which can never resolve.
+    /// With a hash, `missing` may still be found in a cache.
+    fn is_missing(&self) -> bool {
+        match self.as_ref() {
+            ExprF::Import(Import {
+                location: ImportLocation::Missing,
+                hash: None,
+                ..
+            }) => true,
+            _ => false,
+        }
+    }
+
+    /// Like `traverse_resolve_mut`, but builds a new expression instead of
+    /// resolving in place. Since `f` is only borrowed immutably, independent
+    /// subexpressions may be resolved concurrently, e.g. by a caller that
+    /// shares `f` between threads.
+    pub fn traverse_resolve<Err, F1>(&self, f: &F1) -> Result<Expr<E>, Err>
+    where
+        E: Clone,
+        F1: Fn(Import<Expr<E>>) -> Result<E, Err>,
+    {
+        match self.as_ref() {
+            ExprF::BinOp(BinOp::ImportAlt, l, r) if l.is_missing() => {
+                r.traverse_resolve(f)
+            }
+            ExprF::BinOp(BinOp::ImportAlt, l, r) => l
+                .traverse_resolve(f)
+                .or_else(|err| r.traverse_resolve(f).map_err(|_| err)),
+            e => {
+                let e = e.traverse_ref(|e| e.traverse_resolve(f))?;
+                Ok(self.rewrap(match e {
+                    ExprF::Import(import) => ExprF::Embed(f(import)?),
+                    e => e,
+                }))
+            }
+        }
+    }
 }
 
 // Should probably rename this
@@ -387,8 +744,18 @@ pub fn rc<E>(x: RawExpr<E>) -> Expr<E> {
 pub(crate) fn spanned<E>(span: Span, x: RawExpr<E>) -> Expr<E> {
     Expr::new(x, span)
 }
-pub(crate) fn unspanned<E>(x: RawExpr<E>) -> Expr<E> {
-    Expr::from_expr_no_span(x)
+/// Builds an expression spanning from the start of `first` to the end of
+/// `last`, if both are known.
+pub(crate) fn spanned_between<E>(
+    first: Option<&Span>,
+    last: Option<&Span>,
+    x: RawExpr<E>,
+) -> Expr<E> {
+    let span = match (first, last) {
+        (Some(first), Some(last)) => Some(first.union(last)),
+        _ => None,
+    };
+    Expr(Box::new((x, span)))
 }
 
 /// Add an isize to an usize
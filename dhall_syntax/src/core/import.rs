@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
+
 /// The beginning of a file path which anchors subsequent path components
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FilePrefix {
     /// Absolute path
     Absolute,
@@ -12,7 +14,7 @@ pub enum FilePrefix {
 }
 
 /// The location of import (i.e. local vs. remote vs. environment)
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ImportLocation<SubExpr> {
     Local(FilePrefix, Vec<String>),
     Remote(URL<SubExpr>),
@@ -20,7 +22,7 @@ pub enum ImportLocation<SubExpr> {
     Missing,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct URL<SubExpr> {
     pub scheme: Scheme,
     pub authority: String,
@@ -29,33 +31,144 @@ pub struct URL<SubExpr> {
     pub headers: Option<SubExpr>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Scheme {
     HTTP,
     HTTPS,
 }
 
 /// How to interpret the import's contents (i.e. as Dhall code or raw text)
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ImportMode {
     Code,
     RawText,
     Location,
 }
 
+/// An integrity check on the contents of an import. It can only be built
+/// through `Hash::new` and its wrappers, so the digest always has the length
+/// its algorithm produces.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Hash {
-    SHA256(Vec<u8>),
+pub struct Hash {
+    algorithm: HashAlgorithm,
+    digest: Vec<u8>,
+}
+
+/// The hashing algorithm used by a `Hash`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    SHA256,
+}
+
+/// Reasons why a hash could not be built
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashError {
+    /// Missing `algorithm:` prefix
+    MissingAlgorithm,
+    UnknownAlgorithm(String),
+    InvalidHex(String),
+    WrongLength {
+        algorithm: HashAlgorithm,
+        expected: usize,
+        found: usize,
+    },
 }
 
 /// Reference to an external resource
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Import<SubExpr> {
     pub mode: ImportMode,
     pub location: ImportLocation<SubExpr>,
     pub hash: Option<Hash>,
 }
 
+impl HashAlgorithm {
+    /// The name used in the textual syntax, e.g. `sha256`
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::SHA256 => "sha256",
+        }
+    }
+    /// Length in bytes of a digest produced by this algorithm
+    pub fn digest_len(self) -> usize {
+        match self {
+            HashAlgorithm::SHA256 => 32,
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashAlgorithm::SHA256),
+            _ => None,
+        }
+    }
+}
+
+impl Hash {
+    /// Builds a hash from a raw digest, checking that it has the length
+    /// expected for `algorithm`.
+    pub fn new(
+        algorithm: HashAlgorithm,
+        digest: Vec<u8>,
+    ) -> Result<Self, HashError> {
+        let expected = algorithm.digest_len();
+        if digest.len() != expected {
+            return Err(HashError::WrongLength {
+                algorithm,
+                expected,
+                found: digest.len(),
+            });
+        }
+        Ok(Hash { algorithm, digest })
+    }
+    /// Builds a hash from a hex-encoded digest.
+    pub fn from_hex(
+        algorithm: HashAlgorithm,
+        hex: &str,
+    ) -> Result<Self, HashError> {
+        let digest = hex::decode(hex)
+            .map_err(|_| HashError::InvalidHex(hex.to_owned()))?;
+        Hash::new(algorithm, digest)
+    }
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+    /// The raw digest
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.digest
+    }
+    /// The digest as a lowercase hex string, without the algorithm prefix
+    pub fn hex(&self) -> String {
+        hex::encode(self.as_bytes())
+    }
+    /// The digest prefixed with its multihash header, as found in the binary
+    /// encoding of imports.
+    pub fn to_multihash(&self) -> Vec<u8> {
+        let code = match self.algorithm() {
+            HashAlgorithm::SHA256 => 0x12,
+        };
+        let mut bytes = vec![code, self.as_bytes().len() as u8];
+        bytes.extend_from_slice(self.as_bytes());
+        bytes
+    }
+    /// The hex-encoded multihash, e.g. `1220...` for sha256. This is the
+    /// name under which the standard import cache stores an expression.
+    pub fn multihash_hex(&self) -> String {
+        hex::encode(self.to_multihash())
+    }
+}
+
+/// Parses the `sha256:0123...` form used in source files.
+impl std::str::FromStr for Hash {
+    type Err = HashError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colon = s.find(':').ok_or(HashError::MissingAlgorithm)?;
+        let (name, hex) = (&s[..colon], &s[colon + 1..]);
+        let algorithm = HashAlgorithm::from_name(name)
+            .ok_or_else(|| HashError::UnknownAlgorithm(name.to_owned()))?;
+        Hash::from_hex(algorithm, hex)
+    }
+}
+
 impl<SE> URL<SE> {
     pub fn traverse_ref<'a, Err, SE2>(
         &'a self,
@@ -105,6 +218,76 @@ impl<SE> ImportLocation<SE> {
     }
 }
 
+/// Canonicalizes path components as the standard does: `.` components are
+/// dropped, and a `..` component cancels the component before it unless that
+/// one is itself a `..`.
+fn canonicalize_path(path: &[String]) -> Vec<String> {
+    let mut canonical: Vec<String> = Vec::new();
+    for component in path {
+        match component.as_str() {
+            "." => {}
+            ".." => match canonical.last() {
+                Some(last) if last != ".." => {
+                    canonical.pop();
+                }
+                _ => canonical.push(component.clone()),
+            },
+            _ => canonical.push(component.clone()),
+        }
+    }
+    canonical
+}
+
+impl<SE: Clone> URL<SE> {
+    /// The canonical form of this URL: the path is canonicalized and the
+    /// host, which is case-insensitive, is lowercased. The user info is
+    /// kept as is, since user names and passwords may be case-sensitive.
+    pub fn canonicalize(&self) -> Self {
+        // The user info can't contain `@`, so the host starts after the
+        // last one.
+        let host_start = self.authority.rfind('@').map(|i| i + 1).unwrap_or(0);
+        let authority = format!(
+            "{}{}",
+            &self.authority[..host_start],
+            self.authority[host_start..].to_ascii_lowercase()
+        );
+        URL {
+            scheme: self.scheme,
+            authority,
+            path: canonicalize_path(&self.path),
+            query: self.query.clone(),
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+impl<SE: Clone> ImportLocation<SE> {
+    /// The canonical form of this location, as defined by the standard.
+    /// Two locations that canonicalize to the same value refer to the same
+    /// import. A relative path that starts with `..` is made relative to the
+    /// parent directory, so `./../a` becomes `../a`.
+    pub fn canonicalize(&self) -> Self {
+        use ImportLocation::*;
+        match self {
+            Local(prefix, path) => {
+                let mut path = canonicalize_path(path);
+                let prefix = match prefix {
+                    FilePrefix::Here
+                        if path.first().map(String::as_str) == Some("..") =>
+                    {
+                        path.remove(0);
+                        FilePrefix::Parent
+                    }
+                    prefix => *prefix,
+                };
+                Local(prefix, path)
+            }
+            Remote(url) => Remote(url.canonicalize()),
+            Env(_) | Missing => self.clone(),
+        }
+    }
+}
+
 impl<SE> Import<SE> {
     pub fn traverse_ref<'a, Err, SE2>(
         &'a self,
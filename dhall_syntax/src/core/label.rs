@@ -24,7 +24,32 @@ impl From<&Label> for String {
     }
 }
 
+/// The words that can't be used as labels without backticks, see the
+/// `keyword` rule of the grammar.
+pub const KEYWORDS: [&str; 15] = [
+    "if", "then", "else", "let", "in", "using", "missing", "assert", "as",
+    "Infinity", "NaN", "merge", "Some", "toMap", "forall",
+];
+
 impl Label {
+    /// Whether `s` is allowed as a label by the standard: a non-empty string
+    /// of printable ASCII characters other than the backtick.
+    pub fn is_valid(s: &str) -> bool {
+        !s.is_empty() && s.chars().all(|c| c >= ' ' && c <= '~' && c != '`')
+    }
+    /// Whether the label can be written without backticks, as a
+    /// `simple-label` that isn't a keyword. Builtins may still need
+    /// backticks where a variable is expected.
+    pub fn is_simple(&self) -> bool {
+        let mut chars = self.0.chars();
+        let first_ok = match chars.next() {
+            Some(c) => c.is_ascii_alphabetic() || c == '_',
+            None => false,
+        };
+        first_ok
+            && chars.all(|c| c.is_ascii_alphanumeric() || "-/_".contains(c))
+            && !KEYWORDS.contains(&self.as_ref())
+    }
     pub fn from_str(s: &str) -> Label {
         Label(s.into())
     }
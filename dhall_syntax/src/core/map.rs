@@ -64,12 +64,46 @@ mod dup_tree_map {
     use super::one_or_more;
     use super::one_or_more::OneOrMore;
     use std::collections::{btree_map, BTreeMap};
+    use std::hash::{Hash, Hasher};
     use std::iter;
 
-    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    /// Iteration is in key order, and the values of a duplicated key come in
+    /// the order they were inserted. That order doesn't depend on anything
+    /// else, so printing or encoding a map always gives the same result.
+    ///
+    /// The order in which the entries were inserted is also remembered, and
+    /// is available through `iter_insertion_order`. Equality, ordering and
+    /// hashing ignore it: they only depend on the entries in iteration order,
+    /// so they agree with each other and maps can be used as keys of a
+    /// `HashMap` or a `BTreeMap`. Two maps that differ only by the order of
+    /// the values of a duplicated key are different.
+    #[derive(Debug, Clone)]
     pub struct DupTreeMap<K, V> {
         map: BTreeMap<K, OneOrMore<V>>,
         size: usize,
+        order: Vec<K>,
+    }
+
+    impl<K: PartialEq, V: PartialEq> PartialEq for DupTreeMap<K, V> {
+        fn eq(&self, other: &Self) -> bool {
+            self.map == other.map
+        }
+    }
+    impl<K: Eq, V: Eq> Eq for DupTreeMap<K, V> {}
+    impl<K: PartialOrd, V: PartialOrd> PartialOrd for DupTreeMap<K, V> {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            self.map.partial_cmp(&other.map)
+        }
+    }
+    impl<K: Ord, V: Ord> Ord for DupTreeMap<K, V> {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.map.cmp(&other.map)
+        }
+    }
+    impl<K: Hash, V: Hash> Hash for DupTreeMap<K, V> {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.map.hash(state)
+        }
     }
 
     pub type IterInternalIntermediate<'a, K, V> =
@@ -118,14 +152,16 @@ mod dup_tree_map {
             DupTreeMap {
                 map: BTreeMap::new(),
                 size: 0,
+                order: Vec::new(),
             }
         }
 
         pub fn insert(&mut self, key: K, value: V)
         where
-            K: Ord,
+            K: Ord + Clone,
         {
             use std::collections::btree_map::Entry;
+            self.order.push(key.clone());
             match self.map.entry(key) {
                 Entry::Vacant(e) => {
                     e.insert(OneOrMore::new(value));
@@ -157,6 +193,27 @@ mod dup_tree_map {
             }
         }
 
+        /// Iterates over the entries in the order they were inserted.
+        pub fn iter_insertion_order(
+            &self,
+        ) -> impl Iterator<Item = (&K, &V)> + '_
+        where
+            K: Ord,
+        {
+            // Only duplicated keys need to count how many of their values
+            // were already returned.
+            let mut seen: BTreeMap<&K, usize> = BTreeMap::new();
+            self.order.iter().map(move |k| match &self.map[k] {
+                OneOrMore::One(v) => (k, v),
+                values => {
+                    let n = seen.entry(k).or_insert(0);
+                    let v = values.iter().nth(*n).unwrap();
+                    *n += 1;
+                    (k, v)
+                }
+            })
+        }
+
         pub fn iter_mut(&mut self) -> IterMut<'_, K, V>
         where
             K: Ord,
@@ -231,7 +288,7 @@ mod dup_tree_map {
 
     impl<K, V> iter::FromIterator<(K, V)> for DupTreeMap<K, V>
     where
-        K: Ord,
+        K: Ord + Clone,
     {
         fn from_iter<T>(iter: T) -> Self
         where
@@ -303,6 +360,8 @@ mod dup_tree_set {
     use super::DupTreeMap;
     use std::iter;
 
+    /// Iteration is in order, with duplicates next to each other. Like for
+    /// `DupTreeMap`, equality, ordering and hashing agree with iteration.
     #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub struct DupTreeSet<K> {
         map: DupTreeMap<K, ()>,
@@ -381,7 +440,7 @@ mod dup_tree_set {
 
     impl<K> iter::FromIterator<K> for DupTreeSet<K>
     where
-        K: Ord,
+        K: Ord + Clone,
     {
         fn from_iter<T>(iter: T) -> Self
         where
@@ -4,8 +4,13 @@ mod import;
 pub use import::*;
 mod label;
 pub use label::*;
+mod source;
+pub use source::*;
 mod text;
 pub use text::*;
+mod version;
+pub use version::*;
 pub mod context;
 pub mod map;
+mod serialize;
 pub mod visitor;
@@ -0,0 +1,43 @@
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_SOURCE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The text of a parsed source and the name it was given. The spans of the
+/// parsed expression share it, so it is freed along with the last of them.
+pub struct SourceFile {
+    /// Tells sources apart, and orders them by when they were parsed.
+    id: usize,
+    name: Option<Box<str>>,
+    text: Box<str>,
+}
+
+impl SourceFile {
+    pub(crate) fn new(name: Option<&str>, text: &str) -> Rc<SourceFile> {
+        Rc::new(SourceFile {
+            id: NEXT_SOURCE_ID.fetch_add(1, Ordering::Relaxed),
+            name: name.map(Box::from),
+            text: Box::from(text),
+        })
+    }
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+    /// The name the source was given when parsing, typically a file path.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| &**name)
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl std::fmt::Debug for SourceFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // The text can be large, and spans only need to say where they are
+        f.debug_struct("SourceFile")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish()
+    }
+}
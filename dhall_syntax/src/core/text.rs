@@ -1,6 +1,7 @@
+use serde::{Deserialize, Serialize};
 use std::iter::FromIterator;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct InterpolatedText<SubExpr> {
     head: String,
     tail: Vec<(SubExpr, String)>,
@@ -169,7 +170,13 @@ impl<SubExpr> FromIterator<InterpolatedTextContents<SubExpr>>
         let mut crnt_str = &mut res.head;
         for x in iter.into_iter() {
             match x {
-                InterpolatedTextContents::Text(s) => crnt_str.push_str(&s),
+                InterpolatedTextContents::Text(s) => {
+                    if crnt_str.is_empty() {
+                        *crnt_str = s;
+                    } else {
+                        crnt_str.push_str(&s);
+                    }
+                }
                 InterpolatedTextContents::Expr(e) => {
                     res.tail.push((e, String::new()));
                     crnt_str = &mut res.tail.last_mut().unwrap().1;
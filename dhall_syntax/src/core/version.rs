@@ -0,0 +1,174 @@
+use crate::*;
+
+/// A release of the Dhall standard.
+///
+/// Only the releases that introduced or removed syntax supported by this
+/// crate are listed; pinning an older version than the oldest one here is the
+/// same as pinning the oldest one. The crate implements the semantics of
+/// `implemented()`; later versions can be pinned to reject what they removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StandardVersion {
+    V7_0_0,
+    V8_0_0,
+    V9_0_0,
+    V10_0_0,
+    V17_0_0,
+}
+
+/// A language feature that is not available in every supported version of
+/// the standard.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// `toMap x`
+    ToMap,
+    /// `assert : t`
+    Assert,
+    /// `x === y`
+    Equivalence,
+    /// `Natural/subtract`
+    NaturalSubtract,
+    /// `Optional/fold`
+    OptionalFold,
+    /// `Optional/build`
+    OptionalBuild,
+}
+
+impl StandardVersion {
+    /// The most recent version known to this crate.
+    pub fn latest() -> Self {
+        StandardVersion::V17_0_0
+    }
+
+    /// The version whose semantics this crate implements, which expressions
+    /// are parsed against by default.
+    pub fn implemented() -> Self {
+        StandardVersion::V10_0_0
+    }
+}
+
+impl Feature {
+    /// The first version of the standard that includes this feature.
+    pub fn introduced_in(self) -> StandardVersion {
+        use Feature::*;
+        match self {
+            ToMap => StandardVersion::V8_0_0,
+            Assert | Equivalence => StandardVersion::V9_0_0,
+            NaturalSubtract => StandardVersion::V10_0_0,
+            OptionalFold | OptionalBuild => StandardVersion::V7_0_0,
+        }
+    }
+
+    /// The first version of the standard that no longer includes this
+    /// feature, if any.
+    pub fn removed_in(self) -> Option<StandardVersion> {
+        use Feature::*;
+        match self {
+            OptionalFold | OptionalBuild => Some(StandardVersion::V17_0_0),
+            _ => None,
+        }
+    }
+
+    /// Whether this feature can be used when targeting `version`.
+    pub fn is_available_in(self, version: StandardVersion) -> bool {
+        self.introduced_in() <= version
+            && self.removed_in().map_or(true, |removed| version < removed)
+    }
+
+    /// Fails with an explanation if this feature can't be used when targeting
+    /// `version`. This is the one check shared by the parser and by
+    /// `first_unavailable_feature`.
+    pub fn check_available_in(
+        self,
+        version: StandardVersion,
+    ) -> Result<(), String> {
+        if self.is_available_in(version) {
+            Ok(())
+        } else {
+            Err(self.unavailable_message(version))
+        }
+    }
+
+    /// How to do without this feature once it is removed, if it was.
+    pub fn replacement(self) -> Option<&'static str> {
+        use Feature::*;
+        match self {
+            OptionalFold => Some("use `merge` on the `Optional` instead"),
+            OptionalBuild => {
+                Some("build the `Optional` with `Some` and `None` instead")
+            }
+            ToMap | Assert | Equivalence | NaturalSubtract => None,
+        }
+    }
+
+    /// Explains why this feature can't be used when targeting `version`.
+    pub fn unavailable_message(self, version: StandardVersion) -> String {
+        match self.removed_in() {
+            Some(removed) if removed <= version => {
+                let mut message = format!(
+                    "`{}` is not available in version {} of the standard; it was removed in {}",
+                    self, version, removed
+                );
+                if let Some(replacement) = self.replacement() {
+                    message.push_str("; ");
+                    message.push_str(replacement);
+                }
+                message
+            }
+            _ => format!(
+                "`{}` is not available in version {} of the standard; it was introduced in {}",
+                self,
+                version,
+                self.introduced_in()
+            ),
+        }
+    }
+
+    fn used_by<SE, E>(e: &ExprF<SE, E>) -> Option<Self> {
+        match e {
+            ExprF::ToMap(_, _) => Some(Feature::ToMap),
+            ExprF::Assert(_) => Some(Feature::Assert),
+            ExprF::BinOp(BinOp::Equivalence, _, _) => {
+                Some(Feature::Equivalence)
+            }
+            ExprF::Builtin(Builtin::NaturalSubtract) => {
+                Some(Feature::NaturalSubtract)
+            }
+            ExprF::Builtin(Builtin::OptionalFold) => {
+                Some(Feature::OptionalFold)
+            }
+            ExprF::Builtin(Builtin::OptionalBuild) => {
+                Some(Feature::OptionalBuild)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<E: Clone> Expr<E> {
+    /// Lists the versioned features used in this expression, in the order
+    /// they first appear.
+    pub fn features_used(&self) -> Vec<Feature> {
+        fn go<E: Clone>(e: &Expr<E>, found: &mut Vec<Feature>) {
+            if let Some(feature) = Feature::used_by(e.as_ref()) {
+                if !found.contains(&feature) {
+                    found.push(feature);
+                }
+            }
+            e.as_ref().map_ref(|e| go(e, found));
+        }
+        let mut found = Vec::new();
+        go(self, &mut found);
+        found
+    }
+
+    /// Returns the first feature used in this expression that is not
+    /// available in `version`, if any.
+    pub fn first_unavailable_feature(
+        &self,
+        version: StandardVersion,
+    ) -> Option<Feature> {
+        self.features_used()
+            .into_iter()
+            .find(|feature| feature.check_available_in(version).is_err())
+    }
+}
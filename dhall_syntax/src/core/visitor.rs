@@ -148,9 +148,9 @@ where
         EmptyListLit(t) => EmptyListLit(v.visit_subexpr(t)?),
         NEListLit(es) => NEListLit(vec(es, |e| v.visit_subexpr(e))?),
         SomeLit(e) => SomeLit(v.visit_subexpr(e)?),
-        RecordType(kts) => RecordType(dupmap(kts, v)?),
-        RecordLit(kvs) => RecordLit(dupmap(kvs, v)?),
-        UnionType(kts) => UnionType(optdupmap(kts, v)?),
+        RecordType(kts) => RecordType(dupmap(kts.iter_insertion_order(), v)?),
+        RecordLit(kvs) => RecordLit(dupmap(kvs.iter_insertion_order(), v)?),
+        UnionType(kts) => UnionType(optdupmap(kts.iter_insertion_order(), v)?),
         Merge(x, y, t) => Merge(
             v.visit_subexpr(x)?,
             v.visit_subexpr(y)?,
@@ -9,7 +9,7 @@ use std::rc::Rc;
 use dhall_generated_parser::{DhallParser, Rule};
 use dhall_proc_macros::{make_parser, parse_children};
 
-use crate::map::{DupTreeMap, DupTreeSet};
+use crate::map::DupTreeSet;
 use crate::ExprF::*;
 use crate::*;
 
@@ -25,16 +25,19 @@ pub type ParseError = pest::error::Error<Rule>;
 
 pub type ParseResult<T> = Result<T, ParseError>;
 
+/// A node of the parse tree, along with some user data that is threaded
+/// through all the parsing functions.
 #[derive(Debug, Clone)]
-struct ParseInput<'input, Rule>
+struct ParseInput<'input, Rule, Data>
 where
     Rule: pest::RuleType,
 {
     pair: Pair<'input, Rule>,
-    original_input_str: Rc<str>,
+    source: Rc<SourceFile>,
+    user_data: Data,
 }
 
-impl<'input> ParseInput<'input, Rule> {
+impl<'input, Data: Clone> ParseInput<'input, Rule, Data> {
     fn error(&self, message: String) -> ParseError {
         let message = format!(
             "{} while matching on:\n{}",
@@ -44,20 +47,31 @@ impl<'input> ParseInput<'input, Rule> {
         let e = pest::error::ErrorVariant::CustomError { message };
         pest::error::Error::new_from_span(e, self.pair.as_span())
     }
-    fn parse(input_str: &'input str, rule: Rule) -> ParseResult<Self> {
-        let mut pairs = DhallParser::parse(rule, input_str)?;
+    fn parse_with_user_data(
+        input_str: &'input str,
+        source_name: Option<&str>,
+        rule: Rule,
+        user_data: Data,
+    ) -> ParseResult<Self> {
+        let mut pairs = DhallParser::parse(rule, input_str)
+            .map_err(|e| explain_legacy_syntax(input_str, e))?;
         // TODO: proper errors
         let pair = pairs.next().unwrap();
         assert_eq!(pairs.next(), None);
         Ok(ParseInput {
-            original_input_str: input_str.to_string().into(),
+            source: SourceFile::new(source_name, input_str),
             pair,
+            user_data,
         })
     }
+    fn user_data(&self) -> &Data {
+        &self.user_data
+    }
     fn with_pair(&self, new_pair: Pair<'input, Rule>) -> Self {
         ParseInput {
             pair: new_pair,
-            original_input_str: self.original_input_str.clone(),
+            source: self.source.clone(),
+            user_data: self.user_data.clone(),
         }
     }
     /// If the contained pair has exactly one child, return a new Self containing it.
@@ -71,7 +85,7 @@ impl<'input> ParseInput<'input, Rule> {
         None
     }
     fn as_span(&self) -> Span {
-        Span::make(self.original_input_str.clone(), self.pair.as_span())
+        Span::make(self.source.clone(), self.pair.as_span())
     }
     fn as_str(&self) -> &'input str {
         self.pair.as_str()
@@ -129,6 +143,21 @@ fn debug_pair(pair: Pair<Rule>) -> String {
     s
 }
 
+/// The user data of the Dhall parser.
+#[derive(Debug, Clone, Copy)]
+struct ParseState {
+    /// Syntax introduced after this version is rejected.
+    version: StandardVersion,
+}
+
+impl<'input> ParseInput<'input, Rule, ParseState> {
+    fn check_feature(&self, feature: Feature) -> ParseResult<()> {
+        feature
+            .check_available_in(self.user_data().version)
+            .map_err(|message| self.error(message))
+    }
+}
+
 #[derive(Debug)]
 enum Either<A, B> {
     Left(A),
@@ -214,29 +243,38 @@ fn trim_indent<E: Clone>(lines: &mut Vec<ParsedText<E>>) {
     }
 }
 
+/// The grammar rule of each binary operator.
+fn operator_rule(op: crate::BinOp) -> Rule {
+    use crate::BinOp::*;
+    match op {
+        ImportAlt => Rule::import_alt,
+        BoolOr => Rule::bool_or,
+        NaturalPlus => Rule::natural_plus,
+        TextAppend => Rule::text_append,
+        ListAppend => Rule::list_append,
+        BoolAnd => Rule::bool_and,
+        RecursiveRecordMerge => Rule::combine,
+        RightBiasedRecordMerge => Rule::prefer,
+        RecursiveRecordTypeMerge => Rule::combine_types,
+        NaturalTimes => Rule::natural_times,
+        BoolEQ => Rule::bool_eq,
+        BoolNE => Rule::bool_ne,
+        Equivalence => Rule::equivalent,
+    }
+}
+
 lazy_static::lazy_static! {
     static ref PRECCLIMBER: PrecClimber<Rule> = {
-        use Rule::*;
-        // In order of precedence
-        let operators = vec![
-            import_alt,
-            bool_or,
-            natural_plus,
-            text_append,
-            list_append,
-            bool_and,
-            combine,
-            prefer,
-            combine_types,
-            natural_times,
-            bool_eq,
-            bool_ne,
-            equivalent,
-        ];
         PrecClimber::new(
-            operators
-                .into_iter()
-                .map(|op| pcl::Operator::new(op, pcl::Assoc::Left))
+            crate::BinOp::ALL
+                .iter()
+                .map(|&op| {
+                    let assoc = match op.associativity() {
+                        Associativity::Left => pcl::Assoc::Left,
+                        Associativity::Right => pcl::Assoc::Right,
+                    };
+                    pcl::Operator::new(operator_rule(op), assoc)
+                })
                 .collect(),
         )
     };
@@ -246,21 +284,18 @@ struct Parsers;
 
 #[make_parser(Rule)]
 impl Parsers {
-    fn EOI(_input: ParseInput<Rule>) -> ParseResult<()> {
+    fn EOI(_input: ParseInput<Rule, ParseState>) -> ParseResult<()> {
         Ok(())
     }
 
     #[alias(label)]
-    fn simple_label(input: ParseInput<Rule>) -> ParseResult<Label> {
-        Ok(Label::from(input.as_str()))
-    }
-    #[alias(label)]
-    fn quoted_label(input: ParseInput<Rule>) -> ParseResult<Label> {
+    #[handles(quoted_label)]
+    fn simple_label(input: ParseInput<Rule, ParseState>) -> ParseResult<Label> {
         Ok(Label::from(input.as_str()))
     }
 
     fn double_quote_literal<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<ParsedText<E>> {
         Ok(parse_children!(input;
             [double_quote_chunk(chunks)..] => {
@@ -270,7 +305,7 @@ impl Parsers {
     }
 
     fn double_quote_chunk<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<ParsedTextContents<E>> {
         Ok(parse_children!(input;
             [expression(e)] => {
@@ -282,7 +317,9 @@ impl Parsers {
         ))
     }
     #[alias(double_quote_char)]
-    fn double_quote_escaped(input: ParseInput<Rule>) -> ParseResult<String> {
+    fn double_quote_escaped(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<String> {
         Ok(match input.as_str() {
             "\"" => "\"".to_owned(),
             "$" => "$".to_owned(),
@@ -295,36 +332,20 @@ impl Parsers {
             "t" => "\t".to_owned(),
             // "uXXXX" or "u{XXXXX}"
             s => {
-                use std::convert::{TryFrom, TryInto};
-
-                let s = &s[1..];
-                let s = if &s[0..1] == "{" {
-                    &s[1..s.len() - 1]
-                } else {
-                    &s[0..s.len()]
-                };
-
-                if s.len() > 8 {
-                    Err(input.error(format!(
-                        "Escape sequences can't have more than 8 chars: \"{}\"",
+                let digits =
+                    s[1..].trim_start_matches('{').trim_end_matches('}');
+                // Leading zeroes don't overflow, so only values that are too
+                // large fail here.
+                let i = match u32::from_str_radix(digits, 16) {
+                    Ok(i) if i <= 0x10_FFFF => i,
+                    _ => Err(input.error(format!(
+                        "Escape sequences can't be above \\u{{10FFFF}}: \"\\{}\"",
                         s
-                    )))?
-                }
-
-                // pad with zeroes
-                let s: String = std::iter::repeat('0')
-                    .take(8 - s.len())
-                    .chain(s.chars())
-                    .collect();
-
-                // `s` has length 8, so `bytes` has length 4
-                let bytes: &[u8] = &hex::decode(s).unwrap();
-                let i = u32::from_be_bytes(bytes.try_into().unwrap());
-                let c = char::try_from(i).unwrap();
+                    )))?,
+                };
                 match i {
                     0xD800..=0xDFFF => {
-                        let c_ecapsed = c.escape_unicode();
-                        Err(input.error(format!("Escape sequences can't contain surrogate pairs: \"{}\"", c_ecapsed)))?
+                        Err(input.error(format!("Escape sequences can't contain surrogate pairs: \"\\{}\"", s)))?
                     }
                     0x0FFFE..=0x0FFFF
                     | 0x1FFFE..=0x1FFFF
@@ -343,33 +364,47 @@ impl Parsers {
                     | 0xEFFFE..=0xEFFFF
                     | 0xFFFFE..=0xFFFFF
                     | 0x10_FFFE..=0x10_FFFF => {
-                        let c_ecapsed = c.escape_unicode();
-                        Err(input.error(format!("Escape sequences can't contain non-characters: \"{}\"", c_ecapsed)))?
+                        Err(input.error(format!("Escape sequences can't contain non-characters: \"\\{}\"", s)))?
                     }
                     _ => {}
                 }
+                // Surrogates were ruled out above
+                let c = std::char::from_u32(i).unwrap();
                 std::iter::once(c).collect()
             }
         })
     }
     fn double_quote_char<'a>(
-        input: ParseInput<'a, Rule>,
+        input: ParseInput<'a, Rule, ParseState>,
     ) -> ParseResult<String> {
         Ok(input.as_str().to_owned())
     }
 
     fn single_quote_literal<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<ParsedText<E>> {
         Ok(parse_children!(input;
             [single_quote_continue(lines)] => {
                 let newline: ParsedText<E> = "\n".to_string().into();
 
-                // Reverse lines and chars in each line
+                // Reverse lines and chunks in each line, and the text of
+                // each chunk
                 let mut lines: Vec<ParsedText<E>> = lines
                     .into_iter()
                     .rev()
-                    .map(|l| l.into_iter().rev().collect::<ParsedText<E>>())
+                    .map(|l| {
+                        l.into_iter()
+                            .rev()
+                            .map(|c| match c {
+                                InterpolatedTextContents::Text(s) => {
+                                    InterpolatedTextContents::Text(
+                                        s.chars().rev().collect(),
+                                    )
+                                }
+                                c => c,
+                            })
+                            .collect::<ParsedText<E>>()
+                    })
                     .collect();
 
                 trim_indent(&mut lines);
@@ -383,26 +418,28 @@ impl Parsers {
         ))
     }
     fn single_quote_char<'a>(
-        input: ParseInput<'a, Rule>,
+        input: ParseInput<'a, Rule, ParseState>,
     ) -> ParseResult<&'a str> {
         Ok(input.as_str())
     }
     #[alias(single_quote_char)]
     fn escaped_quote_pair<'a>(
-        _input: ParseInput<'a, Rule>,
+        _input: ParseInput<'a, Rule, ParseState>,
     ) -> ParseResult<&'a str> {
         Ok("''")
     }
     #[alias(single_quote_char)]
     fn escaped_interpolation<'a>(
-        _input: ParseInput<'a, Rule>,
+        _input: ParseInput<'a, Rule, ParseState>,
     ) -> ParseResult<&'a str> {
         Ok("${")
     }
 
     // Returns a vec of lines in reversed order, where each line is also in reversed order.
+    // Consecutive chars are accumulated in a single text chunk, whose text is
+    // reversed too so that it can be grown at the end.
     fn single_quote_continue<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Vec<Vec<ParsedTextContents<E>>>> {
         Ok(parse_children!(input;
             [expression(e), single_quote_continue(lines)] => {
@@ -416,9 +453,15 @@ impl Parsers {
                 if c == "\n" || c == "\r\n" {
                     lines.push(vec![]);
                 } else {
-                    // TODO: don't allocate for every char
-                    let c = InterpolatedTextContents::Text(c.to_owned());
-                    lines.last_mut().unwrap().push(c);
+                    let line = lines.last_mut().unwrap();
+                    match line.last_mut() {
+                        Some(InterpolatedTextContents::Text(s)) => {
+                            s.extend(c.chars().rev())
+                        }
+                        _ => line.push(InterpolatedTextContents::Text(
+                            c.chars().rev().collect(),
+                        )),
+                    }
                 }
                 lines
             },
@@ -429,9 +472,23 @@ impl Parsers {
     }
 
     #[alias(expression)]
-    fn builtin<E: Clone>(input: ParseInput<Rule>) -> ParseResult<Expr<E>> {
+    fn builtin<E: Clone>(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<Expr<E>> {
         let s = input.as_str();
         let e = match crate::Builtin::parse(s) {
+            Some(b @ crate::Builtin::NaturalSubtract) => {
+                input.check_feature(Feature::NaturalSubtract)?;
+                Builtin(b)
+            }
+            Some(b @ crate::Builtin::OptionalFold) => {
+                input.check_feature(Feature::OptionalFold)?;
+                Builtin(b)
+            }
+            Some(b @ crate::Builtin::OptionalBuild) => {
+                input.check_feature(Feature::OptionalBuild)?;
+                Builtin(b)
+            }
             Some(b) => Builtin(b),
             None => match s {
                 "True" => BoolLit(true),
@@ -446,25 +503,25 @@ impl Parsers {
     }
 
     #[alias(double_literal)]
-    fn NaN(_input: ParseInput<Rule>) -> ParseResult<core::Double> {
+    fn NaN(_input: ParseInput<Rule, ParseState>) -> ParseResult<core::Double> {
         Ok(std::f64::NAN.into())
     }
     #[alias(double_literal)]
     fn minus_infinity_literal(
-        _input: ParseInput<Rule>,
+        _input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<core::Double> {
         Ok(std::f64::NEG_INFINITY.into())
     }
     #[alias(double_literal)]
     fn plus_infinity_literal(
-        _input: ParseInput<Rule>,
+        _input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<core::Double> {
         Ok(std::f64::INFINITY.into())
     }
 
     #[alias(double_literal)]
     fn numeric_double_literal(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<core::Double> {
         let s = input.as_str().trim();
         match s.parse::<f64>() {
@@ -477,24 +534,39 @@ impl Parsers {
         }
     }
 
-    fn natural_literal(input: ParseInput<Rule>) -> ParseResult<core::Natural> {
-        input
-            .as_str()
-            .trim()
-            .parse()
-            .map_err(|e| input.error(format!("{}", e)))
+    fn natural_literal(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<core::Natural> {
+        let s = input.as_str().trim();
+        let n = if s.starts_with("0x") {
+            core::Natural::from_str_radix(&s[2..], 16)
+        } else {
+            s.parse()
+        };
+        n.map_err(|e| input.error(format!("{}", e)))
     }
 
-    fn integer_literal(input: ParseInput<Rule>) -> ParseResult<core::Integer> {
-        input
-            .as_str()
-            .trim()
-            .parse()
-            .map_err(|e| input.error(format!("{}", e)))
+    fn integer_literal(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<core::Integer> {
+        let s = input.as_str().trim();
+        // The sign is always there, e.g. `+0xFF`
+        let (sign, digits) = s.split_at(1);
+        let n = if digits.starts_with("0x") {
+            core::Integer::from_str_radix(
+                &format!("{}{}", sign, &digits[2..]),
+                16,
+            )
+        } else {
+            s.parse()
+        };
+        n.map_err(|e| input.error(format!("{}", e)))
     }
 
     #[alias(expression, shortcut = true)]
-    fn identifier<E: Clone>(input: ParseInput<Rule>) -> ParseResult<Expr<E>> {
+    fn identifier<E: Clone>(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<Expr<E>> {
         Ok(parse_children!(input;
             [variable(v)] => {
                 spanned(input.as_span(), Var(v))
@@ -503,7 +575,7 @@ impl Parsers {
         ))
     }
 
-    fn variable(input: ParseInput<Rule>) -> ParseResult<V<Label>> {
+    fn variable(input: ParseInput<Rule, ParseState>) -> ParseResult<V<Label>> {
         Ok(parse_children!(input;
             [label(l), natural_literal(idx)] => {
                 V(l, idx)
@@ -516,13 +588,13 @@ impl Parsers {
 
     #[alias(path_component)]
     fn unquoted_path_component<'a>(
-        input: ParseInput<'a, Rule>,
+        input: ParseInput<'a, Rule, ParseState>,
     ) -> ParseResult<String> {
         Ok(input.as_str().to_string())
     }
     #[alias(path_component)]
     fn quoted_path_component<'a>(
-        input: ParseInput<'a, Rule>,
+        input: ParseInput<'a, Rule, ParseState>,
     ) -> ParseResult<String> {
         #[rustfmt::skip]
         const RESERVED: &percent_encoding::AsciiSet =
@@ -549,7 +621,7 @@ impl Parsers {
             })
             .collect())
     }
-    fn path(input: ParseInput<Rule>) -> ParseResult<Vec<String>> {
+    fn path(input: ParseInput<Rule, ParseState>) -> ParseResult<Vec<String>> {
         Ok(parse_children!(input;
             [path_component(components)..] => {
                 components.collect()
@@ -559,7 +631,7 @@ impl Parsers {
 
     #[alias(import_type)]
     fn local<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<ImportLocation<Expr<E>>> {
         Ok(parse_children!(input;
             [local_path((prefix, p))] => ImportLocation::Local(prefix, p),
@@ -567,39 +639,22 @@ impl Parsers {
     }
 
     #[alias(local_path)]
+    #[handles(here_path, home_path, absolute_path)]
     fn parent_path(
-        input: ParseInput<Rule>,
-    ) -> ParseResult<(FilePrefix, Vec<String>)> {
-        Ok(parse_children!(input;
-            [path(p)] => (FilePrefix::Parent, p)
-        ))
-    }
-    #[alias(local_path)]
-    fn here_path(
-        input: ParseInput<Rule>,
-    ) -> ParseResult<(FilePrefix, Vec<String>)> {
-        Ok(parse_children!(input;
-            [path(p)] => (FilePrefix::Here, p)
-        ))
-    }
-    #[alias(local_path)]
-    fn home_path(
-        input: ParseInput<Rule>,
-    ) -> ParseResult<(FilePrefix, Vec<String>)> {
-        Ok(parse_children!(input;
-            [path(p)] => (FilePrefix::Home, p)
-        ))
-    }
-    #[alias(local_path)]
-    fn absolute_path(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<(FilePrefix, Vec<String>)> {
+        let prefix = match input.as_rule() {
+            Rule::parent_path => FilePrefix::Parent,
+            Rule::here_path => FilePrefix::Here,
+            Rule::home_path => FilePrefix::Home,
+            _ => FilePrefix::Absolute,
+        };
         Ok(parse_children!(input;
-            [path(p)] => (FilePrefix::Absolute, p)
+            [path(p)] => (prefix, p)
         ))
     }
 
-    fn scheme(input: ParseInput<Rule>) -> ParseResult<Scheme> {
+    fn scheme(input: ParseInput<Rule, ParseState>) -> ParseResult<Scheme> {
         Ok(match input.as_str() {
             "http" => Scheme::HTTP,
             "https" => Scheme::HTTPS,
@@ -608,47 +663,39 @@ impl Parsers {
     }
 
     fn http_raw<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<URL<Expr<E>>> {
         Ok(parse_children!(input;
-            [scheme(sch), authority(auth), path(p)] => URL {
+            [scheme(sch), authority(auth), path(p), opt(query(q))] => URL {
                 scheme: sch,
                 authority: auth,
                 path: p,
-                query: None,
-                headers: None,
-            },
-            [scheme(sch), authority(auth), path(p), query(q)] => URL {
-                scheme: sch,
-                authority: auth,
-                path: p,
-                query: Some(q),
+                query: q,
                 headers: None,
             },
         ))
     }
 
-    fn authority(input: ParseInput<Rule>) -> ParseResult<String> {
+    fn authority(input: ParseInput<Rule, ParseState>) -> ParseResult<String> {
         Ok(input.as_str().to_owned())
     }
 
-    fn query(input: ParseInput<Rule>) -> ParseResult<String> {
+    fn query(input: ParseInput<Rule, ParseState>) -> ParseResult<String> {
         Ok(input.as_str().to_owned())
     }
 
     #[alias(import_type)]
     fn http<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<ImportLocation<Expr<E>>> {
         Ok(ImportLocation::Remote(parse_children!(input;
-            [http_raw(url)] => url,
-            [http_raw(url), expression(e)] => URL { headers: Some(e), ..url },
+            [http_raw(url), opt(expression(headers))] => URL { headers, ..url },
         )))
     }
 
     #[alias(import_type)]
     fn env<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<ImportLocation<Expr<E>>> {
         Ok(parse_children!(input;
             [environment_variable(v)] => ImportLocation::Env(v),
@@ -656,13 +703,13 @@ impl Parsers {
     }
     #[alias(environment_variable)]
     fn bash_environment_variable(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<String> {
         Ok(input.as_str().to_owned())
     }
     #[alias(environment_variable)]
     fn posix_environment_variable(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<String> {
         Ok(parse_children!(input;
             [posix_environment_variable_character(chars)..] => {
@@ -671,7 +718,7 @@ impl Parsers {
         ))
     }
     fn posix_environment_variable_character<'a>(
-        input: ParseInput<'a, Rule>,
+        input: ParseInput<'a, Rule, ParseState>,
     ) -> ParseResult<Cow<'a, str>> {
         Ok(match input.as_str() {
             "\\\"" => Cow::Owned("\"".to_owned()),
@@ -689,87 +736,76 @@ impl Parsers {
 
     #[alias(import_type)]
     fn missing<E: Clone>(
-        _input: ParseInput<Rule>,
+        _input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<ImportLocation<Expr<E>>> {
         Ok(ImportLocation::Missing)
     }
 
-    fn hash(input: ParseInput<Rule>) -> ParseResult<Hash> {
+    fn hash(input: ParseInput<Rule, ParseState>) -> ParseResult<Hash> {
         let s = input.as_str().trim();
-        let protocol = &s[..6];
-        let hash = &s[7..];
-        if protocol != "sha256" {
-            Err(input.error(format!("Unknown hashing protocol '{}'", protocol)))?
-        }
-        Ok(Hash::SHA256(hex::decode(hash).unwrap()))
+        s.parse().map_err(|e: HashError| input.error(e.to_string()))
     }
 
     fn import_hashed<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<crate::Import<Expr<E>>> {
         use crate::Import;
         let mode = ImportMode::Code;
         Ok(parse_children!(input;
-            [import_type(location)] => Import { mode, location, hash: None },
-            [import_type(location), hash(h)] => Import { mode, location, hash: Some(h) },
+            [import_type(location), opt(hash(hash))] => {
+                Import { mode, location, hash }
+            },
         ))
     }
 
     #[alias(import_mode)]
-    fn Text(_input: ParseInput<Rule>) -> ParseResult<ImportMode> {
+    fn Text(_input: ParseInput<Rule, ParseState>) -> ParseResult<ImportMode> {
         Ok(ImportMode::RawText)
     }
     #[alias(import_mode)]
-    fn Location(_input: ParseInput<Rule>) -> ParseResult<ImportMode> {
+    fn Location(
+        _input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<ImportMode> {
         Ok(ImportMode::Location)
     }
 
     #[alias(expression)]
-    fn import<E: Clone>(input: ParseInput<Rule>) -> ParseResult<Expr<E>> {
+    fn import<E: Clone>(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<Expr<E>> {
         use crate::Import;
         let import = parse_children!(input;
-            [import_hashed(imp)] => {
-                Import { mode: ImportMode::Code, ..imp }
-            },
-            [import_hashed(imp), import_mode(mode)] => {
-                Import { mode, ..imp }
+            [import_hashed(imp), opt(import_mode(mode))] => Import {
+                mode: mode.unwrap_or(ImportMode::Code),
+                ..imp
             },
         );
         Ok(spanned(input.as_span(), Import(import)))
     }
 
-    fn lambda(_input: ParseInput<Rule>) -> ParseResult<()> {
-        Ok(())
-    }
-    fn forall(_input: ParseInput<Rule>) -> ParseResult<()> {
-        Ok(())
-    }
-    fn arrow(_input: ParseInput<Rule>) -> ParseResult<()> {
-        Ok(())
-    }
-    fn merge(_input: ParseInput<Rule>) -> ParseResult<()> {
+    #[handles(forall, arrow, merge, if_)]
+    fn lambda(_input: ParseInput<Rule, ParseState>) -> ParseResult<()> {
         Ok(())
     }
-    fn assert(_input: ParseInput<Rule>) -> ParseResult<()> {
-        Ok(())
-    }
-    fn if_(_input: ParseInput<Rule>) -> ParseResult<()> {
-        Ok(())
+    fn assert(input: ParseInput<Rule, ParseState>) -> ParseResult<()> {
+        input.check_feature(Feature::Assert)
     }
-    fn toMap(_input: ParseInput<Rule>) -> ParseResult<()> {
-        Ok(())
+    fn toMap(input: ParseInput<Rule, ParseState>) -> ParseResult<()> {
+        input.check_feature(Feature::ToMap)
     }
 
     #[alias(expression)]
     fn empty_list_literal<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         Ok(parse_children!(input;
             [expression(e)] => spanned(input.as_span(), EmptyListLit(e)),
         ))
     }
 
-    fn expression<E: Clone>(input: ParseInput<Rule>) -> ParseResult<Expr<E>> {
+    fn expression<E: Clone>(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<Expr<E>> {
         let span = input.as_span();
         Ok(parse_children!(input;
             [lambda(()), label(l), expression(typ),
@@ -783,7 +819,14 @@ impl Parsers {
             [let_binding(bindings).., expression(final_expr)] => {
                 bindings.rev().fold(
                     final_expr,
-                    |acc, x| unspanned(Let(x.0, x.1, x.2, acc))
+                    |acc, (l, t, v, span)| {
+                        let last = acc.span().cloned();
+                        spanned_between(
+                            Some(&span),
+                            last.as_ref(),
+                            Let(l, t, v, acc),
+                        )
+                    }
                 )
             },
             [forall(()), label(l), expression(typ),
@@ -810,65 +853,68 @@ impl Parsers {
     }
 
     fn let_binding<E: Clone>(
-        input: ParseInput<Rule>,
-    ) -> ParseResult<(Label, Option<Expr<E>>, Expr<E>)> {
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<(Label, Option<Expr<E>>, Expr<E>, Span)> {
+        let span = input.as_span();
         Ok(parse_children!(input;
-            [label(name), expression(annot), expression(expr)] =>
-                (name, Some(annot), expr),
-            [label(name), expression(expr)] =>
-                (name, None, expr),
+            [label(name), opt(expression(annot)), expression(expr)] =>
+                (name, annot, expr, span),
         ))
     }
 
     #[alias(expression, shortcut = true)]
     #[prec_climb(expression, PRECCLIMBER)]
     fn operator_expression<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
         l: Expr<E>,
         op: Pair<Rule>,
         r: Expr<E>,
     ) -> ParseResult<Expr<E>> {
-        use crate::BinOp::*;
-        use Rule::*;
-        let op = match op.as_rule() {
-            import_alt => ImportAlt,
-            bool_or => BoolOr,
-            natural_plus => NaturalPlus,
-            text_append => TextAppend,
-            list_append => ListAppend,
-            bool_and => BoolAnd,
-            combine => RecursiveRecordMerge,
-            prefer => RightBiasedRecordMerge,
-            combine_types => RecursiveRecordTypeMerge,
-            natural_times => NaturalTimes,
-            bool_eq => BoolEQ,
-            bool_ne => BoolNE,
-            equivalent => Equivalence,
-            r => Err(input.error(format!("Rule {:?} isn't an operator", r)))?,
+        let op_pair = op.clone();
+        let op = match crate::BinOp::ALL
+            .iter()
+            .find(|&&o| operator_rule(o) == op.as_rule())
+        {
+            Some(&op) => op,
+            None => Err(input
+                .error(format!("Rule {:?} isn't an operator", op.as_rule())))?,
         };
+        if op == crate::BinOp::Equivalence {
+            input
+                .with_pair(op_pair)
+                .check_feature(Feature::Equivalence)?;
+        }
 
-        Ok(unspanned(BinOp(op, l, r)))
+        let (first, last) = (l.span().cloned(), r.span().cloned());
+        Ok(spanned_between(
+            first.as_ref(),
+            last.as_ref(),
+            BinOp(op, l, r),
+        ))
     }
 
-    fn Some_(_input: ParseInput<Rule>) -> ParseResult<()> {
+    fn Some_(_input: ParseInput<Rule, ParseState>) -> ParseResult<()> {
         Ok(())
     }
 
     #[alias(expression, shortcut = true)]
     fn application_expression<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         Ok(parse_children!(input;
             [expression(e)] => e,
             [expression(first), expression(rest)..] => {
-                rest.fold(first, |acc, e| unspanned(App(acc, e)))
+                rest.fold(first, |acc, e| {
+                    let (first, last) = (acc.span().cloned(), e.span().cloned());
+                    spanned_between(first.as_ref(), last.as_ref(), App(acc, e))
+                })
             },
         ))
     }
 
     #[alias(expression, shortcut = true)]
     fn first_application_expression<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         let span = input.as_span();
         Ok(parse_children!(input;
@@ -887,30 +933,37 @@ impl Parsers {
 
     #[alias(expression, shortcut = true)]
     fn selector_expression<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         Ok(parse_children!(input;
             [expression(e)] => e,
             [expression(first), selector(rest)..] => {
-                rest.fold(first, |acc, e| unspanned(match e {
-                    Either::Left(l) => Field(acc, l),
-                    Either::Right(ls) => Projection(acc, ls),
-                }))
+                rest.fold(first, |acc, (e, span)| {
+                    let first = acc.span().cloned();
+                    let e = match e {
+                        Either::Left(l) => Field(acc, l),
+                        Either::Right(ls) => Projection(acc, ls),
+                    };
+                    spanned_between(first.as_ref(), Some(&span), e)
+                })
             },
         ))
     }
 
     fn selector(
-        input: ParseInput<Rule>,
-    ) -> ParseResult<Either<Label, DupTreeSet<Label>>> {
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<(Either<Label, DupTreeSet<Label>>, Span)> {
+        let span = input.as_span();
         Ok(parse_children!(input;
-            [label(l)] => Either::Left(l),
-            [labels(ls)] => Either::Right(ls),
+            [label(l)] => (Either::Left(l), span),
+            [labels(ls)] => (Either::Right(ls), span),
             // [expression(_e)] => unimplemented!("selection by expression"), // TODO
         ))
     }
 
-    fn labels(input: ParseInput<Rule>) -> ParseResult<DupTreeSet<Label>> {
+    fn labels(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<DupTreeSet<Label>> {
         Ok(parse_children!(input;
             [label(ls)..] => ls.collect(),
         ))
@@ -918,55 +971,55 @@ impl Parsers {
 
     #[alias(expression, shortcut = true)]
     fn primitive_expression<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         let span = input.as_span();
         Ok(parse_children!(input;
             [double_literal(n)] => spanned(span, DoubleLit(n)),
             [natural_literal(n)] => spanned(span, NaturalLit(n)),
             [integer_literal(n)] => spanned(span, IntegerLit(n)),
-            [double_quote_literal(s)] => spanned(span, TextLit(s)),
-            [single_quote_literal(s)] => spanned(span, TextLit(s)),
+            [either(double_quote_literal|single_quote_literal)(s)] =>
+                spanned(span, TextLit(s)),
             [expression(e)] => e,
         ))
     }
 
     #[alias(expression)]
     fn empty_record_literal<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         Ok(spanned(input.as_span(), RecordLit(Default::default())))
     }
 
     #[alias(expression)]
     fn empty_record_type<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         Ok(spanned(input.as_span(), RecordType(Default::default())))
     }
 
     #[alias(expression)]
     fn non_empty_record_type_or_literal<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         let e = parse_children!(input;
             [label(first_label), non_empty_record_type(rest)] => {
-                let (first_expr, mut map) = rest;
-                map.insert(first_label, first_expr);
-                RecordType(map)
+                let (first_expr, rest) = rest;
+                let first = std::iter::once((first_label, first_expr));
+                RecordType(first.chain(rest).collect())
             },
             [label(first_label), non_empty_record_literal(rest)] => {
-                let (first_expr, mut map) = rest;
-                map.insert(first_label, first_expr);
-                RecordLit(map)
+                let (first_expr, rest) = rest;
+                let first = std::iter::once((first_label, first_expr));
+                RecordLit(first.chain(rest).collect())
             },
         );
         Ok(spanned(input.as_span(), e))
     }
 
     fn non_empty_record_type<E: Clone>(
-        input: ParseInput<Rule>,
-    ) -> ParseResult<(Expr<E>, DupTreeMap<Label, Expr<E>>)> {
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<(Expr<E>, Vec<(Label, Expr<E>)>)> {
         Ok(parse_children!(input;
             [expression(expr), record_type_entry(entries)..] => {
                 (expr, entries.collect())
@@ -975,7 +1028,7 @@ impl Parsers {
     }
 
     fn record_type_entry<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<(Label, Expr<E>)> {
         Ok(parse_children!(input;
             [label(name), expression(expr)] => (name, expr)
@@ -983,8 +1036,8 @@ impl Parsers {
     }
 
     fn non_empty_record_literal<E: Clone>(
-        input: ParseInput<Rule>,
-    ) -> ParseResult<(Expr<E>, DupTreeMap<Label, Expr<E>>)> {
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<(Expr<E>, Vec<(Label, Expr<E>)>)> {
         Ok(parse_children!(input;
             [expression(expr), record_literal_entry(entries)..] => {
                 (expr, entries.collect())
@@ -993,7 +1046,7 @@ impl Parsers {
     }
 
     fn record_literal_entry<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<(Label, Expr<E>)> {
         Ok(parse_children!(input;
             [label(name), expression(expr)] => (name, expr)
@@ -1001,7 +1054,9 @@ impl Parsers {
     }
 
     #[alias(expression)]
-    fn union_type<E: Clone>(input: ParseInput<Rule>) -> ParseResult<Expr<E>> {
+    fn union_type<E: Clone>(
+        input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<Expr<E>> {
         let map = parse_children!(input;
             [empty_union_type(_)] => Default::default(),
             [union_type_entry(entries)..] => entries.collect(),
@@ -1009,22 +1064,23 @@ impl Parsers {
         Ok(spanned(input.as_span(), UnionType(map)))
     }
 
-    fn empty_union_type(_input: ParseInput<Rule>) -> ParseResult<()> {
+    fn empty_union_type(
+        _input: ParseInput<Rule, ParseState>,
+    ) -> ParseResult<()> {
         Ok(())
     }
 
     fn union_type_entry<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<(Label, Option<Expr<E>>)> {
         Ok(parse_children!(input;
-            [label(name), expression(expr)] => (name, Some(expr)),
-            [label(name)] => (name, None),
+            [label(name), opt(expression(expr))] => (name, expr),
         ))
     }
 
     #[alias(expression)]
     fn non_empty_list_literal<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         Ok(parse_children!(input;
             [expression(items)..] => spanned(
@@ -1035,7 +1091,7 @@ impl Parsers {
     }
 
     fn final_expression<E: Clone>(
-        input: ParseInput<Rule>,
+        input: ParseInput<Rule, ParseState>,
     ) -> ParseResult<Expr<E>> {
         Ok(parse_children!(input;
             [expression(e), EOI(_)] => e
@@ -1043,7 +1099,146 @@ impl Parsers {
     }
 }
 
+/// Union literals like `< A = 1 | B : Bool >` were removed from the language,
+/// and the grammar then fails at the label of the alternative with a value.
+/// Such errors are replaced by one that explains how to migrate.
+fn explain_legacy_syntax(input: &str, e: ParseError) -> ParseError {
+    use pest::error::{ErrorVariant, InputLocation};
+    let pos = match (&e.variant, &e.location) {
+        (ErrorVariant::ParsingError { .. }, InputLocation::Pos(pos)) => *pos,
+        _ => return e,
+    };
+    let rest = &input[pos..];
+    let label_len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "_-/".contains(c)))
+        .unwrap_or_else(|| rest.len());
+    let label = &rest[..label_len];
+    let after = rest[label_len..].trim_start();
+    let before = input[..pos].trim_end();
+    let in_union = before.ends_with('<')
+        || (before.ends_with('|') && !before.ends_with("||"));
+    if label.is_empty()
+        || !in_union
+        || !after.starts_with('=')
+        || after.starts_with("==")
+    {
+        return e;
+    }
+    let message = format!(
+        "union literals were removed, select the alternative from the union type instead, e.g. `< {0} : T | ... >.{0} value`",
+        label
+    );
+    pest::error::Error::new_from_pos(
+        ErrorVariant::CustomError { message },
+        pest::Position::new(input, pos).unwrap(),
+    )
+}
+
+/// A syntax error found by `check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// Byte offsets into the source
+    pub start: usize,
+    pub end: usize,
+    /// 1-based position of `start`
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Diagnostic {
+    fn from_parse_error(e: ParseError) -> Self {
+        use pest::error::{ErrorVariant, InputLocation, LineColLocation};
+        let (start, end) = match e.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let (line, column) = match e.line_col {
+            LineColLocation::Pos(pos) => pos,
+            LineColLocation::Span(start, _) => start,
+        };
+        let rules = |rules: &[Rule]| {
+            rules.iter().map(|r| format!("{:?}", r)).join(", ")
+        };
+        let message = match &e.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => match (positives.is_empty(), negatives.is_empty()) {
+                (false, false) => format!(
+                    "unexpected {}; expected {}",
+                    rules(negatives),
+                    rules(positives)
+                ),
+                (false, true) => format!("expected {}", rules(positives)),
+                (true, false) => format!("unexpected {}", rules(negatives)),
+                (true, true) => "unknown parsing error".to_owned(),
+            },
+            ErrorVariant::CustomError { message } => message.clone(),
+        };
+        Diagnostic {
+            message,
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Checks that the input is syntactically valid, without building an
+/// expression. This only runs the grammar, so it is much cheaper than
+/// `parse_expr`. Parsing stops at the first error, so at most one
+/// diagnostic is returned.
+pub fn check(s: &str) -> Vec<Diagnostic> {
+    match DhallParser::parse(Rule::final_expression, s) {
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            vec![Diagnostic::from_parse_error(explain_legacy_syntax(s, e))]
+        }
+    }
+}
+
 pub fn parse_expr<E: Clone>(s: &str) -> ParseResult<Expr<E>> {
-    let input = ParseInput::parse(s, Rule::final_expression)?;
+    parse_expr_with_version(s, StandardVersion::implemented())
+}
+
+/// Parses an expression, rejecting syntax that is not available in the given
+/// version of the standard.
+pub fn parse_expr_with_version<E: Clone>(
+    s: &str,
+    version: StandardVersion,
+) -> ParseResult<Expr<E>> {
+    parse_expr_from(None, s, version)
+}
+
+/// Parses an expression read from the source called `name`, typically a file
+/// path. The name is reported in errors and recorded in spans.
+pub fn parse_expr_with_name<E: Clone>(
+    name: &str,
+    s: &str,
+) -> ParseResult<Expr<E>> {
+    parse_expr_from(Some(name), s, StandardVersion::implemented())
+        .map_err(|e| e.with_path(name))
+}
+
+fn parse_expr_from<E: Clone>(
+    name: Option<&str>,
+    s: &str,
+    version: StandardVersion,
+) -> ParseResult<Expr<E>> {
+    let state = ParseState { version };
+    let input = ParseInput::parse_with_user_data(
+        s,
+        name,
+        Rule::final_expression,
+        state,
+    )?;
     Parsers::final_expression(input)
 }
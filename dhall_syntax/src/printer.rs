@@ -30,7 +30,7 @@ impl<SE: Display + Clone, E: Display> Display for ExprF<SE, E> {
                 write!(f, "[] : {}", t)?;
             }
             NEListLit(es) => {
-                fmt_list("[", ", ", "]", es, f, Display::fmt)?;
+                fmt_list("[", ", ", "]", es, None, f, Display::fmt)?;
             }
             SomeLit(e) => {
                 write!(f, "Some {}", e)?;
@@ -64,7 +64,7 @@ impl<SE: Display + Clone, E: Display> Display for ExprF<SE, E> {
             }
             Projection(e, ls) => {
                 write!(f, "{}.", e)?;
-                fmt_list("{ ", ", ", " }", ls, f, Display::fmt)?;
+                fmt_list("{ ", ", ", " }", ls, None, f, Display::fmt)?;
             }
             Var(a) => a.fmt(f)?,
             Const(k) => k.fmt(f)?,
@@ -79,21 +79,9 @@ impl<SE: Display + Clone, E: Display> Display for ExprF<SE, E> {
             IntegerLit(a) => a.fmt(f)?,
             DoubleLit(a) => a.fmt(f)?,
             TextLit(a) => a.fmt(f)?,
-            RecordType(a) if a.is_empty() => f.write_str("{}")?,
-            RecordType(a) => fmt_list("{ ", ", ", " }", a, f, |(k, t), f| {
-                write!(f, "{} : {}", k, t)
-            })?,
-            RecordLit(a) if a.is_empty() => f.write_str("{=}")?,
-            RecordLit(a) => fmt_list("{ ", ", ", " }", a, f, |(k, v), f| {
-                write!(f, "{} = {}", k, v)
-            })?,
-            UnionType(a) => fmt_list("< ", " | ", " >", a, f, |(k, v), f| {
-                write!(f, "{}", k)?;
-                if let Some(v) = v {
-                    write!(f, ": {}", v)?;
-                }
-                Ok(())
-            })?,
+            RecordType(a) => fmt_record_type(a.iter(), None, f)?,
+            RecordLit(a) => fmt_record_lit(a.iter(), None, f)?,
+            UnionType(a) => fmt_union_type(a.iter(), None, f)?,
             Import(a) => a.fmt(f)?,
             Embed(a) => a.fmt(f)?,
         }
@@ -115,20 +103,98 @@ enum PrintPhase {
     Primitive,
 }
 
+impl PrintPhase {
+    /// The phases of the left and right operands of `op`. The operand on the
+    /// non-associative side needs parentheses if it uses `op` too.
+    fn operands(op: core::BinOp) -> (PrintPhase, PrintPhase) {
+        let tighter = match core::BinOp::ALL.get(op.precedence() + 1) {
+            Some(next) => PrintPhase::BinOp(*next),
+            None => PrintPhase::App,
+        };
+        match op.associativity() {
+            Associativity::Left => (PrintPhase::BinOp(op), tighter),
+            Associativity::Right => (tighter, PrintPhase::BinOp(op)),
+        }
+    }
+}
+
+/// Options for printing expressions, see `Expr::display_with`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PrintOptions {
+    /// Print the fields of records and unions in the order they were
+    /// written in, instead of sorting them.
+    pub preserve_field_order: bool,
+    /// Print subexpressions nested more than this deep as `…`.
+    pub max_depth: Option<usize>,
+    /// Print at most this many elements of each list, record or union,
+    /// followed by `…` if some were left out.
+    pub max_width: Option<usize>,
+    /// Print only ASCII characters: escape the characters of text literals
+    /// that are not ASCII, e.g. `é` as `\u00e9`, and use the ASCII forms of
+    /// `λ`, `→`, `∀` and of the operators, e.g. `//` for `⫽`.
+    pub ascii_only: bool,
+}
+
+impl PrintOptions {
+    /// Options for a stable text form, meant for diffing, hashing or signing
+    /// rendered expressions.
+    ///
+    /// The output is stable across releases of this crate: it is all on one
+    /// line, with a single space around operators and separators, fields and
+    /// alternatives sorted by name, and only ASCII characters. A change to
+    /// this form is a breaking change. It is not a pretty-printed form, and
+    /// parses back to the same expression.
+    ///
+    /// Bound variables are printed with the names they have, so expressions
+    /// that only differ by those names print differently;
+    /// `Normalized::to_canonical_string` renames them first.
+    pub const CANONICAL: PrintOptions = PrintOptions {
+        preserve_field_order: false,
+        max_depth: None,
+        max_width: None,
+        ascii_only: true,
+    };
+
+    /// Options for the direct subexpressions of an expression.
+    fn descend(self) -> PrintOptions {
+        PrintOptions {
+            max_depth: self.max_depth.map(|d| d.saturating_sub(1)),
+            ..self
+        }
+    }
+}
+
+/// An expression printed with some options.
+pub struct DisplayWith<'a, A>(&'a Expr<A>, PrintOptions);
+
+impl<'a, A: Display + Clone> Display for DisplayWith<'a, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.0.as_ref().fmt_phase(f, PrintPhase::Base, self.1)
+    }
+}
+
+impl<A> Expr<A> {
+    /// Displays the expression using the given options. `Display` uses the
+    /// default options.
+    pub fn display_with(&self, options: PrintOptions) -> DisplayWith<'_, A> {
+        DisplayWith(self, options)
+    }
+}
+
 // Wraps an Expr with a phase, so that phase selsction can be done
 // separate from the actual printing
 #[derive(Clone)]
-struct PhasedExpr<'a, A>(&'a Expr<A>, PrintPhase);
+struct PhasedExpr<'a, A>(&'a Expr<A>, PrintPhase, PrintOptions);
 
 impl<'a, A: Display + Clone> Display for PhasedExpr<'a, A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        self.0.as_ref().fmt_phase(f, self.1)
+        self.0.as_ref().fmt_phase(f, self.1, self.2)
     }
 }
 
 impl<'a, A: Display + Clone> PhasedExpr<'a, A> {
     fn phase(self, phase: PrintPhase) -> PhasedExpr<'a, A> {
-        PhasedExpr(self.0, phase)
+        PhasedExpr(self.0, phase, self.2)
     }
 }
 
@@ -137,10 +203,15 @@ impl<A: Display + Clone> RawExpr<A> {
         &self,
         f: &mut fmt::Formatter,
         phase: PrintPhase,
+        options: PrintOptions,
     ) -> Result<(), fmt::Error> {
         use crate::ExprF::*;
         use PrintPhase::*;
 
+        if options.max_depth == Some(0) {
+            return f.write_str("…");
+        }
+
         let needs_paren = match self {
             Lam(_, _, _)
             | BoolIf(_, _, _)
@@ -152,6 +223,7 @@ impl<A: Display + Clone> RawExpr<A> {
             | Merge(_, _, _)
             | ToMap(_, _)
             | Annot(_, _)
+            | Assert(_)
                 if phase > Base =>
             {
                 true
@@ -165,46 +237,98 @@ impl<A: Display + Clone> RawExpr<A> {
             _ => false,
         };
 
+        if let Let(_, _, _, _) = self {
+            if needs_paren {
+                f.write_str("(")?;
+            }
+            fmt_let_block(self, f, options)?;
+            if needs_paren {
+                f.write_str(")")?;
+            }
+            return Ok(());
+        }
+
         // Annotate subexpressions with the appropriate phase, defaulting to Base
-        let phased_self = match self.map_ref(|e| PhasedExpr(e, Base)) {
-            Pi(a, b, c) => {
-                if &String::from(&a) == "_" {
-                    Pi(a, b.phase(Operator), c)
-                } else {
-                    Pi(a, b, c)
+        let child_options = options.descend();
+        let phased_self =
+            match self.map_ref(|e| PhasedExpr(e, Base, child_options)) {
+                Pi(a, b, c) => {
+                    if &String::from(&a) == "_" {
+                        Pi(a, b.phase(Operator), c)
+                    } else {
+                        Pi(a, b, c)
+                    }
                 }
-            }
-            Merge(a, b, c) => Merge(
-                a.phase(PrintPhase::Import),
-                b.phase(PrintPhase::Import),
-                c.map(|x| x.phase(PrintPhase::App)),
-            ),
-            ToMap(a, b) => ToMap(
-                a.phase(PrintPhase::Import),
-                b.map(|x| x.phase(PrintPhase::App)),
-            ),
-            Annot(a, b) => Annot(a.phase(Operator), b),
-            ExprF::BinOp(op, a, b) => ExprF::BinOp(
-                op,
-                a.phase(PrintPhase::BinOp(op)),
-                b.phase(PrintPhase::BinOp(op)),
-            ),
-            SomeLit(e) => SomeLit(e.phase(PrintPhase::Import)),
-            ExprF::App(f, a) => ExprF::App(
-                f.phase(PrintPhase::Import),
-                a.phase(PrintPhase::Import),
-            ),
-            Field(a, b) => Field(a.phase(Primitive), b),
-            Projection(e, ls) => Projection(e.phase(Primitive), ls),
-            e => e,
-        };
+                Merge(a, b, c) => Merge(
+                    a.phase(PrintPhase::Import),
+                    b.phase(PrintPhase::Import),
+                    c.map(|x| x.phase(PrintPhase::App)),
+                ),
+                ToMap(a, b) => ToMap(
+                    a.phase(PrintPhase::Import),
+                    b.map(|x| x.phase(PrintPhase::App)),
+                ),
+                Annot(a, b) => Annot(a.phase(Operator), b),
+                ExprF::BinOp(op, a, b) => {
+                    let (l, r) = PrintPhase::operands(op);
+                    ExprF::BinOp(op, a.phase(l), b.phase(r))
+                }
+                SomeLit(e) => SomeLit(e.phase(PrintPhase::Import)),
+                EmptyListLit(t) => EmptyListLit(t.phase(PrintPhase::App)),
+                ExprF::App(f, a) => ExprF::App(
+                    f.phase(PrintPhase::Import),
+                    a.phase(PrintPhase::Import),
+                ),
+                Field(a, b) => Field(a.phase(Primitive), b),
+                Projection(e, ls) => Projection(e.phase(Primitive), ls),
+                e => e,
+            };
 
         if needs_paren {
             f.write_str("(")?;
         }
 
-        // Uses the ExprF<PhasedExpr<_>, _> instance
-        phased_self.fmt(f)?;
+        let ordered = options.preserve_field_order;
+        let width = options.max_width;
+        match &phased_self {
+            NEListLit(es) => {
+                fmt_list("[", ", ", "]", es, width, f, Display::fmt)?
+            }
+            RecordType(a) if ordered => {
+                fmt_record_type(a.iter_insertion_order(), width, f)?
+            }
+            RecordType(a) => fmt_record_type(a.iter(), width, f)?,
+            RecordLit(a) if ordered => {
+                fmt_record_lit(a.iter_insertion_order(), width, f)?
+            }
+            RecordLit(a) => fmt_record_lit(a.iter(), width, f)?,
+            UnionType(a) if ordered => {
+                fmt_union_type(a.iter_insertion_order(), width, f)?
+            }
+            UnionType(a) => fmt_union_type(a.iter(), width, f)?,
+            TextLit(a) => fmt_text(
+                a,
+                Escaping::Minimal {
+                    ascii_only: options.ascii_only,
+                },
+                f,
+            )?,
+            Lam(a, b, c) if options.ascii_only => {
+                write!(f, "\\({} : {}) -> {}", a, b, c)?
+            }
+            Pi(a, b, c) if options.ascii_only => {
+                if &String::from(a) == "_" {
+                    write!(f, "{} -> {}", b, c)?
+                } else {
+                    write!(f, "forall({} : {}) -> {}", a, b, c)?
+                }
+            }
+            ExprF::BinOp(op, a, b) if options.ascii_only => {
+                write!(f, "{} {} {}", a, op.ascii_symbol(), b)?
+            }
+            // Uses the ExprF<PhasedExpr<_>, _> instance
+            _ => phased_self.fmt(f)?,
+        }
 
         if needs_paren {
             f.write_str(")")?;
@@ -213,17 +337,98 @@ impl<A: Display + Clone> RawExpr<A> {
     }
 }
 
+/// Prints consecutive `let`s as a single block, with only one `in`:
+/// `let x = 1 let y = x in y`.
+fn fmt_let_block<A: Display + Clone>(
+    mut e: &RawExpr<A>,
+    f: &mut fmt::Formatter,
+    options: PrintOptions,
+) -> Result<(), fmt::Error> {
+    let options = options.descend();
+    let mut first = true;
+    while let ExprF::Let(x, t, v, body) = e {
+        if !first {
+            f.write_str(" ")?;
+        }
+        first = false;
+        write!(f, "let {}", x)?;
+        if let Some(t) = t {
+            write!(f, " : {}", PhasedExpr(t, PrintPhase::Base, options))?;
+        }
+        write!(f, " = {}", PhasedExpr(v, PrintPhase::Base, options))?;
+        e = body.as_ref();
+    }
+    f.write_str(" in ")?;
+    e.fmt_phase(f, PrintPhase::Base, options)
+}
+
 impl<A: Display + Clone> Display for Expr<A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        self.as_ref().fmt_phase(f, PrintPhase::Base)
+        self.as_ref()
+            .fmt_phase(f, PrintPhase::Base, PrintOptions::default())
     }
 }
 
+fn fmt_record_type<'a, K: Display + 'a, T: Display + 'a>(
+    mut entries: impl Iterator<Item = (&'a K, &'a T)>,
+    max_width: Option<usize>,
+    f: &mut fmt::Formatter,
+) -> Result<(), fmt::Error> {
+    match entries.next() {
+        None => f.write_str("{}"),
+        Some(first) => fmt_list(
+            "{ ",
+            ", ",
+            " }",
+            std::iter::once(first).chain(entries),
+            max_width,
+            f,
+            |(k, t), f| write!(f, "{} : {}", k, t),
+        ),
+    }
+}
+
+fn fmt_record_lit<'a, K: Display + 'a, V: Display + 'a>(
+    mut entries: impl Iterator<Item = (&'a K, &'a V)>,
+    max_width: Option<usize>,
+    f: &mut fmt::Formatter,
+) -> Result<(), fmt::Error> {
+    match entries.next() {
+        None => f.write_str("{=}"),
+        Some(first) => fmt_list(
+            "{ ",
+            ", ",
+            " }",
+            std::iter::once(first).chain(entries),
+            max_width,
+            f,
+            |(k, v), f| write!(f, "{} = {}", k, v),
+        ),
+    }
+}
+
+fn fmt_union_type<'a, K: Display + 'a, T: Display + 'a>(
+    entries: impl Iterator<Item = (&'a K, &'a Option<T>)>,
+    max_width: Option<usize>,
+    f: &mut fmt::Formatter,
+) -> Result<(), fmt::Error> {
+    fmt_list("< ", " | ", " >", entries, max_width, f, |(k, v), f| {
+        write!(f, "{}", k)?;
+        if let Some(v) = v {
+            write!(f, ": {}", v)?;
+        }
+        Ok(())
+    })
+}
+
+/// Prints the elements of `it` separated by `sep`. If `max_width` is given,
+/// only that many elements are printed, followed by `…` if there were more.
 fn fmt_list<T, I, F>(
     open: &str,
     sep: &str,
     close: &str,
     it: I,
+    max_width: Option<usize>,
     f: &mut fmt::Formatter,
     func: F,
 ) -> Result<(), fmt::Error>
@@ -236,52 +441,88 @@ where
         if i > 0 {
             f.write_str(sep)?;
         }
+        if Some(i) == max_width {
+            f.write_str("…")?;
+            break;
+        }
         func(x, f)?;
     }
     f.write_str(close)
 }
 
+/// Prints the text as `Text/show` does, see `Escaping::TextShow`.
 impl<SubExpr: Display> Display for InterpolatedText<SubExpr> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        f.write_str("\"")?;
-        for x in self.iter() {
-            match x {
-                InterpolatedTextContents::Text(a) => {
-                    for c in a.chars() {
-                        match c {
-                            '\\' => f.write_str("\\\\"),
-                            '"' => f.write_str("\\\""),
-                            '$' => f.write_str("\\u0024"),
-                            '\u{0008}' => f.write_str("\\b"),
-                            '\u{000C}' => f.write_str("\\f"),
-                            '\n' => f.write_str("\\n"),
-                            '\r' => f.write_str("\\r"),
-                            '\t' => f.write_str("\\t"),
-                            '\u{0000}'..='\u{001F}' => {
-                                // Escape to an explicit "\u{XXXX}" form
-                                let escaped: String =
-                                    c.escape_default().collect();
-                                // Print as "\uXXXX"
-                                write!(
-                                    f,
-                                    "\\u{:0>4}",
-                                    &escaped[3..escaped.len() - 1]
-                                )
+        fmt_text(self, Escaping::TextShow, f)
+    }
+}
+
+/// Which characters `fmt_text` escapes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Escaping {
+    /// As the standard specifies for `Text/show`, which escapes every `$`
+    TextShow,
+    /// Only what would not parse back, like the dhall-haskell formatter,
+    /// and also the characters that are not ASCII if `ascii_only` is set
+    Minimal { ascii_only: bool },
+}
+
+fn fmt_text<SubExpr: Display>(
+    text: &InterpolatedText<SubExpr>,
+    escaping: Escaping,
+    f: &mut fmt::Formatter,
+) -> Result<(), fmt::Error> {
+    let ascii_only = escaping == Escaping::Minimal { ascii_only: true };
+    f.write_str("\"")?;
+    for x in text.iter() {
+        match x {
+            InterpolatedTextContents::Text(a) => {
+                let mut chars = a.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => f.write_str("\\\\"),
+                        '"' => f.write_str("\\\""),
+                        '$' if escaping == Escaping::TextShow => {
+                            f.write_str("\\u0024")
+                        }
+                        // Text chunks are separated by interpolations, so a
+                        // `$` can only start one if it is followed by `{`
+                        '$' if chars.peek() == Some(&'{') => f.write_str("\\$"),
+                        '\u{0008}' => f.write_str("\\b"),
+                        '\u{000C}' => f.write_str("\\f"),
+                        '\n' => f.write_str("\\n"),
+                        '\r' => f.write_str("\\r"),
+                        '\t' => f.write_str("\\t"),
+                        '\u{0000}'..='\u{001F}' => {
+                            // Escape to an explicit "\u{XXXX}" form
+                            let escaped: String = c.escape_default().collect();
+                            // Print as "\uXXXX"
+                            write!(
+                                f,
+                                "\\u{:0>4}",
+                                &escaped[3..escaped.len() - 1]
+                            )
+                        }
+                        c if ascii_only && !c.is_ascii() => {
+                            if c <= '\u{FFFF}' {
+                                write!(f, "\\u{:04x}", c as u32)
+                            } else {
+                                write!(f, "\\u{{{:x}}}", c as u32)
                             }
-                            c => write!(f, "{}", c),
-                        }?;
-                    }
-                }
-                InterpolatedTextContents::Expr(e) => {
-                    f.write_str("${ ")?;
-                    e.fmt(f)?;
-                    f.write_str(" }")?;
+                        }
+                        c => write!(f, "{}", c),
+                    }?;
                 }
             }
+            InterpolatedTextContents::Expr(e) => {
+                f.write_str("${ ")?;
+                e.fmt(f)?;
+                f.write_str(" }")?;
+            }
         }
-        f.write_str("\"")?;
-        Ok(())
     }
+    f.write_str("\"")?;
+    Ok(())
 }
 
 impl Display for Const {
@@ -311,6 +552,28 @@ impl Display for BinOp {
     }
 }
 
+impl BinOp {
+    /// The operator as written with ASCII characters only.
+    fn ascii_symbol(self) -> &'static str {
+        use crate::BinOp::*;
+        match self {
+            BoolOr => "||",
+            TextAppend => "++",
+            NaturalPlus => "+",
+            BoolAnd => "&&",
+            RecursiveRecordMerge => "/\\",
+            NaturalTimes => "*",
+            BoolEQ => "==",
+            BoolNE => "!=",
+            RecursiveRecordTypeMerge => "//\\\\",
+            ImportAlt => "?",
+            RightBiasedRecordMerge => "//",
+            ListAppend => "#",
+            Equivalence => "===",
+        }
+    }
+}
+
 impl Display for NaiveDouble {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let v = f64::from(*self);
@@ -340,7 +603,7 @@ impl Display for Label {
             | "Sort" | "True" | "False" => true,
             _ => crate::Builtin::parse(&s).is_some(),
         };
-        if !is_reserved && s.chars().all(|c| c.is_ascii_alphanumeric()) {
+        if !is_reserved && self.is_simple() {
             write!(f, "{}", s)
         } else {
             write!(f, "`{}`", s)
@@ -350,25 +613,64 @@ impl Display for Label {
 
 impl Display for Hash {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}:{}", self.algorithm(), self.hex())
+    }
+}
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.name())
+    }
+}
+impl Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use HashError::*;
         match self {
-            Hash::SHA256(hash) => write!(f, "sha256:{}", hex::encode(hash)),
+            MissingAlgorithm => write!(f, "Missing hashing protocol"),
+            UnknownAlgorithm(name) => {
+                write!(f, "Unknown hashing protocol '{}'", name)
+            }
+            InvalidHex(hex) => write!(f, "Invalid hex digest '{}'", hex),
+            WrongLength {
+                algorithm,
+                expected,
+                found,
+            } => write!(
+                f,
+                "A {} digest must be {} bytes long, found {}",
+                algorithm, expected, found
+            ),
         }
     }
 }
-impl<SubExpr: Display> Display for Import<SubExpr> {
+/// Whether a local path component can be printed without quotes.
+fn is_unquoted_path_component(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars().all(|c| match c {
+            '!' | '$'..='\'' | '*' | '+' | '-' | '.' | '0'..=';' | '=' => true,
+            '@'..='Z' | '^'..='z' | '|' | '~' => true,
+            _ => false,
+        })
+}
+
+impl<SubExpr: Display> Display for URL<SubExpr> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}://{}/", self.scheme, self.authority)?;
+        f.write_str(&self.path.iter().join("/"))?;
+        if let Some(q) = &self.query {
+            write!(f, "?{}", q)?
+        }
+        if let Some(h) = &self.headers {
+            write!(f, " using ({})", h)?
+        }
+        Ok(())
+    }
+}
+
+impl<SubExpr: Display> Display for ImportLocation<SubExpr> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         use FilePrefix::*;
         use ImportLocation::*;
-        use ImportMode::*;
-        let quote_if_needed = |s: &str| -> String {
-            if s.chars().all(|c| c.is_ascii_alphanumeric()) {
-                s.to_string()
-            } else {
-                format!("\"{}\"", s)
-            }
-        };
-
-        match &self.location {
+        match self {
             Local(prefix, path) => {
                 let prefix = match prefix {
                     Here => ".",
@@ -376,22 +678,16 @@ impl<SubExpr: Display> Display for Import<SubExpr> {
                     Home => "~",
                     Absolute => "",
                 };
-                write!(f, "{}/", prefix)?;
-                let path: String =
-                    path.iter().map(|c| quote_if_needed(&*c)).join("/");
-                f.write_str(&path)?;
-            }
-            Remote(url) => {
-                write!(f, "{}://{}/", url.scheme, url.authority,)?;
-                let path: String = url.path.iter().join("/");
-                f.write_str(&path)?;
-                if let Some(q) = &url.query {
-                    write!(f, "?{}", q)?
-                }
-                if let Some(h) = &url.headers {
-                    write!(f, " using ({})", h)?
+                f.write_str(prefix)?;
+                for c in path {
+                    if is_unquoted_path_component(c) {
+                        write!(f, "/{}", c)?;
+                    } else {
+                        write!(f, "/\"{}\"", c)?;
+                    }
                 }
             }
+            Remote(url) => url.fmt(f)?,
             Env(s) => {
                 write!(f, "env:")?;
                 if s.chars().all(|c| c.is_ascii_alphanumeric()) {
@@ -419,6 +715,14 @@ impl<SubExpr: Display> Display for Import<SubExpr> {
                 write!(f, "missing")?;
             }
         }
+        Ok(())
+    }
+}
+
+impl<SubExpr: Display> Display for Import<SubExpr> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use ImportMode::*;
+        self.location.fmt(f)?;
         if let Some(hash) = &self.hash {
             write!(f, " ")?;
             hash.fmt(f)?;
@@ -489,3 +793,30 @@ impl<Label: Display> Display for V<Label> {
         Ok(())
     }
 }
+
+impl Display for StandardVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use StandardVersion::*;
+        f.write_str(match self {
+            V7_0_0 => "7.0.0",
+            V8_0_0 => "8.0.0",
+            V9_0_0 => "9.0.0",
+            V10_0_0 => "10.0.0",
+            V17_0_0 => "17.0.0",
+        })
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use Feature::*;
+        f.write_str(match self {
+            ToMap => "toMap",
+            Assert => "assert",
+            Equivalence => "===",
+            NaturalSubtract => "Natural/subtract",
+            OptionalFold => "Optional/fold",
+            OptionalBuild => "Optional/build",
+        })
+    }
+}
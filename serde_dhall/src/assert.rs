@@ -0,0 +1,73 @@
+use std::fmt::Debug;
+
+use crate::de::Deserialize;
+
+/// Asserts that a Dhall expression deserializes to the given Rust value.
+///
+/// On mismatch, this panics with a line diff between the pretty-printed
+/// structures, where lines starting with `-` come from the Rust value and
+/// lines starting with `+` from the Dhall expression.
+///
+/// ```edition2018
+/// #[derive(Debug, PartialEq, serde::Deserialize)]
+/// struct Config {
+///     port: u64,
+///     hosts: Vec<String>,
+/// }
+///
+/// let config = Config { port: 8080, hosts: vec!["localhost".to_owned()] };
+/// serde_dhall::assert_matches(&config, "{ port = 8000 + 80, hosts = [\"localhost\"] }");
+/// ```
+pub fn assert_matches<T>(value: &T, dhall_src: &str)
+where
+    T: Deserialize + PartialEq + Debug,
+{
+    let other: T = match crate::from_str(dhall_src) {
+        Ok(x) => x,
+        Err(e) => panic!("Could not deserialize the Dhall expression: {}", e),
+    };
+    if value != &other {
+        let left = format!("{:#?}", value);
+        let right = format!("{:#?}", other);
+        panic!(
+            "The Rust value does not match the Dhall expression (- rust, + dhall):\n{}",
+            diff_lines(&left, &right)
+        );
+    }
+}
+
+/// A line diff based on the longest common subsequence of lines.
+fn diff_lines(left: &str, right: &str) -> String {
+    let left: Vec<_> = left.lines().collect();
+    let right: Vec<_> = right.lines().collect();
+    // lcs[i][j] is the length of the longest common subsequence of
+    // left[i..] and right[j..].
+    let mut lcs = vec![vec![0; right.len() + 1]; left.len() + 1];
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            lcs[i][j] = if left[i] == right[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() || j < right.len() {
+        if i < left.len() && j < right.len() && left[i] == right[j] {
+            out += &format!("  {}\n", left[i]);
+            i += 1;
+            j += 1;
+        } else if j == right.len()
+            || (i < left.len() && lcs[i + 1][j] >= lcs[i][j + 1])
+        {
+            out += &format!("- {}\n", left[i]);
+            i += 1;
+        } else {
+            out += &format!("+ {}\n", right[j]);
+            j += 1;
+        }
+    }
+    out
+}
@@ -0,0 +1,68 @@
+use dhall::phase::NormalizedExpr;
+use dhall_syntax::ExprF;
+
+use crate::de::{Deserialize, Error, Result};
+use crate::Value;
+
+/// A Dhall function that can be called from Rust.
+///
+/// Arguments are converted to Dhall with [serde::Serialize], guided by the
+/// type of the function's input, and results are deserialized like any other
+/// value. Functions of several arguments return a [SimpleFunction] that
+/// takes the next one.
+///
+/// ```edition2018
+/// # fn main() -> serde_dhall::de::Result<()> {
+/// use serde::Serialize;
+/// use serde_dhall::SimpleFunction;
+///
+/// #[derive(Serialize)]
+/// struct Request {
+///     size: u64,
+///     region: Option<String>,
+/// }
+///
+/// let src = r#"
+///     \(r : { size : Natural, region : Optional Text }) ->
+///         Optional/fold Text r.region Natural (\(_ : Text) -> r.size * 2) r.size
+/// "#;
+/// let quota: SimpleFunction = serde_dhall::from_str(src)?;
+/// let q: u64 = quota.call(&Request { size: 10, region: None })?;
+/// assert_eq!(q, 10);
+/// let q: u64 = quota.call(&Request { size: 10, region: Some("eu".to_owned()) })?;
+/// assert_eq!(q, 20);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SimpleFunction {
+    f: Value,
+    input_type: NormalizedExpr,
+}
+
+impl SimpleFunction {
+    pub fn call<A, R>(&self, arg: &A) -> Result<R>
+    where
+        A: serde::Serialize,
+        R: Deserialize,
+    {
+        let arg = crate::ser::to_expr(arg, &self.input_type)?;
+        R::from_dhall(&self.f.apply(arg)?)
+    }
+}
+
+impl Deserialize for SimpleFunction {
+    fn from_dhall(v: &Value) -> Result<Self> {
+        let ty = v.type_expr()?;
+        match ty.as_ref() {
+            ExprF::Pi(_, input_type, _) => Ok(SimpleFunction {
+                f: v.clone(),
+                input_type: input_type.clone(),
+            }),
+            _ => Err(Error::Deserialize(format!(
+                "Expected a function, found a value of type `{}`",
+                ty
+            ))),
+        }
+    }
+}
@@ -107,23 +107,66 @@
 //! # }
 //! ```
 //!
+//! ### Bounded numbers
+//!
+//! A `Natural` or an `Integer` out of the range of the Rust type is an error,
+//! which points at the value in the Dhall source. Narrower ranges can be
+//! checked with `#[serde(try_from = "u64")]`, and errors from the conversion
+//! point at the value too.
+//!
+//! ```edition2018
+//! use std::convert::TryFrom;
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, Deserialize)]
+//! #[serde(try_from = "u64")]
+//! struct Port(u16);
+//!
+//! impl TryFrom<u64> for Port {
+//!     type Error = String;
+//!     fn try_from(n: u64) -> Result<Port, String> {
+//!         match u16::try_from(n) {
+//!             Ok(n) if n > 0 => Ok(Port(n)),
+//!             _ => Err(format!("{} is not a valid port", n)),
+//!         }
+//!     }
+//! }
+//!
+//! #[derive(Debug, Deserialize)]
+//! struct Server {
+//!     port: Port,
+//! }
+//!
+//! let err = serde_dhall::from_str::<Server>("{ port = 0 }").unwrap_err();
+//! assert_eq!(err.to_string(), "0 is not a valid port (at 1:10)");
+//! ```
+//!
 //! [dhall]: https://dhall-lang.org/
 //! [serde]: https://docs.serde.rs/serde/
 //! [serde::Deserialize]: https://docs.serde.rs/serde/trait.Deserialize.html
 
+mod assert;
+mod function;
+mod ser;
 mod serde;
 mod static_type;
 
+pub use assert::assert_matches;
 #[doc(inline)]
-pub use de::{from_str, from_str_auto_type, from_str_check_type};
+pub use de::{
+    from_binary, from_binary_unchecked, from_str, from_str_auto_type,
+    from_str_check_type, from_str_field,
+};
 #[doc(hidden)]
 pub use dhall_proc_macros::StaticType;
+pub use function::SimpleFunction;
 pub use static_type::StaticType;
 #[doc(inline)]
 pub use value::Value;
 
 // A Dhall value.
 pub mod value {
+    use dhall::error::Error as DhallError;
     use dhall::phase::{NormalizedExpr, Parsed, Typed};
     use dhall_syntax::Builtin;
 
@@ -137,6 +180,12 @@ pub mod value {
         pub fn from_str(s: &str, ty: Option<&Value>) -> Result<Self> {
             Value::from_str_using_dhall_error_type(s, ty).map_err(Error::Dhall)
         }
+        pub(crate) fn from_binary(data: &[u8]) -> Result<Self> {
+            let typed = || -> dhall::error::Result<_> {
+                Ok(Parsed::parse_binary(data)?.skip_resolve()?.typecheck()?)
+            };
+            Ok(Value(typed().map_err(Error::Dhall)?))
+        }
         fn from_str_using_dhall_error_type(
             s: &str,
             ty: Option<&Value>,
@@ -144,16 +193,33 @@ pub mod value {
             let resolved = Parsed::parse_str(s)?.resolve()?;
             let typed = match ty {
                 None => resolved.typecheck()?,
-                Some(t) => resolved.typecheck_with(t.as_typed())?,
+                Some(t) => resolved
+                    .typecheck_with(&t.as_typed().clone().normalize())?,
             };
             Ok(Value(typed))
         }
+        /// Selects a field inside nested records with a dot-separated path
+        /// such as `services.web.port`.
+        pub(crate) fn select(&self, path: &str) -> Result<Self> {
+            Ok(Value(self.0.select(path).map_err(Error::Select)?))
+        }
         pub(crate) fn to_expr(&self) -> NormalizedExpr {
             self.0.normalize_to_expr()
         }
         pub(crate) fn as_typed(&self) -> &Typed {
             &self.0
         }
+        pub(crate) fn type_expr(&self) -> Result<NormalizedExpr> {
+            self.0
+                .type_expr()
+                .map_err(|e| Error::Dhall(DhallError::Typecheck(e)))
+        }
+        pub(crate) fn apply(&self, arg: NormalizedExpr) -> Result<Self> {
+            self.0
+                .apply(arg)
+                .map(Value)
+                .map_err(|e| Error::Dhall(DhallError::Typecheck(e)))
+        }
 
         pub(crate) fn make_builtin_type(b: Builtin) -> Self {
             Value(Typed::make_builtin_type(b))
@@ -195,7 +261,8 @@ pub mod de {
     pub use error::{Error, Result};
 
     mod error {
-        use dhall::error::Error as DhallError;
+        use dhall::error::{Error as DhallError, SelectError};
+        use dhall_syntax::Span;
 
         pub type Result<T> = std::result::Result<T, Error>;
 
@@ -204,6 +271,14 @@ pub mod de {
         pub enum Error {
             Dhall(DhallError),
             Deserialize(String),
+            /// Like `Deserialize`, for a value with a known location, e.g. a
+            /// `Natural` out of the range of the Rust type
+            DeserializeAt(String, Span),
+            /// A Rust value couldn't be converted to a Dhall value of the
+            /// expected type
+            Serialize(String),
+            /// The field given to `from_str_field` doesn't exist
+            Select(SelectError),
         }
 
         impl std::fmt::Display for Error {
@@ -211,6 +286,25 @@ pub mod de {
                 match self {
                     Error::Dhall(err) => write!(f, "{}", err),
                     Error::Deserialize(err) => write!(f, "{}", err),
+                    Error::DeserializeAt(err, span) => {
+                        let (line, col) = span.start_line_col();
+                        write!(f, "{} (at {}:{})", err, line, col)
+                    }
+                    Error::Serialize(err) => write!(f, "{}", err),
+                    Error::Select(err) => write!(f, "{}", err),
+                }
+            }
+        }
+
+        impl Error {
+            /// Gives the span of the value being deserialized to an error
+            /// that has none yet.
+            pub(crate) fn at(self, span: Option<&Span>) -> Self {
+                match (self, span) {
+                    (Error::Deserialize(err), Some(span)) => {
+                        Error::DeserializeAt(err, span.clone())
+                    }
+                    (err, _) => err,
                 }
             }
         }
@@ -225,6 +319,15 @@ pub mod de {
                 Error::Deserialize(msg.to_string())
             }
         }
+
+        impl serde::ser::Error for Error {
+            fn custom<T>(msg: T) -> Self
+            where
+                T: std::fmt::Display,
+            {
+                Error::Serialize(msg.to_string())
+            }
+        }
     }
 
     /// A data structure that can be deserialized from a Dhall expression
@@ -252,6 +355,33 @@ pub mod de {
         T::from_dhall(&Value::from_str(s, None)?)
     }
 
+    /// Deserialize an instance of type `T` from the standard binary encoding
+    /// of a Dhall expression, as found in `.dhallb` files. The expression must
+    /// not contain imports.
+    pub fn from_binary<T>(data: &[u8]) -> Result<T>
+    where
+        T: Deserialize,
+    {
+        T::from_dhall(&Value::from_binary(data)?)
+    }
+
+    /// Like [from_binary], but without typechecking or normalizing: the
+    /// decoded expression is deserialized directly, without first being
+    /// converted to a [Value]. It must already be in normal form, e.g. as
+    /// written by `dhall::phase::Normalized::encode`. Since the expression
+    /// doesn't outlive the call, `T` can't borrow from it and its strings are
+    /// copied.
+    pub fn from_binary_unchecked<T>(data: &[u8]) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let expr = dhall::phase::Parsed::parse_binary(data)
+            .and_then(|p| Ok(p.skip_resolve()?))
+            .map_err(Error::Dhall)?
+            .into_expr();
+        crate::serde::deserialize_expr(&expr)
+    }
+
     /// Deserialize an instance of type `T` from a string of Dhall text,
     /// additionally checking that it matches the supplied type.
     ///
@@ -264,6 +394,27 @@ pub mod de {
         T::from_dhall(&Value::from_str(s, Some(ty))?)
     }
 
+    /// Deserialize an instance of type `T` from one field of a string of Dhall
+    /// text, selected by a dot-separated path such as `services.web.port`.
+    ///
+    /// The whole expression is resolved and typechecked, but only the
+    /// selected field is normalized and deserialized.
+    ///
+    /// ```edition2018
+    /// # fn main() -> serde_dhall::de::Result<()> {
+    /// let data = "{ services = { web = { port = 8000 + 80 } }, other = 1 }";
+    /// let port: u64 = serde_dhall::from_str_field(data, "services.web.port")?;
+    /// assert_eq!(port, 8080);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_str_field<T>(s: &str, path: &str) -> Result<T>
+    where
+        T: Deserialize,
+    {
+        T::from_dhall(&Value::from_str(s, None)?.select(path)?)
+    }
+
     /// Deserialize an instance of type `T` from a string of Dhall text,
     /// additionally checking that it matches the type of `T`.
     ///
@@ -0,0 +1,568 @@
+use dhall::phase::NormalizedExpr;
+use dhall_syntax::map::DupTreeMap;
+use dhall_syntax::{rc, Builtin as B, ExprF, Label};
+use serde::ser;
+
+use crate::de::{Error, Result};
+
+/// A Rust value in serde's data model, before it is given a Dhall type.
+#[derive(Debug)]
+enum SerValue {
+    Bool(bool),
+    Natural(u64),
+    Integer(i64),
+    Double(f64),
+    Text(String),
+    None,
+    Some(Box<SerValue>),
+    Seq(Vec<SerValue>),
+    Map(Vec<(SerValue, SerValue)>),
+    Variant(&'static str, Option<Box<SerValue>>),
+}
+
+/// Converts a Rust value to a Dhall expression of the given type. The type
+/// is needed to annotate empty lists and `None`, and to tell records from
+/// `List { mapKey : Text, mapValue : T }`.
+pub(crate) fn to_expr<T>(
+    value: &T,
+    ty: &NormalizedExpr,
+) -> Result<NormalizedExpr>
+where
+    T: ser::Serialize,
+{
+    convert(value.serialize(Serializer)?, ty)
+}
+
+fn convert(v: SerValue, ty: &NormalizedExpr) -> Result<NormalizedExpr> {
+    use ExprF::*;
+    let mismatch = |v: &SerValue| {
+        Error::Serialize(format!("Cannot convert {:?} to type `{}`", v, ty))
+    };
+    let e = match ty.as_ref() {
+        Builtin(b) => match (*b, v) {
+            (B::Bool, SerValue::Bool(b)) => BoolLit(b),
+            (B::Natural, SerValue::Natural(n)) => NaturalLit(n as usize),
+            (B::Natural, SerValue::Integer(n)) if n >= 0 => {
+                NaturalLit(n as usize)
+            }
+            (B::Integer, SerValue::Natural(n)) => IntegerLit(n as isize),
+            (B::Integer, SerValue::Integer(n)) => IntegerLit(n as isize),
+            (B::Double, SerValue::Double(n)) => DoubleLit(n.into()),
+            (B::Text, SerValue::Text(s)) => TextLit(s.into()),
+            (_, v) => return Err(mismatch(&v)),
+        },
+        App(f, t) => match (f.as_ref(), v) {
+            (Builtin(B::Optional), SerValue::None) => {
+                App(rc(Builtin(B::OptionalNone)), t.clone())
+            }
+            (Builtin(B::Optional), SerValue::Some(x)) => {
+                SomeLit(convert(*x, t)?)
+            }
+            // A plain value where an `Optional` is expected.
+            (Builtin(B::Optional), v) => SomeLit(convert(v, t)?),
+            (Builtin(B::List), SerValue::Seq(xs)) => {
+                if xs.is_empty() {
+                    EmptyListLit(ty.clone())
+                } else {
+                    NEListLit(
+                        xs.into_iter()
+                            .map(|x| convert(x, t))
+                            .collect::<Result<_>>()?,
+                    )
+                }
+            }
+            (Builtin(B::List), SerValue::Map(kvs)) => {
+                if kvs.is_empty() {
+                    EmptyListLit(ty.clone())
+                } else {
+                    NEListLit(
+                        kvs.into_iter()
+                            .map(|(k, v)| {
+                                let entry = vec![
+                                    (SerValue::Text("mapKey".to_owned()), k),
+                                    (SerValue::Text("mapValue".to_owned()), v),
+                                ];
+                                convert(SerValue::Map(entry), t)
+                            })
+                            .collect::<Result<_>>()?,
+                    )
+                }
+            }
+            (_, v) => return Err(mismatch(&v)),
+        },
+        RecordType(kts) => {
+            let kvs = match v {
+                SerValue::Map(kvs) => kvs,
+                v => return Err(mismatch(&v)),
+            };
+            let mut fields = Vec::new();
+            for (k, v) in kvs {
+                match k {
+                    SerValue::Text(k) => fields.push((Label::from(k), v)),
+                    k => return Err(mismatch(&k)),
+                }
+            }
+            let mut out = DupTreeMap::new();
+            for (k, t) in kts.iter() {
+                let v = match fields.iter().position(|(l, _)| l == k) {
+                    Some(i) => fields.remove(i).1,
+                    // Only fields of an `Optional` type may be missing, as
+                    // with `skip_serializing_if`.
+                    None => SerValue::None,
+                };
+                out.insert(k.clone(), convert(v, t)?);
+            }
+            if let Some((k, _)) = fields.first() {
+                return Err(Error::Serialize(format!(
+                    "Field `{}` is not in type `{}`",
+                    String::from(k),
+                    ty
+                )));
+            }
+            RecordLit(out)
+        }
+        UnionType(kts) => {
+            let (name, payload) = match v {
+                SerValue::Variant(name, payload) => (name, payload),
+                v => return Err(mismatch(&v)),
+            };
+            let label = Label::from(name);
+            let alt = kts.iter().find(|(k, _)| *k == &label).map(|(_, t)| t);
+            match (alt.cloned(), payload) {
+                (Some(None), None) => Field(ty.clone(), label),
+                (Some(Some(t)), Some(x)) => {
+                    App(rc(Field(ty.clone(), label)), convert(*x, &t)?)
+                }
+                (_, payload) => {
+                    return Err(mismatch(&SerValue::Variant(name, payload)))
+                }
+            }
+        }
+        _ => return Err(mismatch(&v)),
+    };
+    Ok(rc(e))
+}
+
+fn tuple_fields(xs: Vec<SerValue>) -> SerValue {
+    SerValue::Map(
+        xs.into_iter()
+            .enumerate()
+            .map(|(i, x)| (SerValue::Text(format!("_{}", i + 1)), x))
+            .collect(),
+    )
+}
+
+struct Serializer;
+
+struct SeqSerializer(Vec<SerValue>, Option<&'static str>);
+
+struct MapSerializer {
+    entries: Vec<(SerValue, SerValue)>,
+    key: Option<SerValue>,
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn new(variant: Option<&'static str>) -> Self {
+        MapSerializer {
+            entries: Vec::new(),
+            key: None,
+            variant,
+        }
+    }
+    fn end(self) -> SerValue {
+        let map = SerValue::Map(self.entries);
+        match self.variant {
+            Some(name) => SerValue::Variant(name, Some(Box::new(map))),
+            None => map,
+        }
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = SerValue;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<SerValue> {
+        Ok(SerValue::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<SerValue> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i16(self, v: i16) -> Result<SerValue> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i32(self, v: i32) -> Result<SerValue> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i64(self, v: i64) -> Result<SerValue> {
+        Ok(SerValue::Integer(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<SerValue> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u16(self, v: u16) -> Result<SerValue> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u32(self, v: u32) -> Result<SerValue> {
+        self.serialize_u64(v.into())
+    }
+    fn serialize_u64(self, v: u64) -> Result<SerValue> {
+        Ok(SerValue::Natural(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<SerValue> {
+        self.serialize_f64(v.into())
+    }
+    fn serialize_f64(self, v: f64) -> Result<SerValue> {
+        Ok(SerValue::Double(v))
+    }
+    fn serialize_char(self, v: char) -> Result<SerValue> {
+        Ok(SerValue::Text(v.to_string()))
+    }
+    fn serialize_str(self, v: &str) -> Result<SerValue> {
+        Ok(SerValue::Text(v.to_owned()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<SerValue> {
+        Ok(SerValue::Seq(
+            v.iter().map(|b| SerValue::Natural((*b).into())).collect(),
+        ))
+    }
+    fn serialize_none(self) -> Result<SerValue> {
+        Ok(SerValue::None)
+    }
+    fn serialize_some<T>(self, v: &T) -> Result<SerValue>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(SerValue::Some(Box::new(v.serialize(self)?)))
+    }
+    fn serialize_unit(self) -> Result<SerValue> {
+        Ok(SerValue::Map(Vec::new()))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<SerValue> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<SerValue> {
+        Ok(SerValue::Variant(variant, None))
+    }
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        v: &T,
+    ) -> Result<SerValue>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        v.serialize(self)
+    }
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        v: &T,
+    ) -> Result<SerValue>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        Ok(SerValue::Variant(
+            variant,
+            Some(Box::new(v.serialize(self)?)),
+        ))
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SeqSerializer> {
+        Ok(SeqSerializer(Vec::new(), None))
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<SeqSerializer> {
+        Ok(SeqSerializer(Vec::new(), None))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<SeqSerializer> {
+        Ok(SeqSerializer(Vec::new(), None))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SeqSerializer> {
+        Ok(SeqSerializer(Vec::new(), Some(variant)))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer> {
+        Ok(MapSerializer::new(None))
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer> {
+        Ok(MapSerializer::new(None))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer> {
+        Ok(MapSerializer::new(Some(variant)))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = SerValue;
+    type Error = Error;
+    fn serialize_element<T>(&mut self, v: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        self.0.push(v.serialize(Serializer)?);
+        Ok(())
+    }
+    fn end(self) -> Result<SerValue> {
+        Ok(SerValue::Seq(self.0))
+    }
+}
+
+/// Tuples are records with fields `_1`, `_2`, ...
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = SerValue;
+    type Error = Error;
+    fn serialize_element<T>(&mut self, v: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, v)
+    }
+    fn end(self) -> Result<SerValue> {
+        Ok(tuple_fields(self.0))
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = SerValue;
+    type Error = Error;
+    fn serialize_field<T>(&mut self, v: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, v)
+    }
+    fn end(self) -> Result<SerValue> {
+        Ok(tuple_fields(self.0))
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = SerValue;
+    type Error = Error;
+    fn serialize_field<T>(&mut self, v: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, v)
+    }
+    fn end(self) -> Result<SerValue> {
+        let name = self.1.unwrap_or_default();
+        Ok(SerValue::Variant(
+            name,
+            Some(Box::new(tuple_fields(self.0))),
+        ))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = SerValue;
+    type Error = Error;
+    fn serialize_key<T>(&mut self, k: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        self.key = Some(k.serialize(Serializer)?);
+        Ok(())
+    }
+    fn serialize_value<T>(&mut self, v: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        let k = self.key.take().ok_or_else(|| {
+            Error::Serialize("Map value without a key".to_owned())
+        })?;
+        self.entries.push((k, v.serialize(Serializer)?));
+        Ok(())
+    }
+    fn end(self) -> Result<SerValue> {
+        Ok(MapSerializer::end(self))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = SerValue;
+    type Error = Error;
+    fn serialize_field<T>(&mut self, k: &'static str, v: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        let v = v.serialize(Serializer)?;
+        self.entries.push((SerValue::Text(k.to_owned()), v));
+        Ok(())
+    }
+    fn end(self) -> Result<SerValue> {
+        Ok(MapSerializer::end(self))
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = SerValue;
+    type Error = Error;
+    fn serialize_field<T>(&mut self, k: &'static str, v: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeStruct::serialize_field(self, k, v)
+    }
+    fn end(self) -> Result<SerValue> {
+        Ok(MapSerializer::end(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::to_expr;
+    use crate::Value;
+
+    /// Serializes `value` as a Dhall expression of type `ty`, and checks
+    /// that it typechecks against `ty` and deserializes back to `value`.
+    fn round_trip<T>(value: T, ty: &str) -> String
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + Debug,
+    {
+        let ty = Value::from_str(ty, None).unwrap();
+        let expr = to_expr(&value, &ty.to_expr()).unwrap().to_string();
+        let back: T = crate::from_str_check_type(&expr, &ty)
+            .unwrap_or_else(|e| panic!("{}: {}", expr, e));
+        assert_eq!(back, value);
+        expr
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Server {
+        host: String,
+        port: u16,
+        weight: f64,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Mode {
+        Off,
+        Limit(u64),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        name: String,
+        servers: Vec<Server>,
+        mode: Mode,
+        backup: Option<Server>,
+        offset: i64,
+        enabled: bool,
+    }
+
+    #[test]
+    fn records() {
+        let server = Server {
+            host: "localhost".to_owned(),
+            port: 80,
+            weight: 0.5,
+        };
+        let ty = "{ host : Text, port : Natural, weight : Double }";
+        assert_eq!(
+            round_trip(server, ty),
+            "{ host = \"localhost\", port = 80, weight = 0.5 }"
+        );
+    }
+
+    #[test]
+    fn unions() {
+        let ty = "< Off | Limit : Natural >";
+        assert_eq!(round_trip(Mode::Off, ty), "< Limit: Natural | Off >.Off");
+        assert_eq!(
+            round_trip(Mode::Limit(3), ty),
+            "< Limit: Natural | Off >.Limit 3"
+        );
+    }
+
+    #[test]
+    fn optionals() {
+        assert_eq!(round_trip(None::<u64>, "Optional Natural"), "None Natural");
+        assert_eq!(round_trip(Some(1u64), "Optional Natural"), "Some 1");
+        assert_eq!(
+            round_trip(Some(None::<bool>), "Optional (Optional Bool)"),
+            "Some (None Bool)"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            round_trip(Vec::<i64>::new(), "List Integer"),
+            "[] : List Integer"
+        );
+        assert_eq!(round_trip(vec![-1i64, 2], "List Integer"), "[-1, +2]");
+        assert_eq!(
+            round_trip(vec![vec!["a".to_owned()], vec![]], "List (List Text)"),
+            "[[\"a\"], [] : List Text]"
+        );
+    }
+
+    #[test]
+    fn nested_structs() {
+        let config = Config {
+            name: "prod".to_owned(),
+            servers: vec![
+                Server {
+                    host: "a".to_owned(),
+                    port: 1,
+                    weight: 1.0,
+                },
+                Server {
+                    host: "b".to_owned(),
+                    port: 2,
+                    weight: 2.5,
+                },
+            ],
+            mode: Mode::Limit(10),
+            backup: None,
+            offset: -4,
+            enabled: true,
+        };
+        let server = "{ host : Text, port : Natural, weight : Double }";
+        let ty = format!(
+            "{{ name : Text, servers : List {0}, mode : < Off | Limit : \
+             Natural >, backup : Optional {0}, offset : Integer, \
+             enabled : Bool }}",
+            server
+        );
+        round_trip(config, &ty);
+    }
+}
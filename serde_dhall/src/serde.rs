@@ -1,7 +1,10 @@
 use std::borrow::Cow;
 
 use dhall::phase::NormalizedExpr;
-use dhall_syntax::ExprF;
+use dhall_syntax::{Builtin, ExprF, InterpolatedTextContents, Label};
+use serde::de::value::{
+    MapAccessDeserializer, MapDeserializer, SeqDeserializer,
+};
 
 use crate::de::{Deserialize, Error, Result};
 use crate::Value;
@@ -15,6 +18,13 @@ where
     }
 }
 
+pub(crate) fn deserialize_expr<T>(e: &NormalizedExpr) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    T::deserialize(Deserializer(Cow::Borrowed(e)))
+}
+
 struct Deserializer<'a>(Cow<'a, NormalizedExpr>);
 
 impl<'de: 'a, 'a> serde::de::IntoDeserializer<'de, Error> for Deserializer<'a> {
@@ -29,40 +39,178 @@ impl<'de: 'a, 'a> serde::Deserializer<'de> for Deserializer<'a> {
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
+    {
+        let span = self.0.span().cloned();
+        self.deserialize_any_unspanned(visitor)
+            .map_err(|e| e.at(span.as_ref()))
+    }
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        use serde::de::IntoDeserializer;
+        use ExprF::*;
+        let span = self.0.span().cloned();
+        match self.0.as_ref().as_ref() {
+            Field(_, k) => visitor.visit_enum(k.as_ref().into_deserializer()),
+            App(f, x) => match f.as_ref() {
+                Field(_, k) => visitor.visit_enum(MapAccessDeserializer::new(
+                    MapDeserializer::new(std::iter::once((
+                        k.as_ref(),
+                        Deserializer(Cow::Borrowed(x)),
+                    ))),
+                )),
+                _ => self.deserialize_any(visitor),
+            },
+            // The tag of an adjacently tagged enum names the variant.
+            TextLit(t) => {
+                let name: Option<String> = t
+                    .iter()
+                    .map(|chunk| match chunk {
+                        InterpolatedTextContents::Text(x) => Some(x),
+                        InterpolatedTextContents::Expr(_) => None,
+                    })
+                    .collect();
+                match name {
+                    Some(name) => visitor.visit_enum(name.into_deserializer()),
+                    None => self.deserialize_any(visitor),
+                }
+            }
+            // Adjacently and internally tagged enums come as records.
+            _ => self.deserialize_any(visitor),
+        }
+        .map_err(|e| e.at(span.as_ref()))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'a> Deserializer<'a> {
+    fn deserialize_any_unspanned<'de, V>(self, visitor: V) -> Result<V::Value>
+    where
+        'de: 'a,
+        V: serde::de::Visitor<'de>,
     {
         use std::convert::TryInto;
         use ExprF::*;
         match self.0.as_ref().as_ref() {
-            NaturalLit(n) => {
-                if let Ok(n64) = (*n).try_into() {
-                    visitor.visit_u64(n64)
-                } else if let Ok(n32) = (*n).try_into() {
-                    visitor.visit_u32(n32)
-                } else {
-                    unimplemented!()
+            NaturalLit(n) => match (*n).try_into() {
+                Ok(n) => visitor.visit_u64(n),
+                Err(_) => visitor.visit_u128(*n as u128),
+            },
+            IntegerLit(n) => match (*n).try_into() {
+                Ok(n) => visitor.visit_i64(n),
+                Err(_) => visitor.visit_i128(*n as i128),
+            },
+            BoolLit(b) => visitor.visit_bool(*b),
+            DoubleLit(n) => visitor.visit_f64((*n).into()),
+            TextLit(t) => {
+                let mut s = String::new();
+                for chunk in t.iter() {
+                    match chunk {
+                        InterpolatedTextContents::Text(x) => s.push_str(&x),
+                        InterpolatedTextContents::Expr(_) => {
+                            return Err(unsupported(&self.0))
+                        }
+                    }
                 }
+                visitor.visit_string(s)
+            }
+            EmptyListLit(_) => {
+                visitor.visit_seq(SeqDeserializer::new(std::iter::empty::<
+                    Deserializer,
+                >()))
             }
-            IntegerLit(n) => {
-                if let Ok(n64) = (*n).try_into() {
-                    visitor.visit_i64(n64)
-                } else if let Ok(n32) = (*n).try_into() {
-                    visitor.visit_i32(n32)
-                } else {
-                    unimplemented!()
+            NEListLit(xs) => visitor.visit_seq(SeqDeserializer::new(
+                xs.iter().map(|x| Deserializer(Cow::Borrowed(x))),
+            )),
+            SomeLit(x) => visitor.visit_some(Deserializer(Cow::Borrowed(x))),
+            App(f, _) if is_none(f) => visitor.visit_none(),
+            RecordLit(m) => visitor.visit_map(RecordAccess {
+                fields: m.iter(),
+                value: None,
+            }),
+            // Union values are presented like serde represents externally
+            // tagged enums, so that they can be buffered for `flatten` and
+            // tagged enums: the name of an alternative without a payload,
+            // or a map from the name of the alternative to its payload.
+            Field(_, k) => visitor.visit_str(k.as_ref()),
+            App(f, x) => match f.as_ref() {
+                Field(_, k) => {
+                    visitor.visit_map(MapDeserializer::new(std::iter::once((
+                        k.as_ref(),
+                        Deserializer(Cow::Borrowed(x)),
+                    ))))
                 }
+                _ => Err(unsupported(&self.0)),
+            },
+            _ => Err(unsupported(&self.0)),
+        }
+    }
+}
+
+/// The fields of a record. Errors in the value of a field are given its span,
+/// including those raised after the value is deserialized, e.g. by a
+/// `#[serde(try_from = "u64")]` conversion.
+struct RecordAccess<'a, I> {
+    fields: I,
+    value: Option<&'a NormalizedExpr>,
+}
+
+impl<'de: 'a, 'a, I> serde::de::MapAccess<'de> for RecordAccess<'a, I>
+where
+    I: Iterator<Item = (&'a Label, &'a NormalizedExpr)>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        use serde::de::IntoDeserializer;
+        match self.fields.next() {
+            Some((k, v)) => {
+                self.value = Some(v);
+                let k: &str = k.as_ref();
+                seed.deserialize(k.into_deserializer()).map(Some)
             }
-            RecordLit(m) => visitor.visit_map(
-                serde::de::value::MapDeserializer::new(m.iter().map(
-                    |(k, v)| (k.as_ref(), Deserializer(Cow::Borrowed(v))),
-                )),
-            ),
-            _ => unimplemented!(),
+            None => Ok(None),
         }
     }
 
-    serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let v = self.value.take().expect("value requested before key");
+        seed.deserialize(Deserializer(Cow::Borrowed(v)))
+            .map_err(|e| e.at(v.span()))
+    }
+}
+
+fn is_none(e: &NormalizedExpr) -> bool {
+    match e.as_ref() {
+        ExprF::Builtin(Builtin::OptionalNone) => true,
+        _ => false,
     }
 }
+
+fn unsupported(e: &NormalizedExpr) -> Error {
+    Error::Deserialize(format!("Cannot deserialize the expression `{}`", e))
+}
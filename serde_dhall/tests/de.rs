@@ -0,0 +1,127 @@
+use serde::Deserialize;
+use serde_dhall::from_str;
+
+#[test]
+fn test_flatten_and_tagged_enums() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Inner {
+        y: u64,
+        z: String,
+    }
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Outer {
+        x: bool,
+        #[serde(flatten)]
+        inner: Inner,
+    }
+    assert_eq!(
+        from_str::<Outer>("{ x = True, y = 1, z = \"a\" }").unwrap(),
+        Outer {
+            x: true,
+            inner: Inner {
+                y: 1,
+                z: "a".to_owned()
+            }
+        }
+    );
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(tag = "type")]
+    enum Internal {
+        A { n: u64 },
+        B,
+    }
+    assert_eq!(
+        from_str::<Internal>("{ type = \"A\", n = 2 }").unwrap(),
+        Internal::A { n: 2 }
+    );
+    assert_eq!(
+        from_str::<Vec<Internal>>("[{ type = \"B\" }]").unwrap(),
+        vec![Internal::B]
+    );
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(tag = "t", content = "c")]
+    enum Adjacent {
+        A(u64),
+        B(Option<String>),
+    }
+    assert_eq!(
+        from_str::<Adjacent>("{ t = \"B\", c = Some \"x\" }").unwrap(),
+        Adjacent::B(Some("x".to_owned()))
+    );
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum External {
+        A(u64),
+        B,
+    }
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct WithUnion {
+        #[serde(flatten)]
+        rest: std::collections::BTreeMap<String, External>,
+    }
+    let u = "let U = < A : Natural | B > in ";
+    assert_eq!(
+        from_str::<Vec<External>>(&format!("{}[U.A 1, U.B]", u)).unwrap(),
+        vec![External::A(1), External::B]
+    );
+    assert_eq!(
+        from_str::<WithUnion>(&format!("{}{{ a = U.A 3 }}", u))
+            .unwrap()
+            .rest
+            .get("a"),
+        Some(&External::A(3))
+    );
+}
+
+#[test]
+fn test_from_binary() {
+    use dhall::phase::Parsed;
+    use serde_dhall::{from_binary, from_binary_unchecked};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct A {
+        x: u64,
+        y: Vec<String>,
+    }
+    let expected = A {
+        x: 2,
+        y: vec!["a".to_owned()],
+    };
+    let parsed = Parsed::parse_str("{ x = 1 + 1, y = [\"a\"] }").unwrap();
+    let data = parsed.encode().unwrap();
+    assert_eq!(from_binary::<A>(&data).unwrap(), expected);
+    assert!(from_binary_unchecked::<A>(&data).is_err());
+
+    let normalized = parsed.skip_resolve().unwrap().typecheck().unwrap();
+    let data = normalized.normalize().encode().unwrap();
+    assert_eq!(from_binary_unchecked::<A>(&data).unwrap(), expected);
+
+    let data = Parsed::parse_str("./a.dhall").unwrap().encode().unwrap();
+    assert!(from_binary::<A>(&data).is_err());
+}
+
+#[test]
+#[should_panic(expected = "-     1,\n+     2,")]
+fn test_assert_matches_diff() {
+    serde_dhall::assert_matches(&vec![0u64, 1], "[0, 2]");
+}
+
+#[test]
+fn test_out_of_range_numbers() {
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        port: u16,
+        offset: i8,
+    }
+    let err = |s| from_str::<Config>(s).unwrap_err().to_string();
+    assert_eq!(
+        err("{ port = 70000, offset = +1 }"),
+        "invalid value: integer `70000`, expected u16 (at 1:10)"
+    );
+    assert_eq!(
+        err("{ port = 80,\n  offset = -200 }"),
+        "invalid value: integer `-200`, expected i8 (at 2:12)"
+    );
+}